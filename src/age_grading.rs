@@ -0,0 +1,242 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use chrono::TimeDelta;
+use serde::Deserialize;
+use tracing::{error, info};
+
+use crate::category_rules::age_on;
+use crate::config::app_dir;
+use crate::race::{Race, Racer, Track};
+use crate::restclient::Gender;
+
+/// Age factors of one distance and gender, e.g. from the WMA tables
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AgeStandard {
+    pub gender: Gender,
+    /// length in meters
+    pub distance: u32,
+    /// open class standard time in seconds
+    pub open_standard: f64,
+    /// age of the first factor
+    pub first_age: u32,
+    /// factor of each age from `first_age` on, younger racers get 1.0 and older the last one
+    pub factors: Vec<f64>,
+}
+
+/// Factor tables loaded from `age_grading.toml` in the app directory
+#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AgeGrading {
+    #[serde(default)]
+    pub standard: Vec<AgeStandard>,
+}
+
+fn age_grading_path() -> PathBuf {
+    app_dir().join("age_grading.toml")
+}
+
+pub fn load_age_grading() -> AgeGrading {
+    let path = age_grading_path();
+    match fs::read_to_string(&path) {
+        Ok(toml_str) => match toml::from_str::<AgeGrading>(&toml_str) {
+            Ok(age_grading) => {
+                info!("Loaded age grading {path:?}");
+                age_grading
+            }
+            Err(err) => {
+                error!("Failed to parse {path:?}: {err}");
+                AgeGrading::default()
+            }
+        },
+        Err(err) => {
+            if err.kind() != ErrorKind::NotFound {
+                error!("Failed to load {path:?}: {err}");
+            }
+            AgeGrading::default()
+        }
+    }
+}
+
+impl AgeStandard {
+    pub fn factor(&self, age: u32) -> f64 {
+        if age < self.first_age {
+            return 1.0;
+        }
+        let index = (age - self.first_age) as usize;
+        self.factors
+            .get(index)
+            .or(self.factors.last())
+            .copied()
+            .unwrap_or(1.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgeGradedResult {
+    pub rank: u32,
+    pub racer: Racer,
+    pub age: u32,
+    pub factor: f64,
+    /// running time multiplied by the age factor
+    pub graded_time: TimeDelta,
+    /// open standard as a percentage of the graded time, 100 is a world record
+    pub percent: f64,
+}
+
+impl AgeGrading {
+    fn standard(&self, gender: Gender, distance: u32) -> Option<&AgeStandard> {
+        self.standard
+            .iter()
+            .find(|standard| standard.gender == gender && standard.distance == distance)
+    }
+
+    /// Ranked finishers of a track by their age-graded percentage, best first. Racers
+    /// without a birth date or gender and tracks without a distance or table are left out.
+    pub fn results(&self, race: &Race, track: &Track) -> Vec<AgeGradedResult> {
+        let Some(distance) = race.track_info.get(track).and_then(|info| info.distance) else {
+            return Vec::new();
+        };
+        let race_date = race.race_date();
+
+        let mut results: Vec<AgeGradedResult> = race
+            .get_finished_racers_by_track(track)
+            .into_iter()
+            .filter_map(|racer| {
+                let standard = self.standard(racer.gender?, distance)?;
+                let age = age_on(racer.birth_date?, race_date);
+                let time = racer.time?;
+                let factor = standard.factor(age);
+                let graded_time =
+                    TimeDelta::milliseconds((time.num_milliseconds() as f64 * factor) as i64);
+                if graded_time <= TimeDelta::zero() {
+                    return None;
+                }
+                let percent =
+                    standard.open_standard * 1000.0 / graded_time.num_milliseconds() as f64 * 100.0;
+                Some(AgeGradedResult {
+                    rank: 0,
+                    racer: racer.clone(),
+                    age,
+                    factor,
+                    graded_time,
+                    percent,
+                })
+            })
+            .collect();
+
+        results.sort_by(|a, b| {
+            b.percent
+                .total_cmp(&a.percent)
+                .then(a.racer.start_number.cmp(&b.racer.start_number))
+        });
+        for (index, result) in results.iter_mut().enumerate() {
+            result.rank = index as u32 + 1;
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use super::*;
+    use crate::config::RaceConfig;
+    use crate::race::test_registration;
+
+    #[test]
+    fn factors() {
+        let age_grading: AgeGrading = toml::from_str(
+            r#"
+            [[standard]]
+            gender = "male"
+            distance = 10000
+            open_standard = 1577
+            first_age = 35
+            factors = [0.9946, 0.9896, 0.9846]
+            "#,
+        )
+        .unwrap();
+        let standard = age_grading.standard(Gender::Male, 10000).unwrap();
+        assert!(age_grading.standard(Gender::Female, 10000).is_none());
+        assert_eq!(standard.factor(20), 1.0);
+        assert_eq!(standard.factor(36), 0.9896);
+        assert_eq!(standard.factor(80), 0.9846);
+    }
+
+    #[test]
+    fn graded_results() {
+        let registration = |number, track: &str, gender, birth_year: Option<i32>| {
+            let mut registration = test_registration(number, number, track);
+            if track == "10 Km" {
+                registration.track.distance = Some(10000);
+            }
+            registration.gender = gender;
+            registration.birth_date =
+                birth_year.and_then(|year| NaiveDate::from_ymd_opt(year, 1, 1));
+            registration
+        };
+        let registrations = vec![
+            registration(1, "10 Km", Some(Gender::Male), Some(1989)),
+            registration(2, "10 Km", Some(Gender::Male), Some(2005)),
+            registration(3, "10 Km", Some(Gender::Male), Some(1945)),
+            registration(4, "10 Km", Some(Gender::Male), None),
+            registration(5, "10 Km", None, Some(1980)),
+            // no table for women
+            registration(6, "10 Km", Some(Gender::Female), Some(1980)),
+            // no distance of the track
+            registration(7, "5 Km", Some(Gender::Male), Some(1980)),
+        ];
+        let mut race = Race::for_test("finish", RaceConfig::default(), registrations);
+        let start = Utc.with_ymd_and_hms(2025, 10, 5, 8, 0, 0).unwrap();
+        race.date_of_event = Some(start);
+        race.age_grading = toml::from_str(
+            r#"
+            [[standard]]
+            gender = "male"
+            distance = 10000
+            open_standard = 1577
+            first_age = 35
+            factors = [0.9946, 0.9896, 0.9846]
+            "#,
+        )
+        .unwrap();
+        race.start(Track("10 Km".into()), start);
+        race.start(Track("5 Km".into()), start);
+        for (number, minutes) in [
+            (1, 40),
+            (2, 38),
+            (3, 39),
+            (4, 30),
+            (5, 30),
+            (6, 30),
+            (7, 20),
+        ] {
+            race.finish_start_number(
+                number.to_string().parse().unwrap(),
+                start + TimeDelta::minutes(minutes),
+            );
+        }
+
+        let results = race.age_grading.results(&race, &Track("10 Km".into()));
+        let ranked: Vec<_> = results
+            .iter()
+            .map(|result| (result.rank, result.racer.id, result.age))
+            .collect();
+        assert_eq!(ranked, vec![(1, 2, 20), (2, 3, 80), (3, 1, 36)]);
+
+        // 38:00 without a factor, 39:00 * 0.9846 and 40:00 * 0.9896
+        let expected = [(2_280_000, 69.17), (2_303_964, 68.45), (2_375_040, 66.40)];
+        for (result, (millis, percent)) in results.iter().zip(expected) {
+            assert!((result.graded_time.num_milliseconds() - millis).abs() <= 1);
+            assert!((result.percent - percent).abs() < 0.01);
+        }
+        assert!(race
+            .age_grading
+            .results(&race, &Track("5 Km".into()))
+            .is_empty());
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::HashSet;
+
+use serde::Deserialize;
+
+use crate::race::{Race, Racer, StartNumber};
+
+/// Who is left out of the category prizes
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AwardExclusion {
+    /// category prizes go to the best of the category
+    #[default]
+    None,
+    /// overall prize winners get no category prize
+    Overall,
+    /// a racer gets a single prize, the overall one or the first category in order
+    OnePrize,
+}
+
+fn default_category() -> usize {
+    3
+}
+
+/// Prizes handed out at the ceremony
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AwardRules {
+    /// overall prizes of each track, none if zero
+    #[serde(default)]
+    pub overall: usize,
+    /// prizes of each category
+    #[serde(default = "default_category")]
+    pub category: usize,
+    #[serde(default)]
+    pub exclusion: AwardExclusion,
+    /// categories with fewer starters get no prizes
+    #[serde(default)]
+    pub min_category_size: usize,
+}
+
+impl Default for AwardRules {
+    fn default() -> Self {
+        AwardRules {
+            overall: 0,
+            category: default_category(),
+            exclusion: AwardExclusion::default(),
+            min_category_size: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Award {
+    /// prize place, differs from the rank when better racers are excluded
+    pub place: u32,
+    pub rank: u32,
+    pub racer: Racer,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AwardList {
+    pub title: String,
+    pub awards: Vec<Award>,
+}
+
+impl AwardRules {
+    fn pick(
+        &self,
+        title: String,
+        ranked: Vec<(u32, &Racer)>,
+        count: usize,
+        excluded: &HashSet<StartNumber>,
+    ) -> AwardList {
+        let awards = ranked
+            .into_iter()
+            .filter(|(_, racer)| !excluded.contains(&racer.start_number))
+            .take(count)
+            .enumerate()
+            .map(|(index, (rank, racer))| Award {
+                place: index as u32 + 1,
+                rank,
+                racer: racer.clone(),
+            })
+            .collect();
+        AwardList { title, awards }
+    }
+
+    /// Overall prizes of the tracks followed by the category prizes
+    pub fn awards(&self, race: &Race) -> Vec<AwardList> {
+        let mut lists = Vec::new();
+        let mut awarded: HashSet<StartNumber> = HashSet::new();
+
+        if self.overall > 0 {
+            for track in &race.tracks {
+                let ranked = race
+                    .get_finished_racers_by_track(track)
+                    .into_iter()
+                    .map(|racer| (racer.track_rank.unwrap_or_default(), racer))
+                    .collect();
+                let list = self.pick(
+                    format!("Overall: {}", track.0),
+                    ranked,
+                    self.overall,
+                    &HashSet::new(),
+                );
+                awarded.extend(list.awards.iter().map(|a| a.racer.start_number.clone()));
+                lists.push(list);
+            }
+        }
+
+        for category in &race.categories {
+            let starters = race
+                .racers
+                .iter()
+                .filter(|racer| racer.categories.contains(category) && racer.start.is_some())
+                .count();
+            if starters < self.min_category_size {
+                continue;
+            }
+            let ranked = race
+                .get_finished_racers_by_category(category)
+                .into_iter()
+                .map(|racer| {
+                    let rank = racer.categories_rank.get(category).copied();
+                    (rank.unwrap_or_default(), racer)
+                })
+                .collect();
+            let excluded = match self.exclusion {
+                AwardExclusion::None => HashSet::new(),
+                AwardExclusion::Overall | AwardExclusion::OnePrize => awarded.clone(),
+            };
+            let list = self.pick(
+                format!("Category: {}", category.0),
+                ranked,
+                self.category,
+                &excluded,
+            );
+            if self.exclusion == AwardExclusion::OnePrize {
+                awarded.extend(list.awards.iter().map(|a| a.racer.start_number.clone()));
+            }
+            lists.push(list);
+        }
+
+        lists.retain(|list| !list.awards.is_empty());
+        lists
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RaceConfig;
+    use crate::race::{test_registration, Category, Track};
+    use chrono::{TimeDelta, Utc};
+    use std::collections::HashMap;
+
+    fn racer(rank: u32) -> Racer {
+        Racer {
+            id: rank,
+            start_number: rank.to_string().parse().unwrap(),
+            tag: String::new(),
+            first_name: "Bob".into(),
+            last_name: format!("Jones{rank}"),
+            track: Track("10 Km".into()),
+            track_rank: Some(rank),
+            categories: vec![Category("Men".into())],
+            categories_rank: HashMap::new(),
+            birth_date: None,
+            gender: None,
+            team: None,
+            legs: vec![],
+            start: None,
+            finish: None,
+            time: None,
+        }
+    }
+
+    #[test]
+    fn exclusion() {
+        let racers: Vec<Racer> = (1..=5).map(racer).collect();
+        let ranked = || racers.iter().map(|r| (r.track_rank.unwrap(), r)).collect();
+        let rules = AwardRules::default();
+        let excluded: HashSet<StartNumber> =
+            racers[..2].iter().map(|r| r.start_number.clone()).collect();
+
+        let places = |list: AwardList| -> Vec<(u32, u32)> {
+            list.awards.iter().map(|a| (a.place, a.rank)).collect()
+        };
+        assert_eq!(
+            places(rules.pick("Men".into(), ranked(), 3, &HashSet::new())),
+            vec![(1, 1), (2, 2), (3, 3)]
+        );
+        assert_eq!(
+            places(rules.pick("Men".into(), ranked(), 3, &excluded)),
+            vec![(1, 3), (2, 4), (3, 5)]
+        );
+
+        let rules: AwardRules = toml::from_str(
+            r#"
+            overall = 2
+            exclusion = "one_prize"
+            min_category_size = 5
+            "#,
+        )
+        .unwrap();
+        assert_eq!(rules.category, 3);
+        assert_eq!(rules.exclusion, AwardExclusion::OnePrize);
+    }
+
+    #[test]
+    fn race_awards() {
+        let registration = |number: u32, categories: &[&str]| {
+            let mut registration = test_registration(number, number, "10 Km");
+            registration.categories = categories
+                .iter()
+                .map(|name| crate::restclient::Category {
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+                .collect();
+            registration
+        };
+        let registrations = vec![
+            registration(1, &["Men"]),
+            registration(2, &["Men", "Masters"]),
+            registration(3, &["Men"]),
+            registration(4, &["Masters"]),
+            registration(5, &["Men"]),
+            registration(6, &["Women"]),
+        ];
+        let mut race = Race::for_test("finish", RaceConfig::default(), registrations);
+        let start = Utc::now();
+        race.start(Track("10 Km".into()), start);
+        for number in 1..=6 {
+            race.finish_start_number(
+                number.to_string().parse().unwrap(),
+                start + TimeDelta::minutes(30 + i64::from(number)),
+            );
+        }
+
+        let winners = |exclusion| {
+            let rules = AwardRules {
+                overall: 1,
+                category: 2,
+                exclusion,
+                min_category_size: 2,
+            };
+            rules
+                .awards(&race)
+                .into_iter()
+                .map(|list| {
+                    let awards = list
+                        .awards
+                        .iter()
+                        .map(|award| (award.place, award.rank, award.racer.id))
+                        .collect::<Vec<_>>();
+                    (list.title, awards)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Women has a single starter and no prizes
+        assert_eq!(
+            winners(AwardExclusion::None),
+            vec![
+                ("Overall: 10 Km".to_string(), vec![(1, 1, 1)]),
+                ("Category: Masters".to_string(), vec![(1, 1, 2), (2, 2, 4)]),
+                ("Category: Men".to_string(), vec![(1, 1, 1), (2, 2, 2)]),
+            ]
+        );
+        assert_eq!(
+            winners(AwardExclusion::Overall),
+            vec![
+                ("Overall: 10 Km".to_string(), vec![(1, 1, 1)]),
+                ("Category: Masters".to_string(), vec![(1, 1, 2), (2, 2, 4)]),
+                ("Category: Men".to_string(), vec![(1, 2, 2), (2, 3, 3)]),
+            ]
+        );
+        // racer 2 takes the prize of Masters, the first category in order
+        assert_eq!(
+            winners(AwardExclusion::OnePrize),
+            vec![
+                ("Overall: 10 Km".to_string(), vec![(1, 1, 1)]),
+                ("Category: Masters".to_string(), vec![(1, 1, 2), (2, 2, 4)]),
+                ("Category: Men".to_string(), vec![(1, 3, 3), (2, 4, 5)]),
+            ]
+        );
+    }
+}
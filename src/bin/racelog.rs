@@ -0,0 +1,125 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use rustpolnak::config::{load_config_from, race_timezone, StorageBackend, DEFAULT_TIMEZONE};
+use rustpolnak::race::StartNumber;
+use rustpolnak::race_events::{load_events, merge, RaceEvents};
+use rustpolnak::storage::{import_jsonl, jsonl_race_ids, sqlite_path};
+use rustpolnak::time_utils::format_time;
+
+#[derive(Parser, Debug)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+    /// config file used before the default paths, its `storage` is merged into
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Merge the log of a backup timing laptop into the local race log
+    Merge {
+        /// race id of the local log
+        race_id: u32,
+        /// path to race_{id}.jsonl from the backup laptop
+        backup: PathBuf,
+        /// resolve the conflict by taking the finish time from the backup log
+        #[arg(long, value_name = "START_NUMBER")]
+        take_backup: Vec<StartNumber>,
+        /// only print what would be merged
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Import race_{id}.jsonl logs into the SQLite database
+    Import {
+        /// races to import, all logs in the app directory by default
+        race_ids: Vec<u32>,
+    },
+}
+
+fn merge_logs(
+    race_id: u32,
+    backup: PathBuf,
+    take_backup: Vec<StartNumber>,
+    dry_run: bool,
+    storage: StorageBackend,
+) {
+    let mut racelog = RaceEvents::load(race_id, storage);
+    let tz = race_timezone(race_id, DEFAULT_TIMEZONE);
+    let merged = merge(racelog.events(), &load_events(&backup));
+
+    println!("{} duplicate events skipped", merged.duplicates);
+    for event in &merged.events {
+        println!("Taking from backup: {event:?}");
+    }
+
+    let mut resolved = Vec::new();
+    for conflict in &merged.conflicts {
+        if take_backup.contains(&conflict.start_number) {
+            println!(
+                "Start number {}: taking backup finish",
+                conflict.start_number
+            );
+            resolved.push(conflict.backup.clone());
+        } else {
+            let backup_finish = match &conflict.backup.event {
+                rustpolnak::race_events::EventType::RacerFinish(finish) => finish.finish,
+                _ => None,
+            };
+            println!(
+                "Conflict for start number {}: primary {}, backup {} (use --take-backup {} to take the backup finish)",
+                conflict.start_number,
+                format_time(Some(conflict.primary), tz),
+                format_time(backup_finish, tz),
+                conflict.start_number,
+            );
+        }
+    }
+
+    if dry_run {
+        return;
+    }
+
+    for event in merged.events {
+        racelog.append(event);
+    }
+    for mut event in resolved {
+        // the operator decision is made now, so it has to win the replay over older events
+        event.timestamp = chrono::Utc::now();
+        event.origin = None;
+        racelog.append(event);
+    }
+}
+
+fn import(race_ids: Vec<u32>) {
+    let race_ids = if race_ids.is_empty() {
+        jsonl_race_ids()
+    } else {
+        race_ids
+    };
+
+    for race_id in race_ids {
+        let imported = import_jsonl(race_id);
+        println!("Race {race_id}: imported {imported} events");
+    }
+    println!("Database: {}", sqlite_path().display());
+}
+
+fn main() {
+    env_logger::init();
+
+    let args = Args::parse();
+    match args.command {
+        Command::Merge {
+            race_id,
+            backup,
+            take_backup,
+            dry_run,
+        } => {
+            let config = load_config_from(args.config);
+            merge_logs(race_id, backup, take_backup, dry_run, config.storage)
+        }
+        Command::Import { race_ids } => import(race_ids),
+    }
+}
@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+use std::process::exit;
+
+use clap::Parser;
+use rustpolnak::config::load_config_from;
+use rustpolnak::race::Race;
+use rustpolnak::registrations::ImportedRegistrations;
+use rustpolnak::restclient::RaceRestAPI;
+use rustpolnak::server::{read_tags, router, ServerState};
+use rustpolnak::storage::is_local_race;
+use tracing::{error, info};
+
+/// Time a race without the UI, operators connect over HTTP and WebSocket
+#[derive(Parser, Debug)]
+struct Args {
+    /// race to time
+    race_id: u32,
+    /// address of the HTTP API
+    #[arg(long, default_value = "0.0.0.0:8080")]
+    listen: String,
+    /// config file used before the default paths
+    #[arg(long)]
+    config: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let args = Args::parse();
+    let config = load_config_from(args.config.clone());
+
+    let race = if is_local_race(args.race_id) {
+        let source = ImportedRegistrations {
+            storage: config.storage,
+        };
+        Race::load(&source, args.race_id, config.storage, config.timezone).await
+    } else {
+        let api: Option<RaceRestAPI> = config.api.as_ref().map(|api| {
+            let auth = api.auth().unwrap_or_else(|err| {
+                error!("{err}");
+                exit(1);
+            });
+            RaceRestAPI::new(&api.url, auth)
+        });
+        Race::load(&api, args.race_id, config.storage, config.timezone).await
+    };
+    let race = race.unwrap_or_else(|err| {
+        error!("Failed to load race {}: {err}", args.race_id);
+        exit(1);
+    });
+
+    let state = ServerState::new(race);
+    tokio::spawn(read_tags(state.clone(), config.rfid_devices.clone()));
+
+    let listener = tokio::net::TcpListener::bind(&args.listen)
+        .await
+        .unwrap_or_else(|err| {
+            error!("Failed to listen on {}: {err}", args.listen);
+            exit(1);
+        });
+    info!("Serving race {} on {}", args.race_id, args.listen);
+    if let Err(err) = axum::serve(listener, router(state)).await {
+        error!("Server failed: {err}");
+    }
+}
@@ -0,0 +1,147 @@
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::restclient::Gender;
+
+/// Category assigned to racers of a track, gender and age on the race date
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CategoryRule {
+    pub category: String,
+    /// any track if not set
+    pub track: Option<String>,
+    /// any gender if not set
+    pub gender: Option<Gender>,
+    pub min_age: Option<u32>,
+    pub max_age: Option<u32>,
+}
+
+/// Racer attributes the rules decide on
+pub struct RacerProfile<'a> {
+    pub track: &'a str,
+    pub gender: Option<Gender>,
+    pub birth_date: Option<NaiveDate>,
+}
+
+/// Age in whole years on the given date
+pub fn age_on(birth_date: NaiveDate, date: NaiveDate) -> u32 {
+    date.years_since(birth_date).unwrap_or(0)
+}
+
+impl CategoryRule {
+    fn needs_age(&self) -> bool {
+        self.min_age.is_some() || self.max_age.is_some()
+    }
+
+    /// None if the racer lacks the gender or birth date the rule needs
+    fn matches(&self, racer: &RacerProfile, race_date: NaiveDate) -> Option<bool> {
+        if self
+            .track
+            .as_ref()
+            .is_some_and(|track| track != racer.track)
+        {
+            return Some(false);
+        }
+        if let Some(gender) = self.gender {
+            if racer.gender? != gender {
+                return Some(false);
+            }
+        }
+        if self.needs_age() {
+            let age = age_on(racer.birth_date?, race_date);
+            if self.min_age.is_some_and(|min| age < min)
+                || self.max_age.is_some_and(|max| age > max)
+            {
+                return Some(false);
+            }
+        }
+        Some(true)
+    }
+}
+
+/// Names of the categories the rules assign to the racer, None if the racer lacks
+/// the data to decide
+pub fn assign(
+    rules: &[CategoryRule],
+    racer: &RacerProfile,
+    race_date: NaiveDate,
+) -> Option<Vec<String>> {
+    let mut categories = Vec::new();
+    for rule in rules {
+        if rule.matches(racer, race_date)? && !categories.contains(&rule.category) {
+            categories.push(rule.category.clone());
+        }
+    }
+    Some(categories)
+}
+
+/// Whether a category is assigned by the rules
+pub fn is_ruled(rules: &[CategoryRule], category: &str) -> bool {
+    rules.iter().any(|rule| rule.category == category)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assign_categories() {
+        let rules: Vec<CategoryRule> = toml::from_str::<toml::Table>(
+            r#"
+            rules = [
+                { category = "Kids", track = "1 Km", max_age = 14 },
+                { category = "M", track = "10 Km", gender = "male", max_age = 39 },
+                { category = "M40", track = "10 Km", gender = "male", min_age = 40 },
+                { category = "W", track = "10 Km", gender = "female" },
+            ]
+            "#,
+        )
+        .unwrap()["rules"]
+            .clone()
+            .try_into()
+            .unwrap();
+        let race_date = NaiveDate::from_ymd_opt(2025, 10, 5).unwrap();
+        let born = |y, m, d| NaiveDate::from_ymd_opt(y, m, d);
+
+        assert_eq!(age_on(born(1985, 10, 5).unwrap(), race_date), 40);
+        assert_eq!(age_on(born(1985, 10, 6).unwrap(), race_date), 39);
+
+        let racer = |track, gender, birth_date| RacerProfile {
+            track,
+            gender,
+            birth_date,
+        };
+        assert_eq!(
+            assign(
+                &rules,
+                &racer("10 Km", Some(Gender::Male), born(1985, 10, 5)),
+                race_date
+            ),
+            Some(vec!["M40".to_string()])
+        );
+        assert_eq!(
+            assign(
+                &rules,
+                &racer("10 Km", Some(Gender::Male), born(1985, 10, 6)),
+                race_date
+            ),
+            Some(vec!["M".to_string()])
+        );
+        assert_eq!(
+            assign(
+                &rules,
+                &racer("10 Km", Some(Gender::Female), None),
+                race_date
+            ),
+            Some(vec!["W".to_string()])
+        );
+        assert_eq!(
+            assign(&rules, &racer("10 Km", Some(Gender::Male), None), race_date),
+            None
+        );
+        assert_eq!(
+            assign(&rules, &racer("4 Km", None, None), race_date),
+            Some(vec![])
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use dioxus::prelude::*;
+
+use crate::{
+    components::app::Action,
+    outbox::{Outbox, Payload},
+    race::Race,
+    restclient::{self, Gender, RaceRestAPI},
+    storage::is_local_race,
+};
+
+/// Id of the track or category in the registrations, 0 for a new one
+fn registration_id_of(race: &Race, name: &str, category: bool) -> u32 {
+    race.registrations()
+        .iter()
+        .find_map(|racer| {
+            if category {
+                racer
+                    .categories
+                    .iter()
+                    .find(|c| c.name == name)
+                    .map(|c| c.id)
+            } else {
+                (racer.track.name == name).then_some(racer.track.id)
+            }
+        })
+        .unwrap_or(0)
+}
+
+/// Form registering a racer on the spot
+#[component]
+pub fn AddRacer(race: Race, show: Signal<bool>) -> Element {
+    let api = use_context::<Option<RaceRestAPI>>();
+    let mut outbox = use_context::<Signal<Outbox>>();
+    let can_push = api.is_some() && !is_local_race(race.id);
+
+    let mut first_name = use_signal(String::new);
+    let mut last_name = use_signal(String::new);
+    let mut start_number = use_signal(String::new);
+    let mut tag = use_signal(String::new);
+    let first_track = race.tracks.first().map(|t| t.0.clone()).unwrap_or_default();
+    let mut track = use_signal(|| first_track);
+    let mut categories = use_signal(String::new);
+    let mut birth_date = use_signal(String::new);
+    let mut gender = use_signal(String::new);
+    let mut team = use_signal(String::new);
+    let mut push = use_signal(|| can_push);
+    let mut error = use_signal(|| Option::<String>::None);
+
+    let tracks = race.tracks.clone();
+    let known_categories = race.categories.clone();
+
+    rsx! {
+        form {
+            class: "card mb-1",
+            onsubmit: move |event| {
+                event.prevent_default();
+                let racer = restclient::Racer {
+                    id: race.next_walk_in_id(),
+                    first_name: first_name().trim().to_string(),
+                    last_name: last_name().trim().to_string(),
+                    start_number: start_number().trim().parse().ok(),
+                    categories: categories()
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .map(|name| restclient::Category {
+                            id: registration_id_of(&race, name, true),
+                            name: name.to_string(),
+                            description: None,
+                            ..Default::default()
+                        })
+                        .collect(),
+                    tag_id: Some(tag().trim().to_string()).filter(|tag| !tag.is_empty()),
+                    track: restclient::Track {
+                        id: registration_id_of(&race, track().trim(), false),
+                        name: track().trim().to_string(),
+                        description: None,
+                        ..Default::default()
+                    },
+                    birth_date: birth_date().parse().ok(),
+                    gender: gender().parse().ok(),
+                    team: Some(team().trim().to_string()).filter(|team| !team.is_empty()),
+                    legs: vec![],
+                };
+                if let Err(err) = race.validate_walk_in(&racer) {
+                    error.set(Some(err));
+                    return;
+                }
+
+                use_coroutine_handle::<Action>().send(Action::Register(Box::new(racer.clone())));
+                if push() {
+                    outbox.write().push(race.id, Payload::Register {
+                        racer: Box::new(racer),
+                    });
+                }
+                show.set(false);
+            },
+            div { class: "card-body d-flex flex-wrap gap-1",
+                input {
+                    class: "form-control w-auto",
+                    placeholder: "First name",
+                    value: first_name,
+                    oninput: move |e| first_name.set(e.value()),
+                }
+                input {
+                    class: "form-control w-auto",
+                    placeholder: "Last name",
+                    value: last_name,
+                    oninput: move |e| last_name.set(e.value()),
+                }
+                input {
+                    class: "form-control w-auto",
+                    placeholder: "Start number",
+                    r#type: "number",
+                    value: start_number,
+                    oninput: move |e| start_number.set(e.value()),
+                }
+                input {
+                    class: "form-control w-auto",
+                    placeholder: "Tag",
+                    value: tag,
+                    oninput: move |e| tag.set(e.value()),
+                }
+                select {
+                    class: "form-select w-auto",
+                    onchange: move |e| track.set(e.value()),
+                    for option_track in tracks {
+                        option {
+                            value: "{option_track}",
+                            selected: option_track.0 == track(),
+                            "{option_track}"
+                        }
+                    }
+                }
+                input {
+                    class: "form-control w-auto",
+                    placeholder: "Categories",
+                    list: "walk-in-categories",
+                    value: categories,
+                    oninput: move |e| categories.set(e.value()),
+                }
+                input {
+                    class: "form-control w-auto",
+                    r#type: "date",
+                    title: "Birth date",
+                    value: birth_date,
+                    oninput: move |e| birth_date.set(e.value()),
+                }
+                select {
+                    class: "form-select w-auto",
+                    onchange: move |e| gender.set(e.value()),
+                    option { value: "", selected: gender().is_empty(), "Gender" }
+                    for option_gender in [Gender::Male, Gender::Female] {
+                        option {
+                            value: "{option_gender}",
+                            selected: gender() == option_gender.to_string(),
+                            "{option_gender}"
+                        }
+                    }
+                }
+                input {
+                    class: "form-control w-auto",
+                    placeholder: "Team",
+                    value: team,
+                    oninput: move |e| team.set(e.value()),
+                }
+                datalist { id: "walk-in-categories",
+                    for category in known_categories {
+                        option { value: "{category}" }
+                    }
+                }
+                if can_push {
+                    div { class: "form-check align-self-center",
+                        input {
+                            class: "form-check-input",
+                            r#type: "checkbox",
+                            id: "walk-in-push",
+                            checked: push(),
+                            onchange: move |e| push.set(e.checked()),
+                        }
+                        label { class: "form-check-label", r#for: "walk-in-push", "Register on the server" }
+                    }
+                }
+                button { class: "btn btn-primary", r#type: "submit", "Add racer" }
+                button {
+                    class: "btn btn-outline-secondary",
+                    r#type: "button",
+                    onclick: move |_| show.set(false),
+                    "Cancel"
+                }
+            }
+            if let Some(err) = error() {
+                p { class: "alert alert-danger m-2", "{err}" }
+            }
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use dioxus::prelude::*;
+
+use crate::race::Race;
+
+/// Finishers of each track ranked by their age-graded percentage
+#[component]
+pub fn AgeGraded(race: Race) -> Element {
+    if race.age_grading.standard.is_empty() {
+        return rsx! {
+            p { class: "alert alert-secondary mb-1",
+                "No age factors, add them to age_grading.toml in the app directory"
+            }
+        };
+    }
+    let tracks: Vec<_> = race
+        .tracks
+        .iter()
+        .map(|track| (track.clone(), race.get_age_graded_racers_by_track(track)))
+        .filter(|(_, results)| !results.is_empty())
+        .collect();
+    if tracks.is_empty() {
+        return rsx! {
+            p { class: "alert alert-secondary mb-1",
+                "No finisher with a birth date, gender and track distance yet"
+            }
+        };
+    }
+
+    rsx! {
+        div { class: "d-flex flex-wrap gap-2 mb-1 overflow-auto", style: "max-height: 40vh",
+            for (track , results) in tracks {
+                table { class: "table table-sm w-auto mb-0",
+                    thead {
+                        tr {
+                            th { colspan: 5, "{track}" }
+                        }
+                    }
+                    tbody {
+                        for result in results {
+                            tr {
+                                td { "{result.rank}." }
+                                td { "{result.racer.first_name} {result.racer.last_name}" }
+                                td { "{result.age}" }
+                                td { "{race.config.timing.format(Some(result.graded_time))}" }
+                                td { class: "text-end", "{result.percent:.2} %" }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use dioxus::prelude::*;
+
+use crate::race::Race;
+
+/// Prize winners in large print for the ceremony
+#[component]
+pub fn Announcer(race: Race) -> Element {
+    let lists = race.config.awards.awards(&race);
+    if lists.is_empty() {
+        return rsx! {
+            p { class: "alert alert-secondary mb-1", "No prize winners yet" }
+        };
+    }
+
+    rsx! {
+        div { class: "d-flex flex-wrap gap-3 mb-1 overflow-auto", style: "max-height: 60vh",
+            for list in lists {
+                div { class: "card",
+                    div { class: "card-header fs-4 fw-bold", "{list.title}" }
+                    ol { class: "list-group list-group-flush",
+                        for award in list.awards {
+                            li { class: "list-group-item fs-4",
+                                strong { "{award.place}. " }
+                                "{award.racer.first_name} {award.racer.last_name} "
+                                span { class: "text-secondary fs-6",
+                                    "#{award.racer.start_number} {race.config.timing.format(award.racer.time)}"
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -8,16 +8,35 @@ use tracing::{error, info, trace};
 
 use crate::{
     components::{
-        manual_start_number_input::ManualStartNumberInput, racers::Racers, races_list::RacesList,
-        track, upload_results::UploadResults,
+        add_racer::AddRacer,
+        age_graded::AgeGraded,
+        announcer::Announcer,
+        manual_start_number_input::ManualStartNumberInput,
+        pending_dnf::PendingDnf,
+        racers::Racers,
+        races_list::RacesList,
+        refresh_registrations::RefreshRegistrations,
+        replay::Replay,
+        series_standings::SeriesStandings,
+        start_list::StartList,
+        sync_status::{synchronize, SyncState, SyncStatus},
+        team_standings::TeamStandings,
+        track,
+        upload_results::{deliver_results, push_live_results, UploadResults},
+        validation_report::ValidationReport,
     },
     config::Config,
+    outbox::Outbox,
     printer::print_result,
     race::{Race, StartNumber, Track},
+    restclient::{self, RaceRestAPI},
     rfid_reader,
+    storage::is_local_race,
+    time_utils::{format_date, format_time},
+    validation::validate,
 };
 
-type SelectedRace = Option<Result<Race, Box<dyn std::error::Error>>>;
+pub type SelectedRace = Option<Result<Race, Box<dyn std::error::Error>>>;
 
 const LOADING: Asset = asset!("/assets/loading.webp");
 
@@ -26,6 +45,10 @@ pub enum Action {
     Start(Track, DateTime<Utc>),
     FinishByStartNumber(StartNumber, DateTime<Utc>),
     FinishEdit(StartNumber, Option<DateTime<Utc>>),
+    ApplyRegistrations(Vec<restclient::Racer>),
+    Register(Box<restclient::Racer>),
+    /// confirm or take back that the racer did not finish
+    Dnf(StartNumber, bool),
 }
 
 struct RFIDDevices {
@@ -80,6 +103,13 @@ fn handle_rfid_event(
     }
 }
 
+/// Queue results changed by the last handled event for the live upload
+fn push_live(selected_race: &Signal<SelectedRace>, outbox: Signal<Outbox>) {
+    if let Some(Ok(race)) = &*selected_race.peek() {
+        push_live_results(race, outbox);
+    }
+}
+
 fn handle_action(selected_race: &mut Signal<SelectedRace>, action: Action) {
     match action {
         Action::Start(track, time) => {
@@ -103,6 +133,27 @@ fn handle_action(selected_race: &mut Signal<SelectedRace>, action: Action) {
                 }
             });
         }
+        Action::ApplyRegistrations(registrations) => {
+            selected_race.with_mut(|maybe_race| {
+                if let Some(Ok(race)) = maybe_race {
+                    race.apply_registrations(registrations);
+                }
+            });
+        }
+        Action::Register(racer) => {
+            selected_race.with_mut(|maybe_race| {
+                if let Some(Ok(race)) = maybe_race {
+                    race.register(*racer, None);
+                }
+            });
+        }
+        Action::Dnf(start_number, dnf) => {
+            selected_race.with_mut(|maybe_race| {
+                if let Some(Ok(race)) = maybe_race {
+                    race.set_dnf(start_number, dnf);
+                }
+            });
+        }
     }
 }
 
@@ -111,9 +162,38 @@ pub fn App() -> Element {
     let mut selected_race = use_signal(|| SelectedRace::None);
     let config = use_context::<Config>();
     let mut show_starts = use_signal(|| true);
+    let mut show_replay = use_signal(|| false);
+    let mut show_refresh = use_signal(|| false);
+    let mut show_add_racer = use_signal(|| false);
+    let mut show_teams = use_signal(|| false);
+    let mut show_series = use_signal(|| false);
+    let mut show_start_list = use_signal(|| false);
+    let mut show_announcer = use_signal(|| false);
+    let mut show_age_graded = use_signal(|| false);
+    let mut show_sync = use_signal(|| false);
+    let sync_state = use_signal(SyncState::default);
+    // race whose validation report was dismissed
+    let mut validation_dismissed = use_signal(|| Option::<u32>::None);
+    let mut replay_at = use_signal(|| Option::<DateTime<Utc>>::None);
     let results_output_path =
         PathBuf::from(shellexpand::tilde(&config.results_path.clone()).to_string());
     let mut rfid_devices = use_signal(|| RFIDDevices::new(&config.rfid_devices));
+    let outbox = use_context_provider(|| Signal::new(Outbox::load()));
+
+    let api = use_context::<Option<RaceRestAPI>>();
+    let storage = config.storage;
+    let timezone = config.timezone;
+    use_future(move || deliver_results(api.clone(), outbox, selected_race, storage, timezone));
+    let sync_url = config.sync_url.clone();
+    let sync_enabled = sync_url.is_some();
+    use_future(move || {
+        let sync_url = sync_url.clone();
+        async move {
+            if let Some(url) = sync_url {
+                synchronize(url, selected_race, sync_state).await;
+            }
+        }
+    });
 
     use_coroutine(move |mut actions_rx: UnboundedReceiver<Action>| {
         let config = config.clone();
@@ -127,11 +207,13 @@ pub fn App() -> Element {
                 tokio::select! {
                     Ok(rfid_event) = rfid_rx.recv() => {
                         println!("{rfid_event:?}");
-                       handle_rfid_event(&mut selected_race, &mut rfid_devices, rfid_event);
+                        handle_rfid_event(&mut selected_race, &mut rfid_devices, rfid_event);
+                        push_live(&selected_race, outbox);
                     }
                     Some(action) = actions_rx.next() => {
                         println!("{action:?}");
                         handle_action(&mut selected_race, action);
+                        push_live(&selected_race, outbox);
                     }
                 }
             }
@@ -154,15 +236,86 @@ pub fn App() -> Element {
                         show_starts.toggle();
                     },
                 }
+                button {
+                    class: "btn",
+                    class: if show_replay() { "btn-warning" } else { "btn-light" },
+                    dangerous_inner_html: iconify::svg!("mdi:history"),
+                    onclick: move |_| {
+                        show_replay.toggle();
+                        if !show_replay() {
+                            replay_at.set(None);
+                        }
+                    },
+                }
+                button {
+                    class: "btn",
+                    class: if show_refresh() { "btn-warning" } else { "btn-light" },
+                    title: "Refresh registrations",
+                    dangerous_inner_html: iconify::svg!("mdi:account-sync"),
+                    onclick: move |_| show_refresh.toggle(),
+                }
+                button {
+                    class: "btn",
+                    class: if show_add_racer() { "btn-warning" } else { "btn-light" },
+                    title: "Add racer",
+                    dangerous_inner_html: iconify::svg!("mdi:account-plus"),
+                    onclick: move |_| show_add_racer.toggle(),
+                }
+                button {
+                    class: "btn",
+                    class: if show_teams() { "btn-warning" } else { "btn-light" },
+                    title: "Team standings",
+                    dangerous_inner_html: iconify::svg!("mdi:account-group"),
+                    onclick: move |_| show_teams.toggle(),
+                }
+                button {
+                    class: "btn",
+                    class: if show_series() { "btn-warning" } else { "btn-light" },
+                    title: "Series standings",
+                    dangerous_inner_html: iconify::svg!("mdi:trophy"),
+                    onclick: move |_| show_series.toggle(),
+                }
+                button {
+                    class: "btn",
+                    class: if show_start_list() { "btn-warning" } else { "btn-light" },
+                    title: "Pursuit start list",
+                    dangerous_inner_html: iconify::svg!("mdi:format-list-numbered"),
+                    onclick: move |_| show_start_list.toggle(),
+                }
+                button {
+                    class: "btn",
+                    class: if show_age_graded() { "btn-warning" } else { "btn-light" },
+                    title: "Age graded results",
+                    dangerous_inner_html: iconify::svg!("mdi:human-cane"),
+                    onclick: move |_| show_age_graded.toggle(),
+                }
+                button {
+                    class: "btn",
+                    class: if show_announcer() { "btn-warning" } else { "btn-light" },
+                    title: "Prize winners",
+                    dangerous_inner_html: iconify::svg!("mdi:bullhorn"),
+                    onclick: move |_| show_announcer.toggle(),
+                }
                 RacesList { selected_race }
                 span {
                     class: "btn",
                     class: if rfid_devices.read().is_ok() { "btn-success" } else { "btn-danger" },
                     dangerous_inner_html: iconify::svg!("ri:rfid-line"),
                 }
+                if sync_enabled {
+                    button {
+                        class: "btn",
+                        class: if sync_state.read().is_connected() { "btn-success" } else { "btn-danger" },
+                        title: "Synchronized stations",
+                        dangerous_inner_html: iconify::svg!("mdi:lan-connect"),
+                        onclick: move |_| show_sync.toggle(),
+                    }
+                }
                 match race {
                     Some(race) => rsx! {
-                        UploadResults { race: race.clone() }
+                        if !is_local_race(race.id) {
+                            UploadResults { race: race.clone() }
+                        }
                         button {
                             class: "btn btn-primary",
                             onclick: move |_| {
@@ -184,15 +337,85 @@ pub fn App() -> Element {
 
             match &*selected_race.read() {
                 Some(Ok(race)) => rsx! {
-                    if show_starts() {
-                        div { class: "d-flex flex-column row-gap-1 mb-1",
-                            for track in race.tracks_stats() {
-                                track::Track { track }
-                            }
+                    if let Some(fetched) = race.offline {
+                        p { class: "alert alert-warning mb-1",
+                            "Offline, registrations from {format_date(fetched, race.timezone)} {format_time(Some(fetched), race.timezone)}"
                         }
                     }
-                    ManualStartNumberInput {}
-                    Racers { race: race.clone() }
+                    match replay_at() {
+                        Some(at) => rsx! {
+                            Replay { race: race.clone(), replay_at }
+                            Racers { race: race.replay(at), readonly: true }
+                        },
+                        None => rsx! {
+                            if show_replay() {
+                                Replay { race: race.clone(), replay_at }
+                            }
+                            if show_sync() {
+                                SyncStatus {
+                                    state: sync_state,
+                                    station: race.station(),
+                                    timezone: race.timezone,
+                                }
+                            }
+                            if show_add_racer() {
+                                AddRacer { race: race.clone(), show: show_add_racer }
+                            }
+                            if show_refresh() && !is_local_race(race.id) {
+                                RefreshRegistrations { race: race.clone(), show: show_refresh }
+                            }
+                            if validation_dismissed() != Some(race.id)
+                                && race.racers.iter().all(|racer| racer.start.is_none())
+                            {
+                                {
+                                    let issues = validate(
+                                        &race.racers,
+                                        &race.track_info,
+                                        &race.config,
+                                        race.race_date(),
+                                    );
+                                    let race_id = race.id;
+                                    rsx! {
+                                        if !issues.is_empty() {
+                                            ValidationReport {
+                                                issues,
+                                                ondismiss: move |_| validation_dismissed.set(Some(race_id)),
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                            if show_series() {
+                                SeriesStandings {}
+                            }
+                            if show_teams() {
+                                TeamStandings { race: race.clone() }
+                            }
+                            if show_start_list() {
+                                StartList { race: race.clone() }
+                            }
+                            if show_age_graded() {
+                                AgeGraded { race: race.clone() }
+                            }
+                            if show_announcer() {
+                                Announcer { race: race.clone() }
+                            }
+                            if show_starts() {
+                                div { class: "d-flex flex-column row-gap-1 mb-1",
+                                    for track in race.tracks_stats() {
+                                        track::Track {
+                                            info: race.track_info.get(&track.track).cloned().unwrap_or_default(),
+                                            track,
+                                            timezone: race.timezone,
+                                        }
+                                    }
+                                }
+                            }
+                            PendingDnf { race: race.clone() }
+                            ManualStartNumberInput {}
+                            Racers { race: race.clone() }
+                        },
+                    }
                 },
                 Some(Err(err)) => rsx! {
                     p { class: "alert alert-danger", "{err:#?}" }
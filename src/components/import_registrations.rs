@@ -0,0 +1,173 @@
+use chrono::{NaiveDate, Utc};
+use dioxus::prelude::*;
+use tracing::info;
+
+use crate::{
+    config::Config,
+    registrations::{
+        guess_mapping, read_table, table_to_registrations, Column, ColumnMapping, Table,
+    },
+    storage::create_local_race,
+    time_utils::localize,
+};
+
+/// Number of imported racers shown before the race is created
+const PREVIEW_ROWS: usize = 5;
+
+/// Create a local race from registrations in a CSV or spreadsheet file
+#[component]
+pub fn ImportRegistrations(oncreated: EventHandler<u32>) -> Element {
+    let config = use_context::<Config>();
+    let mut name = use_signal(String::new);
+    let mut date = use_signal(|| Utc::now().with_timezone(&config.timezone).date_naive());
+    let mut table = use_signal(|| Option::<Table>::None);
+    let mut mapping = use_signal(ColumnMapping::new);
+    let mut error = use_signal(|| Option::<String>::None);
+
+    let registrations = table
+        .read()
+        .as_ref()
+        .map(|table| table_to_registrations(table, &mapping.read()));
+    let header = table
+        .read()
+        .as_ref()
+        .map(|table| table.header.clone())
+        .unwrap_or_default();
+    let can_create = !name().trim().is_empty() && matches!(registrations, Some(Ok(_)));
+
+    rsx! {
+        div {
+            class: "card position-absolute top-100 start-0 shadow",
+            style: "z-index: 10; width: 700px",
+            div { class: "card-body d-flex flex-column row-gap-2",
+                div { class: "input-group",
+                    span { class: "input-group-text", "Race" }
+                    input {
+                        class: "form-control",
+                        placeholder: "Name",
+                        value: name,
+                        oninput: move |e| name.set(e.value()),
+                    }
+                    input {
+                        class: "form-control",
+                        r#type: "date",
+                        value: "{date}",
+                        oninput: move |e| {
+                            if let Ok(parsed) = NaiveDate::parse_from_str(&e.value(), "%Y-%m-%d") {
+                                date.set(parsed);
+                            }
+                        },
+                    }
+                }
+                input {
+                    class: "form-control",
+                    r#type: "file",
+                    accept: ".csv,.xlsx,.xls,.ods",
+                    onchange: move |e| async move {
+                        let Some(files) = e.files() else {
+                            return;
+                        };
+                        let Some(file_name) = files.files().into_iter().next() else {
+                            return;
+                        };
+                        let Some(data) = files.read_file(&file_name).await else {
+                            error.set(Some(format!("Failed to read {file_name}")));
+                            return;
+                        };
+                        match read_table(&file_name, &data) {
+                            Ok(loaded) => {
+                                mapping.set(guess_mapping(&loaded.header));
+                                table.set(Some(loaded));
+                                error.set(None);
+                            }
+                            Err(err) => {
+                                table.set(None);
+                                error.set(Some(format!("Failed to read {file_name}: {err}")));
+                            }
+                        }
+                    },
+                }
+                if let Some(err) = error() {
+                    p { class: "alert alert-danger mb-0", "{err}" }
+                }
+                if table.read().is_some() {
+                    div { class: "d-flex flex-wrap gap-1",
+                        for column in Column::ALL {
+                            div { class: "input-group", style: "width: 330px",
+                                span { class: "input-group-text", style: "width: 130px", "{column}" }
+                                select {
+                                    class: "form-select",
+                                    onchange: move |e| {
+                                        match e.value().parse::<usize>() {
+                                            Ok(index) => mapping.write().insert(column, index),
+                                            Err(_) => mapping.write().remove(&column),
+                                        };
+                                    },
+                                    option {
+                                        value: "",
+                                        selected: !mapping.read().contains_key(&column),
+                                        "—"
+                                    }
+                                    for (index , title) in header.iter().enumerate() {
+                                        option {
+                                            value: "{index}",
+                                            selected: mapping.read().get(&column) == Some(&index),
+                                            "{title}"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                match &registrations {
+                    Some(Ok(racers)) => rsx! {
+                        table { class: "table table-sm mb-0",
+                            tbody {
+                                for racer in racers.iter().take(PREVIEW_ROWS) {
+                                    tr {
+                                        td { "{racer.start_number.map(|n| n.to_string()).unwrap_or_default()}" }
+                                        td { "{racer.first_name}" }
+                                        td { "{racer.last_name}" }
+                                        td { "{racer.tag_id.clone().unwrap_or_default()}" }
+                                        td { "{racer.track.name}" }
+                                        td {
+                                            for category in racer.categories.iter() {
+                                                "{category.name} "
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        span { class: "text-secondary", "{racers.len()} racers" }
+                    },
+                    Some(Err(err)) => rsx! {
+                        p { class: "alert alert-warning mb-0", "{err}" }
+                    },
+                    None => rsx! {},
+                }
+            }
+            div { class: "card-footer d-flex justify-content-end",
+                button {
+                    class: "btn btn-primary",
+                    disabled: !can_create,
+                    onclick: move |_| {
+                        let Some(Ok(racers)) = table
+                            .read()
+                            .as_ref()
+                            .map(|table| table_to_registrations(table, &mapping.read()))
+                        else {
+                            return;
+                        };
+                        let date_of_event = localize(config.timezone, date().and_time(Default::default()));
+                        let race = create_local_race(name().trim(), date_of_event, config.storage, racers);
+                        info!("Created local race {} with id {}", race.name, race.id);
+                        oncreated(race.id);
+                    },
+                    "Create race"
+                }
+            }
+        }
+    }
+}
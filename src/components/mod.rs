@@ -1,10 +1,22 @@
+pub mod add_racer;
+pub mod age_graded;
+pub mod announcer;
 pub mod app;
 pub mod categories_list;
+pub mod import_registrations;
 pub mod manual_start_number_input;
+pub mod pending_dnf;
 pub mod racer_row;
 pub mod racers;
 pub mod races_list;
+pub mod refresh_registrations;
+pub mod replay;
+pub mod series_standings;
+pub mod start_list;
+pub mod sync_status;
+pub mod team_standings;
 pub mod th;
 pub mod time_input;
 pub mod track;
 pub mod upload_results;
+pub mod validation_report;
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use dioxus::prelude::*;
+
+use crate::{components::app::Action, race::Race, time_utils::format_time};
+
+/// Racers still on the course after their cut-off, waiting for the operator to confirm DNF
+#[component]
+pub fn PendingDnf(race: Race) -> Element {
+    let mut now = use_signal(Utc::now);
+
+    use_hook(move || {
+        spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                now.set(Utc::now());
+            }
+        });
+    });
+
+    let pending: Vec<_> = race
+        .pending_dnf(now())
+        .into_iter()
+        .map(|racer| (racer.clone(), race.cut_off(racer)))
+        .collect();
+    if pending.is_empty() {
+        return rsx! {};
+    }
+    let start_numbers: Vec<_> = pending
+        .iter()
+        .map(|(racer, _)| racer.start_number.clone())
+        .collect();
+
+    rsx! {
+        div { class: "alert alert-danger mb-1",
+            div { class: "d-flex justify-content-between align-items-center",
+                strong { "Past the cut-off without a finish" }
+                button {
+                    class: "btn btn-sm btn-danger",
+                    onclick: move |_| {
+                        for start_number in start_numbers.iter() {
+                            use_coroutine_handle::<Action>()
+                                .send(Action::Dnf(start_number.clone(), true));
+                        }
+                    },
+                    "Confirm all DNF"
+                }
+            }
+            ul { class: "mb-0",
+                for (racer , cut_off) in pending {
+                    li {
+                        "{racer.start_number} {racer.first_name} {racer.last_name}, {racer.track}, cut-off {format_time(cut_off, race.timezone)} "
+                        button {
+                            class: "btn btn-sm btn-link p-0",
+                            onclick: move |_| {
+                                use_coroutine_handle::<Action>()
+                                    .send(Action::Dnf(racer.start_number.clone(), true));
+                            },
+                            "Confirm DNF"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
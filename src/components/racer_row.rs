@@ -1,12 +1,19 @@
+use chrono_tz::Tz;
 use dioxus::prelude::*;
 
 use crate::components::app::Action;
 use crate::components::time_input::TimeInput;
 use crate::race::Racer;
-use crate::time_utils::{format_time, format_time_delta_millis};
+use crate::time_utils::{format_time, Timing};
 
 #[component]
-pub fn RacerRow(racer: Racer) -> Element {
+pub fn RacerRow(
+    racer: Racer,
+    timezone: Tz,
+    readonly: Option<bool>,
+    status: Option<String>,
+    timing: Timing,
+) -> Element {
     let editing = use_signal(|| false);
     let start_number = racer.start_number.clone();
 
@@ -16,20 +23,37 @@ pub fn RacerRow(racer: Racer) -> Element {
             td { "{racer.first_name}" }
             td { "{racer.last_name}" }
             td { "{racer.track}" }
-            td { "{format_time(racer.start)}" }
+            td { "{format_time(racer.start, timezone)}" }
             td { width: "124px",
-                TimeInput {
-                    time: racer.finish,
-                    editing,
-                    remove_button: true,
-                    onsave: move |time| {
-                        use_coroutine_handle::<Action>()
-                            .send(Action::FinishEdit(start_number.clone(), time));
-                    },
+                if let Some(true) = readonly {
+                    "{format_time(racer.finish, timezone)}"
+                } else {
+                    TimeInput {
+                        time: racer.finish,
+                        timezone,
+                        editing,
+                        remove_button: true,
+                        onsave: move |time| {
+                            use_coroutine_handle::<Action>()
+                                .send(Action::FinishEdit(start_number.clone(), time));
+                        },
+                    }
+                }
+            }
+            td {
+                "{timing.format(racer.time)}"
+                for (index , (leg , split)) in racer.legs.iter().zip(racer.leg_splits()).enumerate() {
+                    div { class: "small text-secondary text-nowrap",
+                        "{index + 1}. {leg.name} {timing.format(split)}"
+                    }
+                }
+            }
+            td {
+                "{racer.track_rank.map(|rank| rank.to_string()).unwrap_or_default() }"
+                if let Some(status) = status {
+                    span { class: "badge text-bg-danger", "{status}" }
                 }
             }
-            td { "{format_time_delta_millis(racer.time)}" }
-            td { "{racer.track_rank.map(|rank| rank.to_string()).unwrap_or_default() }" }
             td {
                 for category in racer.categories.clone() {
                     "{category} "
@@ -1,3 +1,4 @@
+use chrono_tz::Tz;
 use dioxus::prelude::*;
 
 use crate::{
@@ -7,7 +8,7 @@ use crate::{
         th::{Sorter, Th},
     },
     race::{Category, Race, Racer, RacerField},
-    time_utils::{format_time, format_time_delta_millis},
+    time_utils::{format_time, Timing},
 };
 
 // Checks whether a racer matches all active filters in the map.
@@ -17,6 +18,8 @@ fn matches_filters(
     racer: &Racer,
     filters: &std::collections::HashMap<RacerField, String>,
     category: Option<Category>,
+    tz: Tz,
+    timing: &Timing,
 ) -> bool {
     if let Some(cat) = category {
         if !racer.categories.contains(&cat) {
@@ -66,7 +69,7 @@ fn matches_filters(
                 }
             }
             RacerField::Start => {
-                if !format_time(racer.start)
+                if !format_time(racer.start, tz)
                     .to_lowercase()
                     .contains(&filter_lowercase)
                 {
@@ -74,7 +77,7 @@ fn matches_filters(
                 }
             }
             RacerField::Finish => {
-                if !format_time(racer.finish)
+                if !format_time(racer.finish, tz)
                     .to_lowercase()
                     .contains(&filter_lowercase)
                 {
@@ -82,7 +85,8 @@ fn matches_filters(
                 }
             }
             RacerField::Time => {
-                if !format_time_delta_millis(racer.time)
+                if !timing
+                    .format(racer.time)
                     .to_lowercase()
                     .contains(&filter_lowercase)
                 {
@@ -99,7 +103,7 @@ fn matches_filters(
 }
 
 #[component]
-pub fn Racers(race: Race) -> Element {
+pub fn Racers(race: Race, readonly: Option<bool>) -> Element {
     let selected_category_id = use_signal(|| Option::<Category>::None);
     let sorter = use_signal(|| Sorter::<RacerField>::new(RacerField::StartNumber));
 
@@ -154,9 +158,23 @@ pub fn Racers(race: Race) -> Element {
                 tbody {
                     for racer in sorted
                         .iter()
-                        .filter(|racer| matches_filters(racer, &filters(), selected_category_id()))
+                        .filter(|racer| {
+                            matches_filters(
+                                racer,
+                                &filters(),
+                                selected_category_id(),
+                                race.timezone,
+                                &race.config.timing,
+                            )
+                        })
                     {
-                        RacerRow { racer: racer.clone() }
+                        RacerRow {
+                            racer: racer.clone(),
+                            timezone: race.timezone,
+                            timing: race.config.timing,
+                            readonly,
+                            status: race.status(racer).map(String::from),
+                        }
                     }
                 }
             }
@@ -1,57 +1,219 @@
 use std::error::Error;
+use std::time::Duration;
 
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use dioxus::prelude::*;
+use tracing::{error, info};
 
-use crate::{race::Race, restclient::RaceRestAPI};
+use crate::{
+    components::import_registrations::ImportRegistrations,
+    config::{race_timezone, Config, StorageBackend},
+    race::Race,
+    registrations::ImportedRegistrations,
+    restclient::RaceRestAPI,
+    storage::{cached_races, is_local_race, local_races, save_races, RacesSnapshot},
+    time_utils::{format_date, format_time},
+};
 
 type SignalRace = Signal<Option<Result<Race, Box<dyn std::error::Error>>>>;
 
-async fn load_race(api: RaceRestAPI, mut selected_race: SignalRace, id: u32) {
+/// How often to retry the registration API while working offline
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The registration API is unreachable
+struct Offline {
+    error: String,
+    /// time of the cached races
+    cached: Option<DateTime<Utc>>,
+}
+
+struct Races {
+    races: Vec<crate::restclient::Race>,
+    offline: Option<Offline>,
+}
+
+async fn load(
+    api: &Option<RaceRestAPI>,
+    storage: StorageBackend,
+    timezone: Tz,
+    id: u32,
+) -> Result<Race, Box<dyn Error>> {
+    if is_local_race(id) {
+        let source = ImportedRegistrations { storage };
+        Race::load(&source, id, storage, timezone).await
+    } else {
+        Race::load(api, id, storage, timezone).await
+    }
+}
+
+async fn load_race(
+    api: Option<RaceRestAPI>,
+    storage: StorageBackend,
+    timezone: Tz,
+    mut selected_race: SignalRace,
+    id: u32,
+) {
     selected_race.set(None);
-    let race = Race::load(api, id).await;
+    let race = load(&api, storage, timezone, id).await;
     selected_race.set(Some(race));
 }
 
+async fn fetch_races(api: &Option<RaceRestAPI>, timezone: Tz) -> Races {
+    let (mut races, offline) = match api {
+        None => (Vec::new(), None),
+        Some(api) => match api.races(|race_id| race_timezone(race_id, timezone)).await {
+            Ok(races) => {
+                save_races(&RacesSnapshot {
+                    fetched: Utc::now(),
+                    races: races.clone(),
+                });
+                (races, None)
+            }
+            Err(err) => {
+                error!("Failed to load races: {err}");
+                let cached = cached_races();
+                let offline = Offline {
+                    error: err.to_string(),
+                    cached: cached.as_ref().map(|snapshot| snapshot.fetched),
+                };
+                let races = cached.map(|snapshot| snapshot.races).unwrap_or_default();
+                (races, Some(offline))
+            }
+        },
+    };
+    races.extend(local_races());
+    Races { races, offline }
+}
+
+fn is_offline(races: &Resource<Races>, selected_race: &SignalRace) -> bool {
+    let races_offline = matches!(&*races.peek(), Some(races) if races.offline.is_some());
+    let race_offline = matches!(&*selected_race.peek(), Some(Ok(race)) if race.offline.is_some());
+    races_offline || race_offline
+}
+
 #[component]
 pub fn RacesList(selected_race: SignalRace) -> Element {
-    let api = use_context::<RaceRestAPI>();
+    let api = use_context::<Option<RaceRestAPI>>();
+    let storage = use_context::<Config>().storage;
+    let timezone = use_context::<Config>().timezone;
+    let mut show_import = use_signal(|| false);
 
-    let races: Resource<Result<Vec<crate::restclient::Race>, Box<dyn Error>>> =
-        use_resource(move || {
-            let api = api.clone();
-            async move {
-                let mut races = api.races().await?;
-                races.sort_by(|a, b| b.date_of_event.cmp(&a.date_of_event));
+    let mut races: Resource<Races> = use_resource(move || {
+        let api = api.clone();
+        async move {
+            let mut races = fetch_races(&api, timezone).await;
+            races
+                .races
+                .sort_by_key(|race| std::cmp::Reverse(race.date_of_event));
 
-                if let Some(earliest_race) = races.first() {
-                    load_race(api, selected_race, earliest_race.id).await;
+            let selected = match &*selected_race.peek() {
+                Some(Ok(race)) => Some((race.id, race.offline.is_some())),
+                _ => None,
+            };
+            match selected {
+                None => {
+                    if let Some(earliest_race) = races.races.first() {
+                        load_race(api, storage, timezone, selected_race, earliest_race.id).await;
+                    }
+                }
+                // connectivity is back, refresh registrations of the selected race
+                Some((id, true)) if races.offline.is_none() => {
+                    if let Ok(race) = load(&api, storage, timezone, id).await {
+                        if race.offline.is_none() {
+                            info!("Registrations of race {id} refreshed");
+                            selected_race.set(Some(Ok(race)));
+                        }
+                    }
                 }
-                Ok(races)
+                _ => {}
+            }
+            races
+        }
+    });
+
+    use_future(move || async move {
+        loop {
+            tokio::time::sleep(RECONNECT_INTERVAL).await;
+            if is_offline(&races, &selected_race) {
+                info!("Offline, trying to reconnect");
+                races.restart();
             }
-        });
+        }
+    });
+
+    let selected_id = match &*selected_race.read() {
+        Some(Ok(race)) => Some(race.id),
+        _ => None,
+    };
 
     rsx! {
-        match &*races.read() {
-            Some(Ok(races)) => rsx! {
+        if let Some(races) = &*races.read() {
+            if races.races.is_empty() {
+                match &races.offline {
+                    Some(offline) => rsx! {
+                        p { class: "alert alert-danger mb-0", "{offline.error}" }
+                    },
+                    None => rsx! {
+                        span { class: "btn text-nowrap", "No races, import registrations to create one" }
+                    },
+                }
+            } else {
                 select {
                     class: "form-select",
                     onchange: move |e| {
                         e.prevent_default();
                         let race_id = e.value().parse::<u32>().ok().unwrap();
-                        let api = use_context::<RaceRestAPI>();
+                        let api = use_context::<Option<RaceRestAPI>>();
                         spawn(async move {
-                            load_race(api, selected_race, race_id).await;
+                            load_race(api, storage, timezone, selected_race, race_id).await;
                         });
                     },
-                    for race in races.iter() {
-                        option { value: "{race.id}", "{race.name}" }
+                    for race in races.races.iter() {
+                        option {
+                            value: "{race.id}",
+                            selected: selected_id == Some(race.id),
+                            if is_local_race(race.id) {
+                                "{race.name} (local)"
+                            } else {
+                                "{race.name}"
+                            }
+                        }
+                    }
+                }
+            }
+            if let Some(offline) = &races.offline {
+                span {
+                    class: "btn btn-warning text-nowrap",
+                    title: "Registration API is unreachable: {offline.error}",
+                    if let Some(fetched) = offline.cached {
+                        "Offline, races from {format_date(fetched, timezone)} {format_time(Some(fetched), timezone)}"
+                    } else {
+                        "Offline"
                     }
                 }
-            },
-            Some(Err(err)) => rsx! {
-                p { class: "alert alert-danger", "{err:#?}" }
-            },
-            _ => rsx! {},
+            }
+        }
+        div { class: "position-relative",
+            button {
+                class: "btn",
+                class: if show_import() { "btn-warning" } else { "btn-light" },
+                title: "Import registrations",
+                dangerous_inner_html: iconify::svg!("mdi:file-import"),
+                onclick: move |_| show_import.toggle(),
+            }
+            if show_import() {
+                ImportRegistrations {
+                    oncreated: move |race_id| {
+                        show_import.set(false);
+                        races.restart();
+                        let api = use_context::<Option<RaceRestAPI>>();
+                        spawn(async move {
+                            load_race(api, storage, timezone, selected_race, race_id).await;
+                        });
+                    },
+                }
+            }
         }
     }
 }
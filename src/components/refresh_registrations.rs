@@ -0,0 +1,118 @@
+use dioxus::prelude::*;
+
+use crate::{
+    components::app::Action,
+    race::Race,
+    registrations::diff_registrations,
+    restclient::{RaceRestAPI, Racer},
+};
+
+fn racer_label(racer: &Racer) -> String {
+    format!(
+        "{} {} {} ({})",
+        racer
+            .start_number
+            .map(|start_number| start_number.to_string())
+            .unwrap_or_default(),
+        racer.first_name,
+        racer.last_name,
+        racer.track.name
+    )
+}
+
+/// Fetch the registrations again and show what changed since the race was loaded
+#[component]
+pub fn RefreshRegistrations(race: Race, show: Signal<bool>) -> Element {
+    let api = use_context::<Option<RaceRestAPI>>();
+    let race_id = race.id;
+    let registrations = use_resource(move || {
+        let api = api.clone();
+        async move {
+            match api {
+                Some(api) => api
+                    .registrations(race_id)
+                    .await
+                    .map_err(|err| err.to_string()),
+                None => Err("Registration API is not configured".to_string()),
+            }
+        }
+    });
+
+    let registrations = match &*registrations.read() {
+        Some(Ok(registrations)) => registrations.clone(),
+        Some(Err(err)) => {
+            return rsx! {
+                p { class: "alert alert-danger mb-1", "Failed to refresh registrations: {err}" }
+            }
+        }
+        None => {
+            return rsx! {
+                p { class: "alert alert-secondary mb-1", "Loading registrations…" }
+            }
+        }
+    };
+
+    let diff = diff_registrations(&race.registrations(), &registrations);
+    if diff.is_empty() {
+        return rsx! {
+            p { class: "alert alert-success mb-1", "Registrations are up to date" }
+        };
+    }
+
+    let has_finish = |racer: &Racer| {
+        race.racers
+            .iter()
+            .any(|r| r.id == racer.id && r.finish.is_some())
+    };
+
+    rsx! {
+        div { class: "card mb-1",
+            div { class: "card-body d-flex column-gap-4",
+                div {
+                    h6 { "Added ({diff.added.len()})" }
+                    for racer in diff.added.iter() {
+                        div { class: "text-success", "{racer_label(racer)}" }
+                    }
+                }
+                div {
+                    h6 { "Removed ({diff.removed.len()})" }
+                    for racer in diff.removed.iter() {
+                        div { class: "text-danger",
+                            "{racer_label(racer)} "
+                            if has_finish(racer) {
+                                span { class: "badge text-bg-warning", "finished" }
+                            }
+                        }
+                    }
+                }
+                div {
+                    h6 { "Changed ({diff.changed.len()})" }
+                    for changed in diff.changed.iter() {
+                        div {
+                            "{racer_label(&changed.racer)}: "
+                            for change in changed.changes.iter() {
+                                span { class: "badge text-bg-info me-1", "{change}" }
+                            }
+                        }
+                    }
+                }
+            }
+            div { class: "card-footer d-flex column-gap-1 justify-content-end",
+                button {
+                    class: "btn btn-outline-secondary",
+                    onclick: move |_| show.set(false),
+                    "Cancel"
+                }
+                button {
+                    class: "btn btn-primary",
+                    onclick: move |_| {
+                        use_coroutine_handle::<Action>()
+                            .send(Action::ApplyRegistrations(registrations.clone()));
+                        show.set(false);
+                    },
+                    "Apply"
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use chrono::{DateTime, Duration, Utc};
+use dioxus::prelude::*;
+
+use crate::{race::Race, time_utils::format_time};
+
+#[component]
+pub fn Replay(race: Race, replay_at: Signal<Option<DateTime<Utc>>>) -> Element {
+    let Some((first, last)) = race.log_period() else {
+        return rsx! {
+            p { class: "alert alert-secondary mb-1", "Nothing has been logged yet" }
+        };
+    };
+    let max = last.signed_duration_since(first).num_seconds();
+    let value = replay_at()
+        .map(|at| at.signed_duration_since(first).num_seconds())
+        .unwrap_or(max);
+
+    rsx! {
+        div { class: "input-group mb-1",
+            span { class: "input-group-text", style: "width: 150px",
+                if replay_at().is_some() {
+                    "{format_time(replay_at(), race.timezone)}"
+                } else {
+                    "Live"
+                }
+            }
+            span { class: "input-group-text flex-grow-1",
+                input {
+                    class: "form-range",
+                    r#type: "range",
+                    min: 0,
+                    max,
+                    value,
+                    oninput: move |e| {
+                        if let Ok(secs) = e.value().parse::<i64>() {
+                            let at = if secs >= max { last } else { first + Duration::seconds(secs) };
+                            replay_at.set(Some(at));
+                        }
+                    },
+                }
+            }
+            button {
+                class: "btn btn-outline-secondary",
+                onclick: move |_| replay_at.set(None),
+                "Live"
+            }
+        }
+        match race.verify_upload() {
+            Some(mismatches) if mismatches.is_empty() => rsx! {
+                p { class: "alert alert-success mb-1", "Event log reproduces the last uploaded results" }
+            },
+            Some(mismatches) => rsx! {
+                p { class: "alert alert-danger mb-1",
+                    "Event log differs from the last upload for registrations: {mismatches:?}"
+                }
+            },
+            None => rsx! {},
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use dioxus::prelude::*;
+use tracing::error;
+
+use crate::{
+    config::Config,
+    race::Race,
+    series::{load_match_decisions, load_series, save_match_decisions, Series},
+};
+
+/// Races of the series as they are kept in the race storage
+fn load_races(series: &Series, config: &Config) -> (Vec<Race>, Vec<String>) {
+    let mut races = Vec::new();
+    let mut errors = Vec::new();
+    for race_id in &series.races {
+        match Race::load_stored(*race_id, config.storage, config.timezone) {
+            Ok(race) => races.push(race),
+            Err(err) => {
+                error!(
+                    "Failed to load race {race_id} of series {}: {err}",
+                    series.name
+                );
+                errors.push(format!("Race {race_id}: {err}"));
+            }
+        }
+    }
+    (races, errors)
+}
+
+/// Standings of the series in `series.toml` with the review of uncertain racer matches
+#[component]
+pub fn SeriesStandings() -> Element {
+    let config = use_context::<Config>();
+    let series_list = use_signal(load_series);
+    let mut selected = use_signal(|| 0usize);
+    let mut decisions = use_signal(load_match_decisions);
+
+    let races = use_resource(move || {
+        let config = config.clone();
+        async move {
+            let series = series_list.read().get(selected()).cloned()?;
+            Some(load_races(&series, &config))
+        }
+    });
+
+    if series_list.read().is_empty() {
+        return rsx! {
+            p { class: "alert alert-secondary mb-1", "No series, define them in series.toml" }
+        };
+    }
+    let series = series_list.read()[selected()].clone();
+
+    rsx! {
+        div { class: "card mb-1 overflow-auto", style: "max-height: 50vh",
+            div { class: "card-body d-flex flex-column row-gap-2",
+                select {
+                    class: "form-select w-auto",
+                    onchange: move |e| {
+                        if let Ok(index) = e.value().parse() {
+                            selected.set(index);
+                        }
+                    },
+                    for (index , series) in series_list.read().iter().enumerate() {
+                        option { value: "{index}", selected: index == selected(), "{series.name}" }
+                    }
+                }
+                match &*races.read() {
+                    Some(Some((races, errors))) => {
+                        let standings = series.standings(races, &decisions.read());
+                        rsx! {
+                            for err in errors.iter() {
+                                p { class: "alert alert-danger mb-0", "{err}" }
+                            }
+                            if !standings.reviews.is_empty() {
+                                div { class: "alert alert-warning mb-0",
+                                    strong { "Is it the same racer?" }
+                                    for review in standings.reviews {
+                                        div { class: "d-flex align-items-center gap-1 mt-1",
+                                            span { class: "me-auto", "{review.entry} / {review.candidate}" }
+                                            button {
+                                                class: "btn btn-sm btn-success",
+                                                onclick: {
+                                                    let key = review.key();
+                                                    move |_| {
+                                                        decisions.write().insert(key.clone(), true);
+                                                        save_match_decisions(&decisions.read());
+                                                    }
+                                                },
+                                                "Same"
+                                            }
+                                            button {
+                                                class: "btn btn-sm btn-outline-secondary",
+                                                onclick: {
+                                                    let key = review.key();
+                                                    move |_| {
+                                                        decisions.write().insert(key.clone(), false);
+                                                        save_match_decisions(&decisions.read());
+                                                    }
+                                                },
+                                                "Different"
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                            for category in standings.categories {
+                                table { class: "table table-sm w-auto mb-0",
+                                    thead {
+                                        tr {
+                                            th { colspan: 3, "{category.category}" }
+                                            for race_id in series.races.iter() {
+                                                th { class: "text-end", "{race_id}" }
+                                            }
+                                            th { class: "text-end", "Total" }
+                                        }
+                                    }
+                                    tbody {
+                                        for row in category.rows {
+                                            tr {
+                                                td { "{row.rank}." }
+                                                td { "{row.name}" }
+                                                td { "{row.birth_year.map(|y| y.to_string()).unwrap_or_default()}" }
+                                                for points in row.races {
+                                                    match points {
+                                                        Some((points, true)) => rsx! {
+                                                            td { class: "text-end", "{points}" }
+                                                        },
+                                                        Some((points, false)) => rsx! {
+                                                            td { class: "text-end text-secondary text-decoration-line-through",
+                                                                "{points}"
+                                                            }
+                                                        },
+                                                        None => rsx! {
+                                                            td {}
+                                                        },
+                                                    }
+                                                }
+                                                td { class: "text-end fw-bold", "{row.total}" }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    _ => rsx! {
+                        span { class: "text-secondary", "Loading races…" }
+                    },
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use dioxus::prelude::*;
+
+use crate::{
+    handicap::{by_running_time, has_start_offsets, start_list},
+    race::Race,
+    time_utils::{format_time, format_time_delta_secs},
+};
+
+/// Pursuit start list of a handicap race with the fastest running times
+#[component]
+pub fn StartList(race: Race) -> Element {
+    if !has_start_offsets(&race.config) {
+        return rsx! {
+            p { class: "alert alert-secondary mb-1",
+                "No handicap, set start_offset of the categories or start_offsets in the race config"
+            }
+        };
+    }
+
+    rsx! {
+        div { class: "d-flex flex-wrap gap-2 mb-1 overflow-auto", style: "max-height: 40vh",
+            for track_start_list in start_list(&race) {
+                table { class: "table table-sm w-auto mb-0",
+                    thead {
+                        tr {
+                            th { colspan: 3, "{track_start_list.track}" }
+                        }
+                    }
+                    tbody {
+                        for wave in track_start_list.waves {
+                            tr {
+                                td { "+{format_time_delta_secs(Some(wave.offset))}" }
+                                td { "{format_time(wave.start, race.timezone)}" }
+                                td {
+                                    for racer in wave.racers {
+                                        div { class: "small",
+                                            "{racer.start_number} {racer.first_name} {racer.last_name}"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        for (index , racer) in by_running_time(&race, &track_start_list.track).into_iter().enumerate() {
+                            if index == 0 {
+                                tr {
+                                    th { colspan: 3, "By running time" }
+                                }
+                            }
+                            tr {
+                                td { "{index + 1}." }
+                                td { "{racer.start_number} {racer.first_name} {racer.last_name}" }
+                                td { "{race.config.timing.format(racer.time)}" }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use dioxus::prelude::*;
+use tracing::{error, info};
+
+use crate::{
+    components::app::SelectedRace,
+    sync::{instance_token, sync, StationStatus, SyncRequest},
+    time_utils::format_time,
+};
+
+const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SyncState {
+    /// stations as reported by the primary
+    pub stations: Vec<StationStatus>,
+    /// last successful exchange with the primary
+    pub last_contact: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+}
+
+impl SyncState {
+    pub fn is_connected(&self) -> bool {
+        self.error.is_none() && self.last_contact.is_some()
+    }
+}
+
+/// Exchange the race events with the primary station every second
+pub async fn synchronize(
+    url: String,
+    mut selected_race: Signal<SelectedRace>,
+    mut state: Signal<SyncState>,
+) {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(3))
+        .build()
+        .unwrap_or_default();
+    let instance = instance_token();
+    // race and the sequence numbers acknowledged by the primary
+    let mut acknowledged: Option<(u32, HashMap<String, u64>)> = None;
+
+    loop {
+        let request = match &*selected_race.peek() {
+            Some(Ok(race)) => {
+                if acknowledged.as_ref().map(|(race_id, _)| *race_id) != Some(race.id) {
+                    acknowledged = Some((race.id, HashMap::new()));
+                }
+                acknowledged
+                    .as_ref()
+                    .map(|(_, known)| SyncRequest::new(race, &instance, known))
+            }
+            _ => None,
+        };
+
+        if let Some(request) = request {
+            match sync(&client, &url, &request).await {
+                Ok(response) => {
+                    if !response.events.is_empty() {
+                        selected_race.with_mut(|maybe_race| {
+                            if let Some(Ok(race)) = maybe_race {
+                                if race.id == request.race_id {
+                                    let applied = race.apply_remote(response.events);
+                                    info!("Received {applied} events from the primary");
+                                }
+                            }
+                        });
+                    }
+                    acknowledged = Some((request.race_id, response.known));
+                    state.set(SyncState {
+                        stations: response.stations,
+                        last_contact: Some(Utc::now()),
+                        error: None,
+                    });
+                }
+                Err(err) => {
+                    if state.peek().error.is_none() {
+                        error!("Failed to synchronize with {url}: {err}");
+                    }
+                    state.with_mut(|state| state.error = Some(err.to_string()));
+                }
+            }
+        }
+        tokio::time::sleep(SYNC_INTERVAL).await;
+    }
+}
+
+/// Stations synchronized through the primary and how far behind they are
+#[component]
+pub fn SyncStatus(state: Signal<SyncState>, station: String, timezone: Tz) -> Element {
+    let state = state.read();
+
+    rsx! {
+        div { class: "mb-1",
+            if let Some(err) = &state.error {
+                p { class: "alert alert-danger mb-1",
+                    "Primary unreachable: {err}"
+                    if let Some(contact) = state.last_contact {
+                        ", last contact {format_time(Some(contact), timezone)}"
+                    }
+                }
+            }
+            table { class: "table table-sm w-auto mb-0",
+                tbody {
+                    for status in state.stations.iter() {
+                        tr {
+                            td {
+                                "{status.station}"
+                                if status.primary {
+                                    span { class: "badge text-bg-secondary ms-1", "primary" }
+                                }
+                                if status.station == station {
+                                    span { class: "badge text-bg-info ms-1", "this station" }
+                                }
+                            }
+                            td {
+                                if status.connected {
+                                    span { class: "badge text-bg-success", "connected" }
+                                } else {
+                                    span { class: "badge text-bg-danger",
+                                        "last seen {format_time(Some(status.last_seen), timezone)}"
+                                    }
+                                }
+                            }
+                            td {
+                                if status.behind == 0 {
+                                    "up to date"
+                                } else {
+                                    "{status.behind} events behind"
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use dioxus::prelude::*;
+
+use crate::{race::Race, teams::team_standings};
+
+/// Team competition standings of each track
+#[component]
+pub fn TeamStandings(race: Race) -> Element {
+    let Some(scoring) = race.config.team_scoring.clone() else {
+        return rsx! {
+            p { class: "alert alert-secondary mb-1",
+                "No team competition, set team_scoring in the race config"
+            }
+        };
+    };
+    let standings = team_standings(&race, &scoring);
+    if standings.is_empty() {
+        return rsx! {
+            p { class: "alert alert-secondary mb-1", "No team has a finisher yet" }
+        };
+    }
+
+    rsx! {
+        div { class: "d-flex flex-wrap gap-2 mb-1 overflow-auto", style: "max-height: 40vh",
+            for track_standings in standings {
+                table { class: "table table-sm w-auto mb-0",
+                    thead {
+                        tr {
+                            th { colspan: 3, "{track_standings.track}" }
+                        }
+                    }
+                    tbody {
+                        for standing in track_standings.standings {
+                            tr {
+                                td { "{standing.rank}." }
+                                td {
+                                    strong { "{standing.team}" }
+                                    for member in standing.members.iter() {
+                                        div { class: "small text-secondary",
+                                            "{member.track_rank.unwrap_or_default()}. {member.name} {member.score.format(&race.config.timing)}"
+                                        }
+                                    }
+                                }
+                                td { class: "text-end", "{standing.score.format(&race.config.timing)}" }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,24 +1,30 @@
-use chrono::{DateTime, Local, NaiveTime, TimeZone, Utc};
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
 use dioxus::prelude::*;
 
+use crate::time_utils::localize;
+
 const TIME_FORMAT: &str = "%H:%M:%S";
 
-fn parse_time(str: &str) -> Option<DateTime<Utc>> {
+/// Parse the time on the day of `reference`, or today if there is none
+fn parse_time(str: &str, reference: Option<DateTime<Utc>>, tz: Tz) -> Option<DateTime<Utc>> {
     let naive_time = NaiveTime::parse_from_str(str, TIME_FORMAT).ok()?;
-    let local_time = Local
-        .from_local_datetime(&(Local::now().date_naive().and_time(naive_time)))
-        .single()?;
+    let date = reference
+        .unwrap_or_else(Utc::now)
+        .with_timezone(&tz)
+        .date_naive();
 
-    Some(local_time.to_utc())
+    Some(localize(tz, date.and_time(naive_time)))
 }
 
-fn format_time(time: DateTime<Utc>) -> String {
-    time.with_timezone(&Local).format(TIME_FORMAT).to_string()
+fn format_time(time: DateTime<Utc>, tz: Tz) -> String {
+    time.with_timezone(&tz).format(TIME_FORMAT).to_string()
 }
 
 #[component]
 pub fn TimeInput(
     time: Option<DateTime<Utc>>,
+    timezone: Tz,
     editing: Signal<bool>,
     onsave: EventHandler<Option<DateTime<Utc>>>,
     span_class: Option<String>,
@@ -29,7 +35,7 @@ pub fn TimeInput(
     use_effect(move || {
         if editing() {
             text.set(match time {
-                Some(start) => format_time(start),
+                Some(start) => format_time(start, timezone),
                 None => "".to_string(),
             });
         }
@@ -39,12 +45,12 @@ pub fn TimeInput(
         if editing() {
             input {
                 class: "form-control form-control-sm",
-                class: if parse_time(&text()).is_none() { "is-invalid" },
+                class: if parse_time(&text(), time, timezone).is_none() { "is-invalid" },
                 autofocus: true,
                 value: text,
                 onkeydown: move |evt| {
                     if evt.key() == Key::Enter {
-                        if let Some(parsed) = parse_time(&text()) {
+                        if let Some(parsed) = parse_time(&text(), time, timezone) {
                             editing.set(false);
                             onsave(Some(parsed));
                         }
@@ -61,7 +67,7 @@ pub fn TimeInput(
                     editing.set(true);
                 },
                 if let Some(start) = time {
-                    {format_time(start)}
+                    {format_time(start, timezone)}
                     if let Some(true) = remove_button {
                         button {
                             class: "btn",
@@ -1,13 +1,47 @@
 use std::time::Duration;
 
-use crate::{race::TrackStats, time_utils::format_time_delta_secs};
+use crate::{
+    race::{TrackInfo, TrackStats},
+    time_utils::{format_time, format_time_delta_secs},
+};
 use chrono::Utc;
+use chrono_tz::Tz;
 use dioxus::prelude::*;
 
 use crate::components::{app::Action, time_input::TimeInput};
 
+/// Track attributes shown as a tooltip of the track name
+fn describe(info: &TrackInfo, timezone: Tz) -> String {
+    let mut parts = Vec::new();
+    if let Some(description) = info.description.as_ref().filter(|d| !d.is_empty()) {
+        parts.push(description.clone());
+    }
+    if let Some(distance) = info.distance {
+        parts.push(format!("{distance} m"));
+    }
+    if let Some(laps) = info.laps {
+        parts.push(format!("{laps} laps"));
+    }
+    if let Some(cut_off) = info.cut_off {
+        parts.push(format!("cut-off {}", format_time_delta_secs(Some(cut_off))));
+    }
+    if let Some(closing_time) = info.closing_time {
+        parts.push(format!(
+            "closes {}",
+            format_time(Some(closing_time), timezone)
+        ));
+    }
+    if let Some(scheduled_start) = info.scheduled_start {
+        parts.push(format!(
+            "scheduled start {}",
+            format_time(Some(scheduled_start), timezone)
+        ));
+    }
+    parts.join(", ")
+}
+
 #[component]
-pub fn Track(track: TrackStats) -> Element {
+pub fn Track(track: TrackStats, info: TrackInfo, timezone: Tz) -> Element {
     let mut editing = use_signal(|| false);
     let track2 = track.track.clone();
     let time_since_start = use_signal(|| None);
@@ -33,6 +67,7 @@ pub fn Track(track: TrackStats) -> Element {
             span {
                 class: "input-group-text justify-content-end",
                 style: "width: 150px",
+                title: describe(&info, timezone),
                 "{track.track}"
             }
             span {
@@ -47,6 +82,7 @@ pub fn Track(track: TrackStats) -> Element {
             }
             TimeInput {
                 time: track.start,
+                timezone,
                 editing,
                 span_class: "input-group-text flex-grow-1",
                 onsave: move |time_option| {
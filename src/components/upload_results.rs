@@ -1,20 +1,44 @@
+use std::{collections::HashSet, time::Duration};
+
+use chrono::Utc;
+use chrono_tz::Tz;
 use dioxus::prelude::*;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::{
-    race::{Race, Racer},
-    restclient::{RaceRestAPI, RacerResult},
+    components::app::SelectedRace,
+    config::{race_timezone, StorageBackend},
+    outbox::{Batch, Outbox, Payload},
+    race::{is_walk_in, Race, Racer},
+    race_events::{
+        Event, EventType, RaceEvents, RacerDnf, RacerFinish, RacerRegistered, TrackStart,
+        UploadedResult,
+    },
+    restclient::{self, RaceRestAPI, RacerResult},
+    storage::is_local_race,
+    time_utils::format_time,
 };
 
-enum SubmitState {
-    Idle,
-    Submitting,
-    Success,
-    Error,
-}
+/// How often the outbox is checked for batches to deliver
+const OUTBOX_INTERVAL: Duration = Duration::from_secs(1);
 
-fn racer_to_result(racer: &Racer) -> Option<RacerResult> {
-    match (racer.start, racer.finish) {
+fn racer_to_result(race: &Race, racer: &Racer) -> Option<RacerResult> {
+    if is_walk_in(racer.id) {
+        warn!(
+            "Skipping submit for {}, not registered on the server yet",
+            racer.start_number
+        );
+        return None;
+    }
+    if racer.finish.is_some() && !race.is_ranked(racer) {
+        info!(
+            "Skipping submit for {}, {}",
+            racer.start_number,
+            race.status(racer).unwrap_or("not ranked")
+        );
+        return None;
+    }
+    match (racer.start, racer.official_finish()) {
         (Some(start_time), Some(finish_time)) => Some(RacerResult {
             registration_id: racer.id,
             start_time,
@@ -38,46 +62,430 @@ fn racer_to_result(racer: &Racer) -> Option<RacerResult> {
     }
 }
 
-fn upload_results(race: &Race, mut state: Signal<SubmitState>, api: RaceRestAPI) {
-    let results: Vec<_> = race.racers.iter().filter_map(racer_to_result).collect();
+/// Finished walk-ins whose results wait for a registration that is not on its way
+fn held_back(race: &Race, outbox: &Outbox) -> Vec<String> {
+    race.racers
+        .iter()
+        .filter(|racer| is_walk_in(racer.id) && racer.finish.is_some())
+        .filter_map(|racer| {
+            let label = format!(
+                "{} {} {}",
+                racer.start_number, racer.first_name, racer.last_name
+            );
+            match outbox.registration(race.id, racer.id) {
+                Some(batch) if batch.done().is_none() => None,
+                Some(Batch {
+                    rejected: Some(_),
+                    last_error,
+                    ..
+                }) => Some(format!(
+                    "{label}: registration rejected, {}",
+                    last_error.as_deref().unwrap_or("no details")
+                )),
+                _ => Some(format!("{label}: not registered on the server")),
+            }
+        })
+        .collect()
+}
+
+fn upload_results(race: &Race, mut outbox: Signal<Outbox>) {
+    let results: Vec<_> = race
+        .racers
+        .iter()
+        .filter_map(|racer| racer_to_result(race, racer))
+        .collect();
     if results.is_empty() {
         error!("There are no results!");
-        state.set(SubmitState::Error);
     } else {
-        state.set(SubmitState::Submitting);
-        let id = race.id;
-        spawn(async move {
-            state.set(match api.results(id, results).await {
+        let id = outbox.write().push(race.id, Payload::Results { results });
+        info!("Results of race {} queued as batch {id}", race.id);
+    }
+}
+
+/// Change of the result of a racer, walk-ins are held back until the server registers them
+/// and results of DNF or out of time racers are removed
+fn live_result(race: &Race, racer: &Racer) -> Option<Payload> {
+    if is_walk_in(racer.id) {
+        return None;
+    }
+    Some(match (racer.start, racer.official_finish()) {
+        (Some(start_time), Some(finish_time)) if race.is_ranked(racer) => Payload::Upsert {
+            result: RacerResult {
+                registration_id: racer.id,
+                start_time,
+                finish_time,
+            },
+        },
+        _ => Payload::Delete {
+            registration_id: racer.id,
+        },
+    })
+}
+
+/// Results changed by the logged events
+fn live_results(race: &Race, events: &[Event]) -> Vec<Payload> {
+    let mut payloads = Vec::new();
+    for event in events {
+        match &event.event {
+            EventType::RacerFinish(RacerFinish { start_number, .. }) => payloads.extend(
+                race.racers
+                    .iter()
+                    .filter(|racer| racer.start_number == *start_number)
+                    .filter_map(|racer| live_result(race, racer)),
+            ),
+            EventType::RacerDnf(RacerDnf { start_number, .. }) => payloads.extend(
+                race.racers
+                    .iter()
+                    .filter(|racer| racer.start_number == *start_number && racer.finish.is_some())
+                    .filter_map(|racer| live_result(race, racer)),
+            ),
+            EventType::TrackStart(TrackStart { track, .. }) => payloads.extend(
+                race.racers
+                    .iter()
+                    .filter(|racer| racer.track == *track && racer.finish.is_some())
+                    .filter_map(|racer| live_result(race, racer)),
+            ),
+            // result of a walk-in held back until the server registered it
+            EventType::RacerRegistered(RacerRegistered {
+                racer,
+                replaces: Some(_),
+            }) => payloads.extend(
+                race.racers
+                    .iter()
+                    .filter(|r| r.id == racer.id && r.finish.is_some())
+                    .filter_map(|r| live_result(race, r)),
+            ),
+            _ => {}
+        }
+    }
+    payloads
+}
+
+/// Queue changed results of the race if live results are enabled for it
+pub fn push_live_results(race: &Race, mut outbox: Signal<Outbox>) {
+    let events = race.take_new_events();
+    if !race.config.live_results || is_local_race(race.id) {
+        return;
+    }
+
+    for payload in live_results(race, &events) {
+        info!("Queueing live {payload}");
+        outbox.write().push(race.id, payload);
+    }
+}
+
+fn log_upload(
+    batch: &Batch,
+    results: &[RacerResult],
+    selected_race: Signal<SelectedRace>,
+    storage: StorageBackend,
+) {
+    let uploaded = results
+        .iter()
+        .map(|result| UploadedResult {
+            registration_id: result.registration_id,
+            start: result.start_time,
+            finish: result.finish_time,
+        })
+        .collect();
+
+    match &*selected_race.peek() {
+        Some(Ok(race)) if race.id == batch.race_id => race.log_upload(uploaded),
+        _ => RaceEvents::load(batch.race_id, storage).log_upload(uploaded),
+    }
+}
+
+/// Replace the local registration by the one created by the server and send
+/// the results held back for the local id
+fn log_registration(
+    race_id: u32,
+    local_id: u32,
+    registered: restclient::Racer,
+    mut selected_race: Signal<SelectedRace>,
+    outbox: Signal<Outbox>,
+    storage: StorageBackend,
+) {
+    info!("Racer {local_id} registered as {}", registered.id);
+    match &mut *selected_race.write() {
+        Some(Ok(race)) if race.id == race_id => {
+            race.register(registered, Some(local_id));
+            push_live_results(race, outbox);
+        }
+        _ => RaceEvents::load(race_id, storage).log_registration(registered, Some(local_id)),
+    }
+}
+
+/// Deliver queued results until the application exits
+pub async fn deliver_results(
+    api: Option<RaceRestAPI>,
+    mut outbox: Signal<Outbox>,
+    selected_race: Signal<SelectedRace>,
+    storage: StorageBackend,
+    timezone: Tz,
+) {
+    let Some(api) = api else {
+        info!("Registration API is not configured, results stay in the outbox");
+        return;
+    };
+    loop {
+        let due = outbox.peek().due(Utc::now());
+        // races with a failed batch, their later batches wait for the retry
+        let mut failed = HashSet::new();
+        for batch in due {
+            if failed.contains(&batch.race_id) {
+                continue;
+            }
+            let tz = match &*selected_race.peek() {
+                Some(Ok(race)) if race.id == batch.race_id => race.timezone,
+                _ => race_timezone(batch.race_id, timezone),
+            };
+            let sent = match &batch.payload {
+                Payload::Results { results } => {
+                    api.results(batch.race_id, tz, results.clone()).await
+                }
+                Payload::Upsert { result } => api.upsert_result(batch.race_id, tz, result).await,
+                Payload::Delete { registration_id } => {
+                    api.delete_result(batch.race_id, *registration_id).await
+                }
+                Payload::Register { racer } => {
+                    api.register(batch.race_id, racer).await.map(|registered| {
+                        log_registration(
+                            batch.race_id,
+                            racer.id,
+                            registered,
+                            selected_race,
+                            outbox,
+                            storage,
+                        )
+                    })
+                }
+            };
+
+            match sent {
                 Ok(_) => {
-                    info!("Results succesfully uploaded");
-                    SubmitState::Success
+                    info!(
+                        "Batch {} with {} succesfully uploaded",
+                        batch.id, batch.payload
+                    );
+                    outbox.write().delivered(batch.id, Utc::now());
+                    if let Payload::Results { results } = &batch.payload {
+                        log_upload(&batch, results, selected_race, storage);
+                    }
+                }
+                Err(err) if !err.is_retryable() => {
+                    error!("Batch {} rejected: {err}", batch.id);
+                    outbox
+                        .write()
+                        .rejected(batch.id, err.to_string(), Utc::now());
                 }
                 Err(err) => {
-                    error!("Uploading of results failed: {err:?}");
-                    SubmitState::Error
+                    error!("Uploading of batch {} failed: {err}", batch.id);
+                    outbox.write().failed(batch.id, err.to_string(), Utc::now());
+                    // keep the order of the queued changes of the race
+                    failed.insert(batch.race_id);
                 }
-            })
-        });
+            }
+        }
+        tokio::time::sleep(OUTBOX_INTERVAL).await;
+    }
+}
+
+fn batch_status(batch: &Batch, tz: Tz) -> String {
+    match (batch.delivered, &batch.last_error) {
+        (Some(delivered), _) => format!("Delivered at {}", format_time(Some(delivered), tz)),
+        (None, Some(err)) if batch.rejected.is_some() => err.clone(),
+        (None, Some(err)) => format!(
+            "Attempt {} failed: {err}, retrying at {}",
+            batch.attempts,
+            format_time(Some(batch.next_attempt), tz)
+        ),
+        (None, None) => "Submitting".to_string(),
+    }
+}
+
+/// Bootstrap color of the batch status
+fn batch_color(batch: &Batch) -> &'static str {
+    match (batch.delivered, &batch.last_error) {
+        (Some(_), _) => "success",
+        (None, Some(_)) => "danger",
+        (None, None) => "warning",
     }
 }
 
 #[component]
 pub fn UploadResults(race: Race) -> Element {
-    let api = use_context::<RaceRestAPI>();
-    let upload_state = use_signal(|| SubmitState::Idle);
-
-    let classes = match *upload_state.read() {
-        SubmitState::Success => "btn-success",
-        SubmitState::Submitting => "btn-warning",
-        SubmitState::Error => "btn-danger",
-        _ => "",
-    };
+    let outbox = use_context::<Signal<Outbox>>();
+    let mut show_outbox = use_signal(|| false);
+
+    let last_batch = outbox.read().last_results(race.id).cloned();
+    let pending = outbox.read().pending().count();
+    let timezone = race.timezone;
+    let color = last_batch.as_ref().map(batch_color).unwrap_or("primary");
+    let last_error = last_batch
+        .as_ref()
+        .filter(|batch| batch.delivered.is_none())
+        .and_then(|batch| batch.last_error.clone());
+    let held_back = held_back(&race, &outbox.read());
 
     rsx! {
-        button {
-            class: ["btn btn-primary", classes].join(" "),
-            onclick: move |_evt| { upload_results(&race, upload_state, api.clone()) },
-            dangerous_inner_html: iconify::svg!("mdi:upload"),
+        div { class: "btn-group position-relative",
+            button {
+                class: "btn btn-{color}",
+                title: last_batch.as_ref().map(|batch| batch_status(batch, timezone)),
+                onclick: move |_evt| { upload_results(&race, outbox) },
+                dangerous_inner_html: iconify::svg!("mdi:upload"),
+            }
+            button {
+                class: "btn btn-outline-primary text-nowrap",
+                onclick: move |_| show_outbox.toggle(),
+                "{pending}"
+            }
+            if let Some(err) = last_error {
+                button {
+                    class: "btn btn-outline-danger text-truncate",
+                    style: "max-width: 400px",
+                    title: "{err}",
+                    onclick: move |_| show_outbox.toggle(),
+                    "{err}"
+                }
+            }
+            if !held_back.is_empty() {
+                button {
+                    class: "btn btn-outline-danger text-nowrap",
+                    title: held_back.join("\n"),
+                    onclick: move |_| show_outbox.toggle(),
+                    "{held_back.len()} results held back"
+                }
+            }
+            if show_outbox() {
+                div {
+                    class: "card position-absolute top-100 start-0 shadow",
+                    style: "z-index: 10; width: 600px",
+                    ul { class: "list-group list-group-flush",
+                        for problem in held_back.iter() {
+                            li { class: "list-group-item text-danger", "{problem}" }
+                        }
+                        for batch in outbox.read().batches().iter().rev() {
+                            li { class: "list-group-item d-flex column-gap-2",
+                                span { class: "badge text-bg-{batch_color(batch)}", "#{batch.id}" }
+                                span { "race {batch.race_id}, {batch.payload}" }
+                                span { class: "text-secondary", "{batch_status(batch, timezone)}" }
+                            }
+                        }
+                    }
+                }
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::config::{RaceConfig, TrackOverride};
+    use crate::race::{test_registration, Track, WALK_IN_ID_START};
+
+    #[test]
+    fn walk_in_held_back() {
+        let mut race = Race::for_test(
+            "finish",
+            RaceConfig::default(),
+            vec![test_registration(7, 1, "5 Km")],
+        );
+        race.register(test_registration(WALK_IN_ID_START, 2, "5 Km"), None);
+        race.take_new_events();
+
+        let start = Utc::now();
+        race.start(Track("5 Km".into()), start);
+        race.finish_start_number("1".parse().unwrap(), start + TimeDelta::minutes(20));
+        race.finish_start_number("2".parse().unwrap(), start + TimeDelta::minutes(21));
+
+        let results: Vec<u32> = race
+            .racers
+            .iter()
+            .filter_map(|racer| racer_to_result(&race, racer))
+            .map(|result| result.registration_id)
+            .collect();
+        assert_eq!(results, vec![7]);
+        let payloads = live_results(&race, &race.take_new_events());
+        assert!(!payloads.is_empty());
+        assert!(payloads.iter().all(
+            |payload| matches!(payload, Payload::Upsert { result } if result.registration_id == 7)
+        ));
+
+        // the walk-in is reported while the server refuses to register it
+        let mut outbox = Outbox::in_memory();
+        assert_eq!(
+            held_back(&race, &outbox),
+            vec!["2 Racer 2: not registered on the server"]
+        );
+        let id = outbox.push(
+            race.id,
+            Payload::Register {
+                racer: Box::new(test_registration(WALK_IN_ID_START, 2, "5 Km")),
+            },
+        );
+        assert!(held_back(&race, &outbox).is_empty());
+        outbox.rejected(id, "Rejected: tag taken".into(), Utc::now());
+        assert_eq!(
+            held_back(&race, &outbox),
+            vec!["2 Racer 2: registration rejected, Rejected: tag taken"]
+        );
+
+        // the result is sent once the server registered the walk-in
+        race.register(test_registration(42, 2, "5 Km"), Some(WALK_IN_ID_START));
+        let payloads = live_results(&race, &race.take_new_events());
+        assert_eq!(
+            payloads,
+            vec![Payload::Upsert {
+                result: RacerResult {
+                    registration_id: 42,
+                    start_time: start,
+                    finish_time: start + TimeDelta::minutes(21),
+                }
+            }]
+        );
+    }
+
+    #[test]
+    fn unranked_not_uploaded() {
+        let mut config = RaceConfig::default();
+        config.tracks.insert(
+            "5 Km".into(),
+            TrackOverride {
+                cut_off: Some(30),
+                ..Default::default()
+            },
+        );
+        let registrations = (1..=3)
+            .map(|id| test_registration(id, id, "5 Km"))
+            .collect();
+        let mut race = Race::for_test("finish", config, registrations);
+        let start = Utc::now();
+        race.start(Track("5 Km".into()), start);
+        race.finish_start_number("1".parse().unwrap(), start + TimeDelta::minutes(25));
+        race.finish_start_number("2".parse().unwrap(), start + TimeDelta::minutes(31));
+        race.finish_start_number("3".parse().unwrap(), start + TimeDelta::minutes(28));
+        race.take_new_events();
+        race.set_dnf("3".parse().unwrap(), true);
+
+        let results: Vec<u32> = race
+            .racers
+            .iter()
+            .filter_map(|racer| racer_to_result(&race, racer))
+            .map(|result| result.registration_id)
+            .collect();
+        assert_eq!(results, vec![1]);
+        assert_eq!(
+            live_results(&race, &race.take_new_events()),
+            vec![Payload::Delete { registration_id: 3 }]
+        );
+        let out_of_time = race.racers.iter().find(|racer| racer.id == 2).unwrap();
+        assert_eq!(
+            live_result(&race, out_of_time),
+            Some(Payload::Delete { registration_id: 2 })
+        );
+    }
+}
@@ -0,0 +1,31 @@
+use dioxus::prelude::*;
+
+use crate::validation::ValidationIssue;
+
+/// Problems found in the registrations of the loaded race
+#[component]
+pub fn ValidationReport(issues: Vec<ValidationIssue>, ondismiss: EventHandler) -> Element {
+    let errors = issues.iter().filter(|issue| issue.is_error()).count();
+    let warnings = issues.len() - errors;
+    let color = if errors > 0 { "danger" } else { "warning" };
+
+    rsx! {
+        div { class: "alert alert-{color} mb-1",
+            div { class: "d-flex align-items-center",
+                strong { class: "me-auto",
+                    "Registrations have {errors} errors and {warnings} warnings"
+                }
+                button {
+                    class: "btn-close",
+                    title: "Dismiss",
+                    onclick: move |_| ondismiss(()),
+                }
+            }
+            ul { class: "mb-0 overflow-auto", style: "max-height: 150px",
+                for issue in issues.iter() {
+                    li { class: if issue.is_error() { "text-danger" } else { "" }, "{issue}" }
+                }
+            }
+        }
+    }
+}
@@ -1,22 +1,179 @@
-use std::{env, fs, io::ErrorKind, path::PathBuf, process::exit};
+use std::{collections::HashMap, env, fs, io::ErrorKind, path::PathBuf, process::exit};
 
+use chrono_tz::Tz;
 use serde::Deserialize;
 use tracing::{error, info};
 
+use crate::awards::AwardRules;
+use crate::category_rules::CategoryRule;
+use crate::restclient::Auth;
+use crate::teams::TeamScoring;
+use crate::time_utils::Timing;
+
+/// How the registration API authenticates requests
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    /// username and token as basic auth password
+    #[default]
+    Basic,
+    /// `Authorization: Bearer <token>`
+    Bearer,
+    /// token in the `api_key_header` header
+    ApiKey,
+}
+
+fn default_api_key_header() -> String {
+    "X-API-Key".to_string()
+}
+
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct RestAPIConfig {
     pub url: String,
+    #[serde(default)]
+    pub auth: AuthMode,
+    #[serde(default)]
     pub username: String,
-    pub token: String,
+    /// token in plain text, `token_env` and `token_file` take precedence
+    pub token: Option<String>,
+    /// environment variable with the token
+    pub token_env: Option<String>,
+    /// file with the token
+    pub token_file: Option<String>,
+    #[serde(default = "default_api_key_header")]
+    pub api_key_header: String,
+}
+
+impl RestAPIConfig {
+    fn token(&self) -> Result<String, String> {
+        if let Some(var) = &self.token_env {
+            if let Ok(token) = env::var(var) {
+                return Ok(token);
+            }
+        }
+        if let Some(path) = &self.token_file {
+            let path = shellexpand::tilde(path).to_string();
+            return fs::read_to_string(&path)
+                .map(|token| token.trim().to_string())
+                .map_err(|err| format!("Failed to read token from {path:?}: {err}"));
+        }
+        self.token
+            .clone()
+            .ok_or_else(|| "API token is not configured".to_string())
+    }
+
+    pub fn auth(&self) -> Result<Auth, String> {
+        let token = self.token()?;
+        Ok(match self.auth {
+            AuthMode::Basic => Auth::Basic {
+                username: self.username.clone(),
+                password: token,
+            },
+            AuthMode::Bearer => Auth::Bearer(token),
+            AuthMode::ApiKey => Auth::ApiKey {
+                header: self.api_key_header.clone(),
+                key: token,
+            },
+        })
+    }
+}
+
+/// Where the race events are stored
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// `race_{id}.jsonl` file per race
+    #[default]
+    Jsonl,
+    /// all races in `rustpolnak.sqlite`
+    Sqlite,
+}
+
+/// Timezone of races without any configured timezone
+pub const DEFAULT_TIMEZONE: Tz = chrono_tz::Europe::Prague;
+
+fn default_timezone() -> Tz {
+    DEFAULT_TIMEZONE
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
-    pub api: RestAPIConfig,
+    /// races are only imported from files without the registration API
+    pub api: Option<RestAPIConfig>,
     pub rfid_devices: Vec<String>,
     pub results_path: String,
+    #[serde(default)]
+    pub storage: StorageBackend,
+    /// timezone of the races, e.g. `Europe/Prague`
+    #[serde(default = "default_timezone")]
+    pub timezone: Tz,
+    /// primary station to synchronize the race events with, e.g. `http://10.0.0.1:8080`
+    pub sync_url: Option<String>,
+}
+
+/// Settings of a single race, loaded from `race_{id}.toml` in the app directory
+#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct RaceConfig {
+    /// push every finish to the registration API as it happens
+    pub live_results: bool,
+    /// timezone of the race if it differs from the global one
+    pub timezone: Option<Tz>,
+    /// track attributes by track name, they take precedence over the registration API
+    pub tracks: HashMap<String, TrackOverride>,
+    /// category attributes by category name
+    pub categories: HashMap<String, CategoryOverride>,
+    /// categories assigned by the track, gender and age of the racers
+    pub category_rules: Vec<CategoryRule>,
+    /// team competition, none if not set
+    pub team_scoring: Option<TeamScoring>,
+    /// handicap start delays in seconds by start number, they take precedence over
+    /// the category offsets
+    pub start_offsets: HashMap<String, u32>,
+    /// precision and rounding of the official times
+    pub timing: Timing,
+    /// prizes of the overall and category winners
+    pub awards: AwardRules,
+}
+
+/// Track attributes set locally
+#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct TrackOverride {
+    /// length in meters
+    pub distance: Option<u32>,
+    pub laps: Option<u32>,
+    /// maximum running time in minutes
+    pub cut_off: Option<u32>,
+    /// wall clock time in the timezone of the race, e.g. `2025-10-05 10:30:00`
+    pub scheduled_start: Option<String>,
+    /// course closing time, wall clock like `scheduled_start`
+    pub closing_time: Option<String>,
+    pub sort_order: Option<i32>,
+}
+
+/// Category attributes set locally
+#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct CategoryOverride {
+    pub sort_order: Option<i32>,
+    /// handicap start delay after the track start in seconds
+    pub start_offset: Option<u32>,
+}
+
+/// Name of this station in the synchronization of the race events, the hostname
+/// unless `RUSTPOLNAK_STATION` is set. It has to be unique among the stations.
+pub fn station_id() -> String {
+    env::var("RUSTPOLNAK_STATION")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .or_else(|| env::var("HOSTNAME").ok())
+        .or_else(|| env::var("COMPUTERNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "station".to_string())
 }
 
 pub fn app_dir() -> PathBuf {
@@ -26,13 +183,13 @@ pub fn app_dir() -> PathBuf {
 }
 
 /// Config paths sorted by highest priority first
-fn config_paths() -> Vec<PathBuf> {
+fn config_paths(cli_path: Option<PathBuf>) -> Vec<PathBuf> {
     let mut paths: Vec<PathBuf> = Vec::new();
     let filename = "rustpolnak.toml";
 
     // 1. cli argument
-    if let Some(path) = env::args().nth(1) {
-        paths.push(path.into());
+    if let Some(path) = cli_path {
+        paths.push(path);
     }
 
     // 2. current directory
@@ -43,8 +200,40 @@ fn config_paths() -> Vec<PathBuf> {
     paths
 }
 
+/// Config of the race, the default without `race_{race_id}.toml`; an unreadable
+/// config is an error rather than the default settings
+pub fn load_race_config(race_id: u32) -> Result<RaceConfig, String> {
+    let path = app_dir().join(format!("race_{race_id}.toml"));
+    let result = match fs::read_to_string(&path) {
+        Ok(toml_str) => match toml::from_str::<RaceConfig>(&toml_str) {
+            Ok(config) => {
+                info!("Loaded race config {path:?}");
+                Ok(config)
+            }
+            Err(err) => Err(format!("Failed to parse {path:?}: {err}")),
+        },
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(RaceConfig::default()),
+        Err(err) => Err(format!("Failed to load {path:?}: {err}")),
+    };
+    result.inspect_err(|err| error!("{err}"))
+}
+
+/// Timezone of the race, `default` unless the race config sets its own
+pub fn race_timezone(race_id: u32, default: Tz) -> Tz {
+    load_race_config(race_id)
+        .ok()
+        .and_then(|config| config.timezone)
+        .unwrap_or(default)
+}
+
+/// Load the config, the first command line argument is the config path
 pub fn load_config() -> Config {
-    for path in config_paths() {
+    load_config_from(env::args().nth(1).map(PathBuf::from))
+}
+
+/// Load the config from `cli_path` if it exists, otherwise from the default paths
+pub fn load_config_from(cli_path: Option<PathBuf>) -> Config {
+    for path in config_paths(cli_path) {
         match fs::read_to_string(path.clone()) {
             Ok(toml_str) => match toml::from_str::<Config>(&toml_str) {
                 Ok(config) => {
@@ -69,3 +258,38 @@ pub fn load_config() -> Config {
     error!("No configuration file found!");
     exit(1);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_auth() {
+        let basic: RestAPIConfig =
+            toml::from_str("url = \"http://localhost\"\nusername = \"user\"\ntoken = \"secret\"")
+                .unwrap();
+        assert_eq!(
+            basic.auth(),
+            Ok(Auth::Basic {
+                username: "user".to_string(),
+                password: "secret".to_string()
+            })
+        );
+
+        let api_key: RestAPIConfig = toml::from_str(
+            "url = \"http://localhost\"\nauth = \"api_key\"\ntoken_env = \"RUSTPOLNAK_TEST_UNSET\"\ntoken = \"secret\"",
+        )
+        .unwrap();
+        assert_eq!(
+            api_key.auth(),
+            Ok(Auth::ApiKey {
+                header: "X-API-Key".to_string(),
+                key: "secret".to_string()
+            })
+        );
+
+        let missing: RestAPIConfig =
+            toml::from_str("url = \"http://localhost\"\nauth = \"bearer\"").unwrap();
+        assert!(missing.auth().is_err());
+    }
+}
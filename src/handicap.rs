@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::config::RaceConfig;
+use crate::race::{Category, Race, Racer, StartNumber, Track};
+
+/// Delay of the racer's start after the start of its track. A racer offset takes
+/// precedence, otherwise the largest offset of the racer's categories applies.
+pub fn start_offset(
+    config: &RaceConfig,
+    start_number: &StartNumber,
+    categories: &[Category],
+) -> TimeDelta {
+    let seconds = config
+        .start_offsets
+        .get(&start_number.to_string())
+        .copied()
+        .or_else(|| {
+            categories
+                .iter()
+                .filter_map(|category| config.categories.get(&category.0)?.start_offset)
+                .max()
+        })
+        .unwrap_or(0);
+    TimeDelta::seconds(seconds.into())
+}
+
+/// Whether any racer starts later than its track
+pub fn has_start_offsets(config: &RaceConfig) -> bool {
+    !config.start_offsets.is_empty()
+        || config
+            .categories
+            .values()
+            .any(|category| category.start_offset.is_some_and(|offset| offset > 0))
+}
+
+/// Racers starting together in a pursuit
+#[derive(Debug, Clone, PartialEq)]
+pub struct StartWave {
+    pub offset: TimeDelta,
+    /// wall clock start once the track has started or has a scheduled start
+    pub start: Option<DateTime<Utc>>,
+    pub racers: Vec<Racer>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackStartList {
+    pub track: Track,
+    pub waves: Vec<StartWave>,
+}
+
+/// Pursuit start list of every track, the racers grouped by their start offset
+pub fn start_list(race: &Race) -> Vec<TrackStartList> {
+    race.tracks
+        .iter()
+        .map(|track| {
+            let track_start = race.track_start(track).or_else(|| {
+                race.track_info
+                    .get(track)
+                    .and_then(|info| info.scheduled_start)
+            });
+            let mut waves: BTreeMap<TimeDelta, Vec<Racer>> = BTreeMap::new();
+            for racer in race.racers.iter().filter(|racer| racer.track == *track) {
+                let offset = start_offset(&race.config, &racer.start_number, &racer.categories);
+                waves.entry(offset).or_default().push(racer.clone());
+            }
+            TrackStartList {
+                track: track.clone(),
+                waves: waves
+                    .into_iter()
+                    .map(|(offset, mut racers)| {
+                        racers.sort_by(|a, b| a.start_number.cmp(&b.start_number));
+                        StartWave {
+                            offset,
+                            start: track_start.map(|start| start + offset),
+                            racers,
+                        }
+                    })
+                    .collect(),
+            }
+        })
+        .filter(|start_list| !start_list.waves.is_empty())
+        .collect()
+}
+
+/// Finishers of a track ordered by their actual running time, fastest first
+pub fn by_running_time<'a>(race: &'a Race, track: &Track) -> Vec<&'a Racer> {
+    let mut finished = race.get_finished_racers_by_track(track);
+    finished.sort_by(|a, b| {
+        a.time
+            .cmp(&b.time)
+            .then(a.start_number.cmp(&b.start_number))
+    });
+    finished
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CategoryOverride;
+
+    #[test]
+    fn offsets() {
+        let config: RaceConfig = toml::from_str(
+            r#"
+            [categories.Kids]
+            start_offset = 0
+            [categories.Women]
+            start_offset = 90
+            [categories.Men]
+            start_offset = 180
+            [start_offsets]
+            7 = 30
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.categories["Women"],
+            CategoryOverride {
+                start_offset: Some(90),
+                ..Default::default()
+            }
+        );
+        assert!(has_start_offsets(&config));
+        assert!(!has_start_offsets(&RaceConfig::default()));
+
+        let category = |name: &str| Category(name.into());
+        let number = |number: &str| number.parse::<StartNumber>().unwrap();
+        assert_eq!(
+            start_offset(&config, &number("1"), &[category("Men")]),
+            TimeDelta::seconds(180)
+        );
+        assert_eq!(
+            start_offset(&config, &number("2"), &[category("Women"), category("Men")]),
+            TimeDelta::seconds(180)
+        );
+        assert_eq!(
+            start_offset(&config, &number("7"), &[category("Men")]),
+            TimeDelta::seconds(30)
+        );
+        assert_eq!(
+            start_offset(&config, &number("3"), &[category("Juniors")]),
+            TimeDelta::zero()
+        );
+    }
+}
@@ -1,4 +1,20 @@
 #![allow(dead_code)]
 
+pub mod age_grading;
+pub mod awards;
+pub mod category_rules;
+pub mod config;
+pub mod handicap;
+pub mod outbox;
+pub mod race;
+pub mod race_events;
+pub mod registrations;
 pub mod restclient;
 pub mod rfid_reader;
+pub mod series;
+pub mod server;
+pub mod storage;
+pub mod sync;
+pub mod teams;
+pub mod time_utils;
+pub mod validation;
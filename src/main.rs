@@ -1,20 +1,33 @@
 #![allow(dead_code)]
 
+use std::process::exit;
+
 use dioxus::desktop::{Config, WindowBuilder};
 use dioxus::prelude::*;
-use tracing::Level;
+use tracing::{error, Level};
 
 use crate::components::app::App;
 use crate::restclient::RaceRestAPI;
 
+mod age_grading;
+mod awards;
+mod category_rules;
 mod components;
 mod config;
+mod handicap;
+mod outbox;
 mod printer;
 mod race;
 mod race_events;
+mod registrations;
 mod restclient;
 mod rfid_reader;
+mod series;
+mod storage;
+mod sync;
+mod teams;
 mod time_utils;
+mod validation;
 
 const MAIN_CSS: Asset = asset!("/assets/main.css");
 const BOOTSTRAP_CSS: Asset = asset!("/assets/bootstrap.css");
@@ -43,7 +56,13 @@ fn appconfig() -> Config {
 fn main() {
     dioxus_logger::init(Level::INFO).expect("logger failed to init");
     let config = config::load_config();
-    let restapi = RaceRestAPI::new(&config.api.url, &config.api.username, &config.api.token);
+    let restapi: Option<RaceRestAPI> = config.api.as_ref().map(|api| {
+        let auth = api.auth().unwrap_or_else(|err| {
+            error!("{err}");
+            exit(1);
+        });
+        RaceRestAPI::new(&api.url, auth)
+    });
 
     LaunchBuilder::new()
         .with_cfg(appconfig())
@@ -0,0 +1,299 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::config::app_dir;
+use crate::restclient::{Racer, RacerResult};
+
+/// Delay before the first retry, doubled with every failed attempt
+const RETRY_DELAY_SECS: i64 = 5;
+const MAX_RETRY_DELAY_SECS: i64 = 300;
+/// Delivered and rejected batches are kept for this long to show their status
+const KEEP_DELIVERED_HOURS: i64 = 24;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Payload {
+    /// all results of the race at once
+    Results { results: Vec<RacerResult> },
+    /// result of a single racer
+    Upsert { result: RacerResult },
+    /// remove the result of a single racer
+    Delete { registration_id: u32 },
+    /// racer registered on the spot
+    Register { racer: Box<Racer> },
+}
+
+impl fmt::Display for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Payload::Results { results } => write!(f, "{} results", results.len()),
+            Payload::Upsert { result } => write!(f, "result of {}", result.registration_id),
+            Payload::Delete { registration_id } => {
+                write!(f, "removal of {registration_id}")
+            }
+            Payload::Register { racer } => {
+                write!(
+                    f,
+                    "registration of {} {}",
+                    racer.first_name, racer.last_name
+                )
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Batch {
+    pub id: u64,
+    pub race_id: u32,
+    pub created: DateTime<Utc>,
+    pub payload: Payload,
+    pub attempts: u32,
+    pub next_attempt: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub delivered: Option<DateTime<Utc>>,
+    /// the server refused the batch, it is not retried
+    #[serde(default)]
+    pub rejected: Option<DateTime<Utc>>,
+}
+
+impl Batch {
+    /// Time the batch left the queue, either delivered or rejected
+    pub fn done(&self) -> Option<DateTime<Utc>> {
+        self.delivered.or(self.rejected)
+    }
+}
+
+/// Durable queue of results waiting for upload, persisted in `outbox.json`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outbox {
+    path: PathBuf,
+    batches: Vec<Batch>,
+}
+
+pub fn retry_delay(attempts: u32) -> TimeDelta {
+    let delay = RETRY_DELAY_SECS.saturating_mul(1 << attempts.saturating_sub(1).min(16));
+    TimeDelta::seconds(delay.min(MAX_RETRY_DELAY_SECS))
+}
+
+impl Outbox {
+    /// Outbox that is not saved anywhere
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        Outbox {
+            path: PathBuf::new(),
+            batches: Vec::new(),
+        }
+    }
+
+    pub fn load() -> Self {
+        Outbox::load_from(app_dir().join("outbox.json"))
+    }
+
+    fn load_from(path: PathBuf) -> Self {
+        let batches: Vec<Batch> = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|err| {
+                // keep the unreadable file, the pending results may still be recovered
+                let corrupt = path.with_extension("json.corrupt");
+                error!("Failed to load outbox, moving it to {corrupt:?}: {err}");
+                if let Err(err) = fs::rename(&path, &corrupt) {
+                    error!("Failed to move outbox to {corrupt:?}: {err}");
+                }
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+
+        let mut outbox = Outbox { path, batches };
+        let keep_since = Utc::now() - TimeDelta::hours(KEEP_DELIVERED_HOURS);
+        outbox
+            .batches
+            .retain(|batch| batch.done().is_none_or(|done| done > keep_since));
+        outbox
+    }
+
+    /// Replace `outbox.json` at once, a crash while saving leaves the previous file
+    fn save(&self) {
+        if self.path.as_os_str().is_empty() {
+            return;
+        }
+        let json = serde_json::to_string_pretty(&self.batches).unwrap();
+        let tmp = self.path.with_extension("json.tmp");
+        let saved = File::create(&tmp)
+            .and_then(|mut file| {
+                file.write_all(json.as_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp, &self.path));
+        if let Err(err) = saved {
+            error!("Failed to save outbox: {err}");
+        }
+    }
+
+    pub fn batches(&self) -> &[Batch] {
+        &self.batches
+    }
+
+    pub fn pending(&self) -> impl Iterator<Item = &Batch> {
+        self.batches.iter().filter(|batch| batch.done().is_none())
+    }
+
+    /// The most recently created batch with all results of the race
+    pub fn last_results(&self, race_id: u32) -> Option<&Batch> {
+        self.batches
+            .iter()
+            .filter(|batch| batch.race_id == race_id)
+            .filter(|batch| matches!(batch.payload, Payload::Results { .. }))
+            .max_by_key(|batch| batch.id)
+    }
+
+    /// The most recent registration of a racer registered on the spot
+    pub fn registration(&self, race_id: u32, local_id: u32) -> Option<&Batch> {
+        self.batches
+            .iter()
+            .filter(|batch| batch.race_id == race_id)
+            .filter(|batch| matches!(&batch.payload, Payload::Register { racer } if racer.id == local_id))
+            .max_by_key(|batch| batch.id)
+    }
+
+    pub fn push(&mut self, race_id: u32, payload: Payload) -> u64 {
+        let now = Utc::now();
+        let id = self.batches.iter().map(|b| b.id + 1).max().unwrap_or(1);
+        self.batches.push(Batch {
+            id,
+            race_id,
+            created: now,
+            payload,
+            attempts: 0,
+            next_attempt: now,
+            last_error: None,
+            delivered: None,
+            rejected: None,
+        });
+        self.save();
+        id
+    }
+
+    /// Pending batches whose retry time has come. Batches of a race are delivered in the
+    /// order they were queued, so nothing of the race is returned after its batch still
+    /// waiting for retry. Other races are not held up.
+    pub fn due(&self, now: DateTime<Utc>) -> Vec<Batch> {
+        let mut waiting = HashSet::new();
+        self.pending()
+            .filter(|batch| {
+                if batch.next_attempt > now {
+                    waiting.insert(batch.race_id);
+                }
+                !waiting.contains(&batch.race_id)
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn delivered(&mut self, id: u64, now: DateTime<Utc>) {
+        if let Some(batch) = self.batches.iter_mut().find(|b| b.id == id) {
+            batch.attempts += 1;
+            batch.delivered = Some(now);
+            batch.last_error = None;
+            self.save();
+        }
+    }
+
+    pub fn failed(&mut self, id: u64, error: String, now: DateTime<Utc>) {
+        if let Some(batch) = self.batches.iter_mut().find(|b| b.id == id) {
+            batch.attempts += 1;
+            batch.next_attempt = now + retry_delay(batch.attempts);
+            batch.last_error = Some(error);
+            self.save();
+        }
+    }
+
+    pub fn rejected(&mut self, id: u64, error: String, now: DateTime<Utc>) {
+        if let Some(batch) = self.batches.iter_mut().find(|b| b.id == id) {
+            batch.attempts += 1;
+            batch.rejected = Some(now);
+            batch.last_error = Some(error);
+            self.save();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        assert_eq!(retry_delay(1), TimeDelta::seconds(5));
+        assert_eq!(retry_delay(2), TimeDelta::seconds(10));
+        assert_eq!(retry_delay(4), TimeDelta::seconds(40));
+        assert_eq!(retry_delay(7), TimeDelta::seconds(300));
+        assert_eq!(retry_delay(100), TimeDelta::seconds(300));
+    }
+
+    #[test]
+    fn saved_file() {
+        let dir = std::env::temp_dir().join(format!("rustpolnak-outbox-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("outbox.json");
+        let _ = fs::remove_file(&path);
+
+        let mut outbox = Outbox::load_from(path.clone());
+        outbox.push(1, Payload::Delete { registration_id: 7 });
+        assert_eq!(Outbox::load_from(path.clone()).batches, outbox.batches);
+        assert!(!path.with_extension("json.tmp").exists());
+
+        // e.g. a crash of an older version while writing
+        fs::write(&path, "[{\"id\": 1, \"race_").unwrap();
+        assert!(Outbox::load_from(path.clone()).batches.is_empty());
+        assert!(!path.exists());
+        let corrupt = fs::read_to_string(path.with_extension("json.corrupt")).unwrap();
+        assert!(corrupt.starts_with("[{"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn due_in_order() {
+        let now = Utc::now();
+        let batch = |id, next_attempt| Batch {
+            id,
+            race_id: 1,
+            created: now,
+            payload: Payload::Delete {
+                registration_id: id as u32,
+            },
+            attempts: 0,
+            next_attempt,
+            last_error: None,
+            delivered: None,
+            rejected: None,
+        };
+        let mut delivered = batch(1, now);
+        delivered.delivered = Some(now);
+        let mut rejected = batch(5, now);
+        rejected.rejected = Some(now);
+        let mut other_race = batch(6, now);
+        other_race.race_id = 2;
+        let outbox = Outbox {
+            path: PathBuf::new(),
+            batches: vec![
+                delivered,
+                rejected,
+                batch(2, now),
+                batch(3, now + TimeDelta::seconds(10)),
+                batch(4, now),
+                other_race,
+            ],
+        };
+
+        let due: Vec<u64> = outbox.due(now).iter().map(|b| b.id).collect();
+        assert_eq!(due, vec![2, 6]);
+    }
+}
@@ -1,5 +1,7 @@
-use crate::race::{Category, Race, Racer};
-use crate::time_utils::format_time_delta_millis;
+use crate::handicap::{by_running_time, has_start_offsets};
+use crate::race::{Category, Race, Racer, Track};
+use crate::teams::team_standings;
+use crate::time_utils::{format_date, format_time, format_time_delta_secs};
 use std::path::PathBuf;
 use tracing::{error, info};
 
@@ -29,7 +31,10 @@ pub fn print_result(race: &Race, output_file: PathBuf) -> Result<(), Box<dyn std
 
     print_heading(&mut doc, "Race results");
     print_tracks(&mut doc, race);
+    print_running_times(&mut doc, race);
+    print_age_graded(&mut doc, race);
     print_categories(&mut doc, race);
+    print_teams(&mut doc, race);
     print_winners(&mut doc, race);
 
     match doc.render_to_file(&output_file) {
@@ -76,6 +81,11 @@ fn print_table_header(table: &mut elements::TableLayout) {
                 .styled(style::Effect::Bold)
                 .padded(1),
         )
+        .element(
+            elements::Paragraph::new("Pace")
+                .styled(style::Effect::Bold)
+                .padded(1),
+        )
         .element(
             elements::Paragraph::new("Rank")
                 .styled(style::Effect::Bold)
@@ -85,6 +95,16 @@ fn print_table_header(table: &mut elements::TableLayout) {
         .expect("Invalid table row");
 }
 
+/// Running time per kilometer, empty for tracks without a distance
+fn pace(race: &Race, racer: &Racer) -> String {
+    let pace = race
+        .track_info
+        .get(&racer.track)
+        .zip(racer.time)
+        .and_then(|(info, time)| info.pace(time));
+    format_time_delta_secs(pace)
+}
+
 fn print_tracks(doc: &mut Document, race: &Race) {
     for track in &race.tracks {
         let mut finished = race.get_finished_racers_by_track(track);
@@ -93,13 +113,23 @@ fn print_tracks(doc: &mut Document, race: &Race) {
             continue;
         }
 
-        finished.sort_by(|a, b| a.track_rank.cmp(&b.track_rank));
+        finished.sort_by_key(|racer| racer.track_rank);
 
         doc.push(elements::PageBreak::new());
 
-        let mut table = elements::TableLayout::new(vec![1, 2, 2, 2, 1]);
+        let mut table = elements::TableLayout::new(vec![1, 2, 2, 2, 1, 1]);
 
         print_heading(doc, format!("Track: {}", track.0).as_str());
+        if let Some(start) = race.track_start(track) {
+            doc.push(
+                elements::Paragraph::new(format!(
+                    "Start: {} {}",
+                    format_date(start, race.timezone),
+                    format_time(Some(start), race.timezone)
+                ))
+                .padded(1),
+            );
+        }
         print_table_header(&mut table);
 
         for racer in finished {
@@ -108,40 +138,74 @@ fn print_tracks(doc: &mut Document, race: &Race) {
                 .element(elements::Paragraph::new(format!("{}", racer.start_number)).padded(1))
                 .element(elements::Paragraph::new(&racer.first_name).padded(1))
                 .element(elements::Paragraph::new(&racer.last_name).padded(1))
-                .element(elements::Paragraph::new(format_time_delta_millis(racer.time)).padded(1))
+                .element(elements::Paragraph::new(race.config.timing.format(racer.time)).padded(1))
+                .element(elements::Paragraph::new(pace(race, racer)).padded(1))
                 .element(
                     elements::Paragraph::new(format!("{}", racer.track_rank.unwrap_or_default()))
                         .padded(1),
                 )
                 .push()
                 .expect("Invalid table row");
+            print_leg_splits(&mut table, race, racer);
         }
 
         doc.push(table);
+        print_unranked(doc, race, track);
     }
 }
 
-fn print_category_table(
-    doc: &mut Document,
-    category: &Category,
-    racers: Vec<&Racer>,
-    limit: Option<usize>,
-) {
+/// Racers out of time or confirmed as not finished, listed under the track results
+fn print_unranked(doc: &mut Document, race: &Race, track: &Track) {
+    for racer in race
+        .racers
+        .iter()
+        .filter(|racer| racer.track == *track && racer.start.is_some())
+    {
+        let Some(status) = race.status(racer) else {
+            continue;
+        };
+        doc.push(
+            elements::Paragraph::new(format!(
+                "{} {} {} {} {}",
+                racer.start_number,
+                racer.first_name,
+                racer.last_name,
+                race.config.timing.format(racer.time),
+                status
+            ))
+            .padded(1),
+        );
+    }
+}
+
+/// Rows with the legs of a relay team under its result
+fn print_leg_splits(table: &mut elements::TableLayout, race: &Race, racer: &Racer) {
+    for (index, (leg, split)) in racer.legs.iter().zip(racer.leg_splits()).enumerate() {
+        table
+            .row()
+            .element(elements::Paragraph::new(format!("{}.", index + 1)).padded(1))
+            .element(elements::Paragraph::new(&leg.name).padded(1))
+            .element(elements::Paragraph::new("").padded(1))
+            .element(elements::Paragraph::new(race.config.timing.format(split)).padded(1))
+            .element(elements::Paragraph::new("").padded(1))
+            .element(elements::Paragraph::new("").padded(1))
+            .push()
+            .expect("Invalid table row");
+    }
+}
+
+fn print_category_table(doc: &mut Document, race: &Race, category: &Category, racers: Vec<&Racer>) {
     let mut finished = racers;
 
     if finished.is_empty() {
         return;
     }
 
-    finished.sort_by(|a, b| a.categories_rank[category].cmp(&b.categories_rank[category]));
+    finished.sort_by_key(|r| r.categories_rank.get(category).copied().unwrap_or(u32::MAX));
 
-    if let Some(limit) = limit {
-        finished.truncate(limit);
-    } else {
-        doc.push(elements::PageBreak::new());
-    }
+    doc.push(elements::PageBreak::new());
 
-    let mut table = elements::TableLayout::new(vec![1, 2, 2, 2, 1]);
+    let mut table = elements::TableLayout::new(vec![1, 2, 2, 2, 1, 1]);
 
     print_heading(doc, format!("Category: {}", category.0).as_str());
     print_table_header(&mut table);
@@ -152,22 +216,183 @@ fn print_category_table(
             .element(elements::Paragraph::new(format!("{}", racer.start_number)).padded(1))
             .element(elements::Paragraph::new(&racer.first_name).padded(1))
             .element(elements::Paragraph::new(&racer.last_name).padded(1))
-            .element(elements::Paragraph::new(format_time_delta_millis(racer.time)).padded(1))
+            .element(elements::Paragraph::new(race.config.timing.format(racer.time)).padded(1))
+            .element(elements::Paragraph::new(pace(race, racer)).padded(1))
             .element(
-                elements::Paragraph::new(format!("{}", racer.categories_rank[category])).padded(1),
+                elements::Paragraph::new(format!(
+                    "{}",
+                    racer
+                        .categories_rank
+                        .get(category)
+                        .copied()
+                        .unwrap_or_default()
+                ))
+                .padded(1),
             )
             .push()
             .expect("Invalid table row");
+        print_leg_splits(&mut table, race, racer);
     }
 
     doc.push(table);
 }
 
+/// Handicap races are won by the finish order, the actual running times are listed apart
+fn print_running_times(doc: &mut Document, race: &Race) {
+    if !has_start_offsets(&race.config) {
+        return;
+    }
+
+    for track in &race.tracks {
+        let finished = by_running_time(race, track);
+        if finished.is_empty() {
+            continue;
+        }
+
+        doc.push(elements::PageBreak::new());
+        print_heading(doc, format!("Running times: {}", track.0).as_str());
+
+        let mut table = elements::TableLayout::new(vec![1, 2, 2, 2, 1, 1]);
+        print_table_header(&mut table);
+        for (index, racer) in finished.into_iter().enumerate() {
+            table
+                .row()
+                .element(elements::Paragraph::new(format!("{}", racer.start_number)).padded(1))
+                .element(elements::Paragraph::new(&racer.first_name).padded(1))
+                .element(elements::Paragraph::new(&racer.last_name).padded(1))
+                .element(elements::Paragraph::new(race.config.timing.format(racer.time)).padded(1))
+                .element(elements::Paragraph::new(pace(race, racer)).padded(1))
+                .element(elements::Paragraph::new(format!("{}", index + 1)).padded(1))
+                .push()
+                .expect("Invalid table row");
+        }
+
+        doc.push(table);
+    }
+}
+
+fn print_age_graded(doc: &mut Document, race: &Race) {
+    for track in &race.tracks {
+        let results = race.get_age_graded_racers_by_track(track);
+        if results.is_empty() {
+            continue;
+        }
+
+        doc.push(elements::PageBreak::new());
+        print_heading(doc, format!("Age graded: {}", track.0).as_str());
+
+        let mut table = elements::TableLayout::new(vec![1, 1, 4, 1, 2, 2, 1]);
+        table.set_cell_decorator(elements::FrameCellDecorator::new(true, true, false));
+        let mut header = table.row();
+        for title in [
+            "Rank",
+            "Start number",
+            "Name",
+            "Age",
+            "Time",
+            "Age graded",
+            "%",
+        ] {
+            header.push_element(
+                elements::Paragraph::new(title)
+                    .styled(style::Effect::Bold)
+                    .padded(1),
+            );
+        }
+        header.push().expect("Invalid table row");
+
+        for result in results {
+            let racer = &result.racer;
+            table
+                .row()
+                .element(elements::Paragraph::new(format!("{}", result.rank)).padded(1))
+                .element(elements::Paragraph::new(format!("{}", racer.start_number)).padded(1))
+                .element(
+                    elements::Paragraph::new(format!("{} {}", racer.first_name, racer.last_name))
+                        .padded(1),
+                )
+                .element(elements::Paragraph::new(format!("{}", result.age)).padded(1))
+                .element(elements::Paragraph::new(race.config.timing.format(racer.time)).padded(1))
+                .element(
+                    elements::Paragraph::new(race.config.timing.format(Some(result.graded_time)))
+                        .padded(1),
+                )
+                .element(elements::Paragraph::new(format!("{:.2}", result.percent)).padded(1))
+                .push()
+                .expect("Invalid table row");
+        }
+
+        doc.push(table);
+    }
+}
+
 fn print_categories(doc: &mut Document, race: &Race) {
     for category in &race.categories {
         let finished = race.get_finished_racers_by_category(category);
 
-        print_category_table(doc, category, finished, None);
+        print_category_table(doc, race, category, finished);
+    }
+}
+
+fn print_teams(doc: &mut Document, race: &Race) {
+    let Some(scoring) = &race.config.team_scoring else {
+        return;
+    };
+
+    for track_standings in team_standings(race, scoring) {
+        doc.push(elements::PageBreak::new());
+        print_heading(doc, format!("Teams: {}", track_standings.track.0).as_str());
+
+        let mut table = elements::TableLayout::new(vec![1, 3, 5, 2]);
+        table.set_cell_decorator(elements::FrameCellDecorator::new(true, true, false));
+        table
+            .row()
+            .element(
+                elements::Paragraph::new("Rank")
+                    .styled(style::Effect::Bold)
+                    .padded(1),
+            )
+            .element(
+                elements::Paragraph::new("Team")
+                    .styled(style::Effect::Bold)
+                    .padded(1),
+            )
+            .element(
+                elements::Paragraph::new("Racers")
+                    .styled(style::Effect::Bold)
+                    .padded(1),
+            )
+            .element(
+                elements::Paragraph::new("Score")
+                    .styled(style::Effect::Bold)
+                    .padded(1),
+            )
+            .push()
+            .expect("Invalid table row");
+
+        for standing in track_standings.standings {
+            let mut members = elements::LinearLayout::vertical();
+            for member in &standing.members {
+                members.push(elements::Paragraph::new(format!(
+                    "{}. {} {}",
+                    member.track_rank.unwrap_or_default(),
+                    member.name,
+                    member.score.format(&race.config.timing)
+                )));
+            }
+            table
+                .row()
+                .element(elements::Paragraph::new(format!("{}", standing.rank)).padded(1))
+                .element(elements::Paragraph::new(&standing.team).padded(1))
+                .element(members.padded(1))
+                .element(
+                    elements::Paragraph::new(standing.score.format(&race.config.timing)).padded(1),
+                )
+                .push()
+                .expect("Invalid table row");
+        }
+
+        doc.push(table);
     }
 }
 
@@ -175,9 +400,32 @@ fn print_winners(doc: &mut Document, race: &Race) {
     doc.push(elements::PageBreak::new());
     print_heading(doc, "Winners");
 
-    for category in &race.categories {
-        let finished = race.get_finished_racers_by_category(category);
+    for list in race.config.awards.awards(race) {
+        let mut table = elements::TableLayout::new(vec![1, 2, 2, 2, 1, 1]);
+
+        print_heading(doc, &list.title);
+        print_table_header(&mut table);
+
+        for award in list.awards {
+            let racer = &award.racer;
+            // the place of the prize, with the rank when better racers were excluded
+            let place = if award.place == award.rank {
+                format!("{}", award.place)
+            } else {
+                format!("{} ({})", award.place, award.rank)
+            };
+            table
+                .row()
+                .element(elements::Paragraph::new(format!("{}", racer.start_number)).padded(1))
+                .element(elements::Paragraph::new(&racer.first_name).padded(1))
+                .element(elements::Paragraph::new(&racer.last_name).padded(1))
+                .element(elements::Paragraph::new(race.config.timing.format(racer.time)).padded(1))
+                .element(elements::Paragraph::new(pace(race, racer)).padded(1))
+                .element(elements::Paragraph::new(place).padded(1))
+                .push()
+                .expect("Invalid table row");
+        }
 
-        print_category_table(doc, category, finished, Some(3));
+        doc.push(table);
     }
 }
@@ -1,20 +1,51 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use chrono::{Duration, TimeDelta};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
 use std::cmp::Ordering;
-use std::collections::HashMap;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::num::ParseIntError;
-use std::rc::Rc;
 use std::str::FromStr;
-use tracing::{error, info};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::{error, info, warn};
 
-use crate::race_events::RaceEvents;
-use crate::restclient::RaceRestAPI;
+use crate::age_grading::{load_age_grading, AgeGradedResult, AgeGrading};
+use crate::category_rules::{assign, RacerProfile};
+use crate::config::{
+    load_race_config, CategoryOverride, RaceConfig, StorageBackend, TrackOverride,
+};
+use crate::handicap::start_offset;
+use crate::race_events::{Event, RaceEvents, RaceState, RacerOverride, UploadedResult};
+use crate::registrations::{stored_date_of_event, RegistrationSource};
+use crate::restclient::{parse_dt, Gender};
+use crate::storage::RegistrationsSnapshot;
+use crate::time_utils::Timing;
 
-#[derive(Hash, Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+/// Racers registered on the spot get ids from this value up until the server accepts them
+pub const WALK_IN_ID_START: u32 = 3_000_000_000;
+
+/// Walk-in ids of a single station, so stations registering racers between
+/// synchronizations never pick the same id
+const WALK_IN_IDS_PER_STATION: u32 = 1_000_000;
+const WALK_IN_STATIONS: u32 = 1_000;
+
+/// First walk-in id of the station, two stations may share one, the primary refuses
+/// to synchronize such stations
+pub fn walk_in_id_start(station: &str) -> u32 {
+    // FNV-1a, unlike the std hasher it is the same in every build
+    let hash = station.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    });
+    WALK_IN_ID_START + hash % WALK_IN_STATIONS * WALK_IN_IDS_PER_STATION
+}
+
+/// Registration id not known to the registration API yet
+pub fn is_walk_in(registration_id: u32) -> bool {
+    registration_id >= WALK_IN_ID_START
+}
+
+#[derive(Hash, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Deserialize, Serialize)]
 pub struct StartNumber(u32);
 
 impl fmt::Display for StartNumber {
@@ -23,6 +54,13 @@ impl fmt::Display for StartNumber {
     }
 }
 
+impl StartNumber {
+    /// Registrations without a start number get start number 0
+    pub fn is_missing(&self) -> bool {
+        self.0 == 0
+    }
+}
+
 impl FromStr for StartNumber {
     type Err = ParseIntError;
 
@@ -49,6 +87,106 @@ impl fmt::Display for Track {
     }
 }
 
+/// Track attributes from the registrations and the race config
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct TrackInfo {
+    /// track id in the registration API
+    pub id: u32,
+    pub description: Option<String>,
+    /// length in meters
+    pub distance: Option<u32>,
+    pub laps: Option<u32>,
+    /// maximum running time
+    pub cut_off: Option<TimeDelta>,
+    pub scheduled_start: Option<DateTime<Utc>>,
+    /// course closing time, no finish counts after it
+    pub closing_time: Option<DateTime<Utc>>,
+    pub sort_order: Option<i32>,
+}
+
+impl TrackInfo {
+    fn new(
+        track: &crate::restclient::Track,
+        track_override: Option<&TrackOverride>,
+        tz: Tz,
+    ) -> Self {
+        let track_override = track_override.cloned().unwrap_or_default();
+        let parse = |time: Option<String>, what: &str| {
+            time.and_then(|time| match parse_dt(&time, tz) {
+                Ok(time) => Some(time),
+                Err(err) => {
+                    error!("Invalid {what} of track {}: {err}", track.name);
+                    None
+                }
+            })
+        };
+        let scheduled_start = parse(
+            track_override
+                .scheduled_start
+                .or(track.scheduled_start.clone()),
+            "scheduled start",
+        );
+        let closing_time = parse(track_override.closing_time, "closing time");
+        TrackInfo {
+            id: track.id,
+            description: track.description.clone(),
+            distance: track_override.distance.or(track.distance),
+            laps: track_override.laps.or(track.laps),
+            cut_off: track_override
+                .cut_off
+                .or(track.cut_off)
+                .map(|minutes| TimeDelta::minutes(minutes.into())),
+            scheduled_start,
+            closing_time,
+            sort_order: track_override.sort_order.or(track.sort_order),
+        }
+    }
+
+    /// Time after which a racer started at `start` is out of time, the earlier of
+    /// the maximum running time and the closing time
+    pub fn cut_off_at(&self, start: Option<DateTime<Utc>>) -> Option<DateTime<Utc>> {
+        let by_duration = start
+            .zip(self.cut_off)
+            .map(|(start, cut_off)| start + cut_off);
+        match (by_duration, self.closing_time) {
+            (Some(by_duration), Some(closing_time)) => Some(by_duration.min(closing_time)),
+            (by_duration, closing_time) => by_duration.or(closing_time),
+        }
+    }
+
+    /// Running time per kilometer
+    pub fn pace(&self, time: TimeDelta) -> Option<TimeDelta> {
+        let distance = self.distance.filter(|distance| *distance > 0)?;
+        Some(TimeDelta::milliseconds(
+            time.num_milliseconds() * 1000 / i64::from(distance),
+        ))
+    }
+}
+
+/// Category attributes from the registrations and the race config
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct CategoryInfo {
+    /// category id in the registration API
+    pub id: u32,
+    pub description: Option<String>,
+    pub sort_order: Option<i32>,
+}
+
+impl CategoryInfo {
+    fn new(
+        category: &crate::restclient::Category,
+        category_override: Option<&CategoryOverride>,
+    ) -> Self {
+        CategoryInfo {
+            id: category.id,
+            description: category.description.clone(),
+            sort_order: category_override
+                .and_then(|category_override| category_override.sort_order)
+                .or(category.sort_order),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct TrackStats {
     pub track: Track,
@@ -57,14 +195,46 @@ pub struct TrackStats {
     pub finished: usize,
 }
 
+/// Event log shared by the clones of a race, also across threads
+#[derive(Clone)]
+struct SharedLog(Arc<Mutex<RaceEvents>>);
+
+impl SharedLog {
+    fn new(log: RaceEvents) -> Self {
+        SharedLog(Arc::new(Mutex::new(log)))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, RaceEvents> {
+        self.0.lock().expect("Race log lock poisoned")
+    }
+}
+
+impl PartialEq for SharedLog {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || *self.lock() == *other.lock()
+    }
+}
+
 #[derive(Clone, PartialEq)]
 pub struct Race {
     pub id: u32,
     pub racers: Vec<Racer>,
     pub categories: Vec<Category>,
     pub tracks: Vec<Track>,
+    pub track_info: HashMap<Track, TrackInfo>,
+    pub category_info: HashMap<Category, CategoryInfo>,
+    pub config: RaceConfig,
+    /// age factor tables shared by all races
+    pub age_grading: AgeGrading,
+    /// timezone of the wall clock times of the race
+    pub timezone: Tz,
+    pub date_of_event: Option<DateTime<Utc>>,
+    /// time of the cached registrations used while the API is unreachable
+    pub offline: Option<DateTime<Utc>>,
     track_starts: HashMap<Track, DateTime<Utc>>,
-    log: Rc<RefCell<RaceEvents>>,
+    /// racers confirmed as not finished
+    dnf: HashSet<StartNumber>,
+    log: SharedLog,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -78,11 +248,24 @@ pub struct Racer {
     pub track_rank: Option<u32>,
     pub categories: Vec<Category>,
     pub categories_rank: HashMap<Category, u32>,
+    pub birth_date: Option<NaiveDate>,
+    pub gender: Option<Gender>,
+    pub team: Option<String>,
+    /// legs of a relay team, empty for a single racer
+    pub legs: Vec<RelayLeg>,
     pub start: Option<DateTime<Utc>>,
     pub finish: Option<DateTime<Utc>>,
     pub time: Option<Duration>,
 }
 
+/// Member of a relay team, the leg starts with the handover of the previous one
+#[derive(Clone, Debug, PartialEq)]
+pub struct RelayLeg {
+    pub name: String,
+    pub tag: String,
+    pub finish: Option<DateTime<Utc>>,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum RacerField {
     StartNumber,
@@ -109,7 +292,40 @@ fn track_sort_key(track: &Track) -> (u32, String) {
     }
 }
 
+/// Order of the tracks by the sort order and the distance, the distance in
+/// the track name is only used for tracks without one
+fn track_order(track: &Track, info: Option<&TrackInfo>) -> (i32, u32, String) {
+    let (name_distance, name) = track_sort_key(track);
+    let sort_order = info.and_then(|info| info.sort_order).unwrap_or(0);
+    let distance = info
+        .and_then(|info| info.distance)
+        .unwrap_or(name_distance * 1000);
+    (sort_order, distance, name)
+}
+
 impl Racer {
+    pub fn is_relay(&self) -> bool {
+        !self.legs.is_empty()
+    }
+
+    /// Finish as uploaded, the start plus the official running time
+    pub fn official_finish(&self) -> Option<DateTime<Utc>> {
+        Some(self.start? + self.time?)
+    }
+
+    /// Running time of each relay leg
+    pub fn leg_splits(&self) -> Vec<Option<TimeDelta>> {
+        let mut handover = self.start;
+        self.legs
+            .iter()
+            .map(|leg| {
+                let split = calculate_time(handover, leg.finish);
+                handover = leg.finish;
+                split
+            })
+            .collect()
+    }
+
     pub fn cmp_by(&self, other: &Self, field: RacerField) -> Ordering {
         match field {
             RacerField::StartNumber => self.start_number.0.cmp(&other.start_number.0),
@@ -158,30 +374,62 @@ impl Racer {
     }
 }
 
-/// Extract all unique tracks and sort them
-fn extract_tracks(api_result: &[crate::restclient::Racer]) -> Vec<Track> {
-    let mut tracks: Vec<Track> = api_result
+/// Extract all unique tracks with their attributes
+fn extract_tracks(
+    api_result: &[crate::restclient::Racer],
+    config: &RaceConfig,
+    tz: Tz,
+) -> HashMap<Track, TrackInfo> {
+    api_result
         .iter()
-        .map(|racer| Track(racer.track.name.clone()))
-        .collect::<HashSet<_>>()
-        .into_iter()
-        .collect();
+        .map(|racer| {
+            (
+                Track(racer.track.name.clone()),
+                TrackInfo::new(&racer.track, config.tracks.get(&racer.track.name), tz),
+            )
+        })
+        .collect()
+}
 
-    tracks.sort_by_key(track_sort_key);
-    tracks
+/// Extract all unique categories with their attributes
+fn extract_categories(
+    api_result: &[crate::restclient::Racer],
+    config: &RaceConfig,
+) -> HashMap<Category, CategoryInfo> {
+    api_result
+        .iter()
+        .flat_map(|racer| racer.categories.iter())
+        .map(|category| {
+            (
+                Category(category.name.clone()),
+                CategoryInfo::new(category, config.categories.get(&category.name)),
+            )
+        })
+        .collect()
 }
 
-/// Extract all unique categories and sort them
-fn extract_categories(api_result: &[crate::restclient::Racer]) -> Vec<Category> {
-    let mut categories = HashSet::new();
-    for racer in api_result {
-        for category in &racer.categories {
-            categories.insert(Category(category.name.clone()));
-        }
-    }
-    let mut categories: Vec<Category> = categories.into_iter().collect();
-    categories.sort();
-    categories
+/// Whether the racer finished after the cut-off of its track
+fn out_of_time(info: Option<&TrackInfo>, racer: &Racer) -> bool {
+    racer
+        .finish
+        .zip(info.and_then(|info| info.cut_off_at(racer.start)))
+        .is_some_and(|(finish, cut_off)| finish > cut_off)
+}
+
+/// Finished in time and not confirmed as not finished
+fn is_ranked(info: Option<&TrackInfo>, dnf: &HashSet<StartNumber>, racer: &Racer) -> bool {
+    racer.finish.is_some() && !dnf.contains(&racer.start_number) && !out_of_time(info, racer)
+}
+
+/// Official time since the start of the track, racers finishing within the same
+/// unit of the timing precision get the same key and are ranked by start number
+fn finish_key(
+    timing: &Timing,
+    track_start: Option<&DateTime<Utc>>,
+    finish: Option<DateTime<Utc>>,
+) -> Option<TimeDelta> {
+    let reference = track_start.copied().unwrap_or(DateTime::UNIX_EPOCH);
+    finish.map(|finish| timing.apply(finish.signed_duration_since(reference)))
 }
 
 fn calculate_time(
@@ -195,54 +443,506 @@ fn calculate_time(
 }
 
 impl Race {
-    pub async fn load(api: RaceRestAPI, race_id: u32) -> Result<Race, Box<dyn std::error::Error>> {
-        let api_result = api.registrations(race_id).await?;
-        let racelog = RaceEvents::load(race_id);
-        let tracks = extract_tracks(&api_result);
-        let categories = extract_categories(&api_result);
+    /// Load the race, `timezone` applies unless the race config sets its own
+    pub async fn load(
+        source: &impl RegistrationSource,
+        race_id: u32,
+        storage: StorageBackend,
+        timezone: Tz,
+    ) -> Result<Race, Box<dyn std::error::Error>> {
+        let mut racelog = RaceEvents::load(race_id, storage);
+        let cached = racelog.registrations();
+        let (api_result, offline) = match source.registrations(race_id).await {
+            Ok(racers) => {
+                // saved even when unchanged, the offline notice shows the last fetch
+                racelog.save_registrations(&RegistrationsSnapshot {
+                    fetched: Utc::now(),
+                    racers: racers.clone(),
+                });
+                (racers, None)
+            }
+            Err(err) => match cached {
+                Some(snapshot) => {
+                    error!("Failed to load registrations, using cache: {err}");
+                    (snapshot.racers, Some(snapshot.fetched))
+                }
+                None => return Err(err),
+            },
+        };
+        let date_of_event = source.date_of_event(race_id);
+        Race::from_log(
+            race_id,
+            racelog,
+            api_result,
+            date_of_event,
+            offline,
+            timezone,
+        )
+    }
+
+    /// Load the race from its storage only, the stored registrations are left
+    /// untouched and the date of the race is taken from the cached races list
+    pub fn load_stored(
+        race_id: u32,
+        storage: StorageBackend,
+        timezone: Tz,
+    ) -> Result<Race, Box<dyn std::error::Error>> {
+        let racelog = RaceEvents::load(race_id, storage);
+        let snapshot = racelog
+            .registrations()
+            .ok_or_else(|| format!("No registrations stored for race {race_id}"))?;
+        Race::from_log(
+            race_id,
+            racelog,
+            snapshot.racers,
+            stored_date_of_event(race_id),
+            None,
+            timezone,
+        )
+    }
+
+    fn from_log(
+        race_id: u32,
+        racelog: RaceEvents,
+        registrations: Vec<crate::restclient::Racer>,
+        date_of_event: Option<DateTime<Utc>>,
+        offline: Option<DateTime<Utc>>,
+        timezone: Tz,
+    ) -> Result<Race, Box<dyn std::error::Error>> {
+        let state = racelog.state().clone();
+        let config = load_race_config(race_id)?;
+        let mut race = Race {
+            id: race_id,
+            racers: vec![],
+            categories: vec![],
+            tracks: vec![],
+            track_info: HashMap::new(),
+            category_info: HashMap::new(),
+            timezone: config.timezone.unwrap_or(timezone),
+            config,
+            age_grading: load_age_grading(),
+            date_of_event,
+            offline,
+            track_starts: HashMap::new(),
+            dnf: HashSet::new(),
+            log: SharedLog::new(racelog),
+        };
+        race.set_registrations(registrations);
+        race.apply_state(&state);
+        Ok(race)
+    }
 
-        let racers = api_result
+    /// Replace racers, tracks and categories by the registrations and the racers
+    /// registered locally, without any timing
+    fn set_registrations(&mut self, mut registrations: Vec<crate::restclient::Racer>) {
+        let registered: Vec<_> = self
+            .log
+            .lock()
+            .state()
+            .registered
+            .iter()
+            .filter(|walk_in| !registrations.iter().any(|r| r.id == walk_in.id))
+            .cloned()
+            .collect();
+        registrations.extend(registered);
+
+        // racers without categories get the ones of the category rules
+        let race_date = self.race_date();
+        for racer in registrations.iter_mut().filter(|r| r.categories.is_empty()) {
+            let profile = RacerProfile {
+                track: &racer.track.name,
+                gender: racer.gender,
+                birth_date: racer.birth_date,
+            };
+            let assigned = assign(&self.config.category_rules, &profile, race_date);
+            racer.categories = assigned
+                .unwrap_or_default()
+                .into_iter()
+                .map(|name| crate::restclient::Category {
+                    name,
+                    ..Default::default()
+                })
+                .collect();
+        }
+
+        self.track_info = extract_tracks(&registrations, &self.config, self.timezone);
+        self.category_info = extract_categories(&registrations, &self.config);
+        self.tracks = self.track_info.keys().cloned().collect();
+        self.tracks
+            .sort_by_key(|track| track_order(track, self.track_info.get(track)));
+        self.categories = self.category_info.keys().cloned().collect();
+        self.categories.sort_by_key(|category| {
+            let sort_order = self
+                .category_info
+                .get(category)
+                .and_then(|info| info.sort_order);
+            (sort_order.unwrap_or(0), category.clone())
+        });
+        self.racers = registrations
             .into_iter()
+            .map(|racer| Racer {
+                id: racer.id,
+                start_number: StartNumber(racer.start_number.unwrap_or(0)),
+                tag: racer.tag_id.unwrap_or("".to_string()),
+                first_name: racer.first_name,
+                last_name: racer.last_name,
+                track: Track(racer.track.name.clone()),
+                track_rank: None,
+                categories: racer
+                    .categories
+                    .into_iter()
+                    .map(|category| Category(category.name))
+                    .collect(),
+                categories_rank: HashMap::new(),
+                birth_date: racer.birth_date,
+                gender: racer.gender,
+                team: racer.team,
+                legs: racer
+                    .legs
+                    .into_iter()
+                    .map(|leg| RelayLeg {
+                        name: leg.name,
+                        tag: leg.tag_id.unwrap_or_default(),
+                        finish: None,
+                    })
+                    .collect(),
+                start: None,
+                finish: None,
+                time: None,
+            })
+            .collect();
+    }
+
+    /// Date of the race in its timezone, today if the date is unknown
+    pub fn race_date(&self) -> NaiveDate {
+        self.date_of_event
+            .unwrap_or_else(Utc::now)
+            .with_timezone(&self.timezone)
+            .date_naive()
+    }
+
+    /// Registration id for the next racer registered locally, from the ids of this station
+    pub fn next_walk_in_id(&self) -> u32 {
+        let start = walk_in_id_start(&self.station());
+        self.racers
+            .iter()
+            .map(|racer| racer.id + 1)
+            .filter(|id| *id > start && *id < start + WALK_IN_IDS_PER_STATION)
+            .max()
+            .unwrap_or(start)
+    }
+
+    /// Check that a racer registered on the spot does not clash with the registered racers
+    pub fn validate_walk_in(&self, racer: &crate::restclient::Racer) -> Result<(), String> {
+        if racer.first_name.trim().is_empty() || racer.last_name.trim().is_empty() {
+            return Err("Name is required".to_string());
+        }
+        if racer.track.name.trim().is_empty() {
+            return Err("Track is required".to_string());
+        }
+        let start_number = match racer.start_number {
+            Some(start_number) if start_number > 0 => StartNumber(start_number),
+            _ => return Err("Start number is required".to_string()),
+        };
+        if let Some(other) = self.racers.iter().find(|r| r.start_number == start_number) {
+            return Err(format!(
+                "Start number {start_number} is taken by {} {}",
+                other.first_name, other.last_name
+            ));
+        }
+        if let Some(tag) = racer.tag_id.as_deref().filter(|tag| !tag.is_empty()) {
+            if let Some(other) = self.racers.iter().find(|r| r.tag == tag) {
+                return Err(format!(
+                    "Tag {tag} is taken by {} {}",
+                    other.first_name, other.last_name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a racer registered on the spot, or replace the local registration
+    /// by the one accepted by the server
+    pub fn register(&mut self, racer: crate::restclient::Racer, replaces: Option<u32>) {
+        self.log.lock().log_registration(racer, replaces);
+        self.set_registrations(self.registrations());
+        let state = self.log.lock().state().clone();
+        self.apply_state(&state);
+    }
+
+    /// Name of this station in the synchronization of the race events
+    pub fn station(&self) -> String {
+        self.log.lock().station().to_string()
+    }
+
+    /// Highest sequence number of each station in the race log
+    pub fn sync_known(&self) -> HashMap<String, u64> {
+        self.log.lock().known()
+    }
+
+    /// Events missing at a station that has seen the `known` sequence numbers
+    pub fn events_after(&self, known: &HashMap<String, u64>) -> Vec<Event> {
+        self.log.lock().events_after(known)
+    }
+
+    /// Apply the events received from other stations and return the number of new ones
+    pub fn apply_remote(&mut self, events: Vec<Event>) -> usize {
+        let registered = self.log.lock().state().registered.clone();
+        let applied = self.log.lock().apply_remote(events);
+        if applied > 0 {
+            let state = self.log.lock().state().clone();
+            if state.registered != registered {
+                // racers registered on the spot at another station
+                self.set_registrations(self.registrations());
+            }
+            self.apply_state(&state);
+        }
+        applied
+    }
+
+    /// Registrations the race was built from
+    pub fn registrations(&self) -> Vec<crate::restclient::Racer> {
+        self.log
+            .lock()
+            .registrations()
+            .map(|snapshot| snapshot.racers)
+            .unwrap_or_default()
+    }
+
+    /// Replace the registrations while keeping the timing and the local overrides.
+    /// Finishes are recorded by start number, so a finish of a racer whose start number
+    /// changed in the registration is moved to the new start number, the timing of start
+    /// numbers left without a racer is cleared.
+    pub fn apply_registrations(&mut self, registrations: Vec<crate::restclient::Racer>) {
+        let previous: HashMap<u32, StartNumber> = self
+            .racers
+            .iter()
+            .map(|racer| (racer.id, racer.start_number.clone()))
+            .collect();
+
+        self.log.lock().save_registrations(&RegistrationsSnapshot {
+            fetched: Utc::now(),
+            racers: registrations.clone(),
+        });
+        self.set_registrations(registrations);
+        self.offline = None;
+
+        let state = self.log.lock().state().clone();
+        let moves: Vec<(StartNumber, StartNumber)> = self
+            .racers
+            .iter()
+            .filter(|racer| {
+                state
+                    .overrides
+                    .get(&racer.id)
+                    .is_none_or(|o| o.start_number.is_none())
+            })
+            .filter_map(|racer| {
+                let before = previous.get(&racer.id)?;
+                (*before != racer.start_number)
+                    .then(|| (before.clone(), racer.start_number.clone()))
+            })
+            .collect();
+        self.move_timing(&state, moves);
+        let state = self.log.lock().state().clone();
+        self.clear_unused_timing(&state);
+
+        let state = self.log.lock().state().clone();
+        self.apply_state(&state);
+    }
+
+    /// Clear finishes, handovers and DNFs of start numbers no racer has anymore,
+    /// a racer given such a start number later must not inherit them
+    fn clear_unused_timing(&self, state: &RaceState) {
+        let used: HashSet<StartNumber> = self
+            .racers
+            .iter()
             .map(|racer| {
-                let start_number = racer.start_number.map(StartNumber);
-
-                let track = Track(racer.track.name.clone());
-                let start = racelog.get_track_start(&track);
-                let finish =
-                    start_number.and_then(|start_number| racelog.get_finish_time_for(start_number));
-
-                Racer {
-                    id: racer.id,
-                    start_number: StartNumber(racer.start_number.unwrap_or(0)),
-                    tag: racer.tag_id.unwrap_or("".to_string()),
-                    first_name: racer.first_name,
-                    last_name: racer.last_name,
-                    track,
-                    track_rank: None,
-                    categories: racer
-                        .categories
-                        .into_iter()
-                        .map(|category| Category(category.name))
-                        .collect(),
-                    categories_rank: HashMap::new(),
-                    start,
-                    finish,
-                    time: calculate_time(start, finish),
-                }
+                state
+                    .overrides
+                    .get(&racer.id)
+                    .and_then(|o| o.start_number.clone())
+                    .unwrap_or_else(|| racer.start_number.clone())
             })
             .collect();
+        let mut log = self.log.lock();
+        for number in state.finish_times.keys().filter(|n| !used.contains(n)) {
+            info!("Clearing the finish of {number}, no racer has the start number");
+            log.log_finish(number.clone(), None);
+        }
+        for (number, leg) in state.leg_finishes.keys().filter(|(n, _)| !used.contains(n)) {
+            log.log_leg_finish(number.clone(), *leg, None);
+        }
+        for number in state.dnf.iter().filter(|n| !used.contains(n)) {
+            log.log_dnf(number.clone(), false);
+        }
+    }
 
-        let mut race = Race {
-            id: race_id,
-            racers,
-            categories,
-            tracks,
-            track_starts: racelog.track_starts.clone(),
-            log: RefCell::new(racelog).into(),
+    /// Move finishes and relay handovers to the new start numbers. All moves are taken
+    /// from the state before any of them, so racers may swap their start numbers.
+    /// A start number with a finish of a racer who keeps it is never overwritten.
+    fn move_timing(&self, state: &RaceState, mut moves: Vec<(StartNumber, StartNumber)>) {
+        let has_timing = |number: &StartNumber| {
+            state.finish_times.contains_key(number)
+                || state.leg_finishes.keys().any(|(n, _)| n == number)
         };
-        race.map_start_number_to_track_rank();
-        race.map_start_number_to_categories_rank();
-        Ok(race)
+        loop {
+            let sources: HashSet<StartNumber> =
+                moves.iter().map(|(before, _)| before.clone()).collect();
+            let count = moves.len();
+            moves.retain(|(before, after)| {
+                let occupied = has_timing(after) && !sources.contains(after);
+                if occupied {
+                    warn!("Keeping the finish of {after}, the finish of {before} is not moved");
+                }
+                !occupied
+            });
+            if moves.len() == count {
+                break;
+            }
+        }
+
+        let legs = |number: &StartNumber| -> HashMap<usize, DateTime<Utc>> {
+            state
+                .leg_finishes
+                .iter()
+                .filter(|((n, _), _)| n == number)
+                .map(|((_, leg), finish)| (*leg, *finish))
+                .collect()
+        };
+        let mut log = self.log.lock();
+        for (before, after) in &moves {
+            info!("Moving finish from {before} to {after}");
+            let finish = state.finish_times.get(before).copied();
+            if finish != state.finish_times.get(after).copied() {
+                log.log_finish(after.clone(), finish);
+            }
+            let (moved, replaced) = (legs(before), legs(after));
+            for (leg, finish) in &moved {
+                if replaced.get(leg) != Some(finish) {
+                    log.log_leg_finish(after.clone(), *leg, Some(*finish));
+                }
+            }
+            for leg in replaced.keys().filter(|leg| !moved.contains_key(leg)) {
+                log.log_leg_finish(after.clone(), *leg, None);
+            }
+        }
+
+        // start numbers left by their racers without anyone taking them over
+        let targets: HashSet<&StartNumber> = moves.iter().map(|(_, after)| after).collect();
+        for (before, _) in moves.iter().filter(|(before, _)| !targets.contains(before)) {
+            if state.finish_times.contains_key(before) {
+                log.log_finish(before.clone(), None);
+            }
+            for leg in legs(before).keys() {
+                log.log_leg_finish(before.clone(), *leg, None);
+            }
+        }
+    }
+
+    /// Overwrite the timing of all racers with the given state and recalculate ranks
+    fn apply_state(&mut self, state: &RaceState) {
+        self.track_starts = state.track_starts.clone();
+        self.dnf = state.dnf.clone();
+        for racer in self.racers.iter_mut() {
+            if let Some(racer_override) = state.overrides.get(&racer.id) {
+                if let Some(start_number) = &racer_override.start_number {
+                    racer.start_number = start_number.clone();
+                }
+                if let Some(tag) = &racer_override.tag {
+                    racer.tag = tag.clone();
+                }
+            }
+            let offset = start_offset(&self.config, &racer.start_number, &racer.categories);
+            racer.start = state
+                .track_starts
+                .get(&racer.track)
+                .map(|start| *start + offset);
+            racer.finish = state.finish_times.get(&racer.start_number).copied();
+            for (index, leg) in racer.legs.iter_mut().enumerate() {
+                leg.finish = state
+                    .leg_finishes
+                    .get(&(racer.start_number.clone(), index))
+                    .copied();
+            }
+            racer.time = calculate_time(racer.start, racer.finish)
+                .map(|time| self.config.timing.apply(time));
+            racer.track_rank = None;
+            racer.categories_rank.clear();
+        }
+        self.map_start_number_to_track_rank();
+        self.map_start_number_to_categories_rank();
+    }
+
+    /// Rebuild the race as it stood at the given moment by replaying the event log
+    pub fn replay(&self, at: DateTime<Utc>) -> Race {
+        let state = self.log.lock().state_at(at);
+        let mut race = self.clone();
+        race.apply_state(&state);
+        race
+    }
+
+    /// Change the start number or tag of a racer locally, without touching the registration
+    pub fn override_racer(&mut self, racer_override: RacerOverride) {
+        let Some(racer) = self
+            .racers
+            .iter_mut()
+            .find(|r| r.id == racer_override.registration_id)
+        else {
+            error!("Racer {} not found.", racer_override.registration_id);
+            return;
+        };
+        if let Some(start_number) = &racer_override.start_number {
+            racer.start_number = start_number.clone();
+        }
+        if let Some(tag) = &racer_override.tag {
+            racer.tag = tag.clone();
+        }
+        self.log.lock().log_override(racer_override);
+    }
+
+    /// Events logged since the last call
+    pub fn take_new_events(&self) -> Vec<Event> {
+        self.log.lock().take_new_events()
+    }
+
+    /// Timestamps of the first and the last logged event
+    pub fn log_period(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        self.log.lock().period()
+    }
+
+    pub fn log_upload(&self, results: Vec<UploadedResult>) {
+        self.log.lock().log_upload(results);
+    }
+
+    /// Replay the log up to the last upload and compare it with the uploaded results.
+    /// Returns registration ids of racers whose replayed times differ from the uploaded ones.
+    pub fn verify_upload(&self) -> Option<Vec<u32>> {
+        let (uploaded_at, uploaded) = {
+            let log = self.log.lock();
+            let (timestamp, uploaded) = log.last_upload()?;
+            (timestamp, uploaded.clone())
+        };
+        let replayed = self.replay(uploaded_at);
+
+        let mismatches = uploaded
+            .results
+            .iter()
+            .filter(|result| {
+                !replayed.racers.iter().any(|racer| {
+                    racer.id == result.registration_id
+                        && racer.start == Some(result.start)
+                        && (racer.finish == Some(result.finish)
+                            || racer.official_finish() == Some(result.finish))
+                })
+            })
+            .map(|result| result.registration_id)
+            .collect();
+        Some(mismatches)
+    }
+
+    pub fn track_start(&self, track: &Track) -> Option<DateTime<Utc>> {
+        self.track_starts.get(track).copied()
     }
 
     pub fn tracks_stats(&self) -> Vec<TrackStats> {
@@ -274,18 +974,26 @@ impl Race {
         }
 
         let mut tracks: Vec<TrackStats> = tracks.into_values().collect();
-        tracks.sort_by_key(|track_stats| track_sort_key(&track_stats.track));
+        tracks.sort_by_key(|track_stats| {
+            track_order(&track_stats.track, self.track_info.get(&track_stats.track))
+        });
         tracks
     }
 
     pub fn start(&mut self, track: Track, time: DateTime<Utc>) {
         for racer in self.racers.iter_mut() {
             if racer.track == track {
-                racer.start = Some(time);
+                racer.start =
+                    Some(time + start_offset(&self.config, &racer.start_number, &racer.categories));
+                racer.time = calculate_time(racer.start, racer.finish)
+                    .map(|time| self.config.timing.apply(time));
             }
         }
-        self.log.borrow_mut().log_start(&track, time);
+        self.log.lock().log_start(&track, time);
         self.track_starts.insert(track, time);
+        // an edited start changes the times and who is out of time
+        self.map_start_number_to_track_rank();
+        self.map_start_number_to_categories_rank();
     }
 
     fn finish<F>(&mut self, mut predicate: F, time: Option<DateTime<Utc>>) -> Result<(), ()>
@@ -295,7 +1003,8 @@ impl Race {
         let racer = self.racers.iter_mut().find(|r| predicate(r)).ok_or(())?;
 
         racer.finish = time;
-        racer.time = calculate_time(racer.start, racer.finish);
+        racer.time =
+            calculate_time(racer.start, racer.finish).map(|time| self.config.timing.apply(time));
 
         if racer.finish.is_none() {
             info!("Removing finish time for racer {}", racer.start_number);
@@ -303,9 +1012,7 @@ impl Race {
             racer.categories_rank.clear();
         }
 
-        self.log
-            .borrow_mut()
-            .log_finish(racer.start_number.clone(), time);
+        self.log.lock().log_finish(racer.start_number.clone(), time);
         self.map_start_number_to_track_rank();
         self.map_start_number_to_categories_rank();
         Ok(())
@@ -338,7 +1045,59 @@ impl Race {
         }
     }
 
+    /// Close a leg of a relay team, the last leg finishes the team
+    pub fn finish_leg(
+        &mut self,
+        start_number: StartNumber,
+        leg: usize,
+        time: Option<DateTime<Utc>>,
+    ) {
+        let Some(racer) = self
+            .racers
+            .iter_mut()
+            .find(|r| r.start_number == start_number && r.start.is_some())
+        else {
+            error!("Relay team with starting number {start_number:?} not found.");
+            return;
+        };
+        let legs = racer.legs.len();
+        let Some(relay_leg) = racer.legs.get_mut(leg) else {
+            error!("Relay team {start_number} has no leg {}", leg + 1);
+            return;
+        };
+        relay_leg.finish = time;
+        if racer.legs[..leg]
+            .iter()
+            .any(|previous| previous.finish.is_none())
+        {
+            warn!(
+                "Leg {} of relay team {start_number} closed before the previous one",
+                leg + 1
+            );
+        }
+        self.log
+            .lock()
+            .log_leg_finish(start_number.clone(), leg, time);
+
+        if leg + 1 == legs {
+            let _ = self.finish(|r| r.start_number == start_number, time);
+        }
+    }
+
     pub fn tag_finished(&mut self, tag: &str, time: Option<DateTime<Utc>>) {
+        self.log.lock().log_read(tag);
+        let relay_leg = self.racers.iter().find_map(|r| {
+            let leg = r
+                .legs
+                .iter()
+                .position(|leg| leg.tag == tag && leg.finish.is_none())?;
+            (r.start.is_some() && r.finish.is_none()).then(|| (r.start_number.clone(), leg))
+        });
+        if let Some((start_number, leg)) = relay_leg {
+            self.finish_leg(start_number, leg, time);
+            return;
+        }
+
         if self
             .finish(
                 |r| r.tag == tag && r.start.is_some() && r.finish.is_none(),
@@ -350,6 +1109,55 @@ impl Race {
         }
     }
 
+    /// Time after which the racer is out of time
+    pub fn cut_off(&self, racer: &Racer) -> Option<DateTime<Utc>> {
+        self.track_info.get(&racer.track)?.cut_off_at(racer.start)
+    }
+
+    pub fn is_out_of_time(&self, racer: &Racer) -> bool {
+        out_of_time(self.track_info.get(&racer.track), racer)
+    }
+
+    pub fn is_dnf(&self, racer: &Racer) -> bool {
+        self.dnf.contains(&racer.start_number)
+    }
+
+    /// Why a racer is not ranked, if so
+    pub fn status(&self, racer: &Racer) -> Option<&'static str> {
+        if self.is_dnf(racer) {
+            Some("DNF")
+        } else if self.is_out_of_time(racer) {
+            Some("out of time")
+        } else {
+            None
+        }
+    }
+
+    pub fn is_ranked(&self, racer: &Racer) -> bool {
+        is_ranked(self.track_info.get(&racer.track), &self.dnf, racer)
+    }
+
+    /// Racers on the course past their cut-off waiting for the operator to confirm DNF
+    pub fn pending_dnf(&self, now: DateTime<Utc>) -> Vec<&Racer> {
+        self.racers
+            .iter()
+            .filter(|r| r.finish.is_none() && !self.is_dnf(r))
+            .filter(|r| self.cut_off(r).is_some_and(|cut_off| cut_off < now))
+            .collect()
+    }
+
+    /// Confirm or take back that the racer did not finish
+    pub fn set_dnf(&mut self, start_number: StartNumber, dnf: bool) {
+        if dnf {
+            self.dnf.insert(start_number.clone());
+        } else {
+            self.dnf.remove(&start_number);
+        }
+        self.log.lock().log_dnf(start_number, dnf);
+        self.map_start_number_to_track_rank();
+        self.map_start_number_to_categories_rank();
+    }
+
     pub fn map_start_number_to_categories_rank(&mut self) {
         let categories = self.categories.clone();
         for category in categories {
@@ -358,15 +1166,24 @@ impl Race {
     }
 
     fn calculate_categories_rank(&mut self, category: &Category) {
+        let track_info = &self.track_info;
+        let track_starts = &self.track_starts;
+        let timing = &self.config.timing;
+        let dnf = &self.dnf;
         let mut finished: Vec<&mut Racer> = self
             .racers
             .iter_mut()
             .filter(|r| r.categories.contains(category))
-            .filter(|r| r.finish.is_some())
+            .filter_map(|r| {
+                // racers out of time or not finished lose their rank
+                r.categories_rank.remove(category);
+                is_ranked(track_info.get(&r.track), dnf, r).then_some(r)
+            })
             .collect();
 
         finished.sort_by(|a, b| {
-            let ord = a.finish.cmp(&b.finish);
+            let key = |r: &Racer| finish_key(timing, track_starts.get(&r.track), r.finish);
+            let ord = key(a).cmp(&key(b));
             if ord != std::cmp::Ordering::Equal {
                 return ord;
             }
@@ -389,15 +1206,23 @@ impl Race {
     }
 
     fn calculate_track_rank(&mut self, track: &Track) {
+        let info = self.track_info.get(track);
+        let track_starts = &self.track_starts;
+        let timing = &self.config.timing;
+        let dnf = &self.dnf;
         let mut finished: Vec<&mut Racer> = self
             .racers
             .iter_mut()
             .filter(|r| r.track == *track)
-            .filter(|r| r.finish.is_some())
+            .filter_map(|r| {
+                r.track_rank = None;
+                is_ranked(info, dnf, r).then_some(r)
+            })
             .collect();
 
         finished.sort_by(|a, b| {
-            let ord = a.finish.cmp(&b.finish);
+            let key = |r: &Racer| finish_key(timing, track_starts.get(&r.track), r.finish);
+            let ord = key(a).cmp(&key(b));
             if ord != std::cmp::Ordering::Equal {
                 return ord;
             }
@@ -417,28 +1242,91 @@ impl Race {
             .racers
             .iter()
             .filter(|r| r.track == *track)
-            .filter(|r| r.finish.is_some())
+            .filter(|r| self.is_ranked(r))
             .collect();
 
-        finished.sort_by(|a, b| a.track_rank.cmp(&b.track_rank));
+        finished.sort_by_key(|racer| racer.track_rank);
         finished
     }
 
+    /// Finishers of a track ranked by their age-graded percentage
+    pub fn get_age_graded_racers_by_track(&self, track: &Track) -> Vec<AgeGradedResult> {
+        self.age_grading.results(self, track)
+    }
+
     pub fn get_finished_racers_by_category(&self, category: &Category) -> Vec<&Racer> {
         let mut finished: Vec<&Racer> = self
             .racers
             .iter()
             .filter(|r| r.categories.contains(category))
-            .filter(|r| r.finish.is_some())
+            .filter(|r| self.is_ranked(r))
             .collect();
 
-        finished.sort_by(|a, b| a.categories_rank[category].cmp(&b.categories_rank[category]));
+        finished.sort_by_key(|r| r.categories_rank.get(category).copied().unwrap_or(u32::MAX));
         finished
     }
 }
 
+/// Race of the given registrations timed by `station`, its log is kept only in memory
+#[cfg(test)]
+impl Race {
+    pub fn for_test(
+        station: &str,
+        config: RaceConfig,
+        registrations: Vec<crate::restclient::Racer>,
+    ) -> Race {
+        let mut log = RaceEvents::in_memory(station);
+        log.save_registrations(&RegistrationsSnapshot {
+            fetched: Utc::now(),
+            racers: registrations.clone(),
+        });
+        let mut race = Race {
+            id: 1,
+            racers: vec![],
+            categories: vec![],
+            tracks: vec![],
+            track_info: HashMap::new(),
+            category_info: HashMap::new(),
+            config,
+            age_grading: AgeGrading::default(),
+            timezone: crate::config::DEFAULT_TIMEZONE,
+            date_of_event: None,
+            offline: None,
+            track_starts: HashMap::new(),
+            dnf: HashSet::new(),
+            log: SharedLog::new(log),
+        };
+        race.set_registrations(registrations);
+        race.apply_state(&RaceState::default());
+        race
+    }
+}
+
+/// Registration of a racer on `track` with the tag `T{start_number}`
+#[cfg(test)]
+pub fn test_registration(id: u32, start_number: u32, track: &str) -> crate::restclient::Racer {
+    crate::restclient::Racer {
+        id,
+        first_name: "Racer".into(),
+        last_name: start_number.to_string(),
+        start_number: Some(start_number),
+        categories: vec![],
+        tag_id: Some(format!("T{start_number}")),
+        track: crate::restclient::Track {
+            id: 1,
+            name: track.into(),
+            ..Default::default()
+        },
+        birth_date: None,
+        gender: None,
+        team: None,
+        legs: vec![],
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::config::DEFAULT_TIMEZONE;
     use crate::race::*;
 
     #[test]
@@ -457,6 +1345,63 @@ mod tests {
         );
     }
 
+    #[test]
+    fn track_metadata() {
+        let api_track = |name: &str, distance, sort_order| crate::restclient::Track {
+            id: 1,
+            name: name.into(),
+            distance,
+            sort_order,
+            scheduled_start: Some("2025-10-05 10:30:00".into()),
+            ..Default::default()
+        };
+        let mut config = RaceConfig::default();
+        config.tracks.insert(
+            "Sprint".into(),
+            TrackOverride {
+                distance: Some(400),
+                cut_off: Some(30),
+                ..Default::default()
+            },
+        );
+
+        let sprint = TrackInfo::new(
+            &api_track("Sprint", Some(12000), None),
+            config.tracks.get("Sprint"),
+            DEFAULT_TIMEZONE,
+        );
+        assert_eq!(sprint.distance, Some(400));
+        assert_eq!(sprint.cut_off, Some(TimeDelta::minutes(30)));
+        assert_eq!(
+            sprint.scheduled_start,
+            Some("2025-10-05T08:30:00Z".parse().unwrap())
+        );
+        let ten = TrackInfo {
+            distance: Some(10000),
+            ..Default::default()
+        };
+        assert_eq!(
+            ten.pace(TimeDelta::minutes(45)),
+            Some(TimeDelta::seconds(270))
+        );
+        assert_eq!(TrackInfo::default().pace(TimeDelta::minutes(45)), None);
+
+        // the sort order and distance win over the distance in the name
+        let kids = TrackInfo {
+            sort_order: Some(-1),
+            ..Default::default()
+        };
+        let mut tracks = [
+            (Track("10 Km".into()), Some(&ten)),
+            (Track("Sprint".into()), Some(&sprint)),
+            (Track("Kids 800 m".into()), Some(&kids)),
+            (Track("4 Km".into()), None),
+        ];
+        tracks.sort_by_key(|(track, info)| track_order(track, *info));
+        let names: Vec<_> = tracks.iter().map(|(track, _)| track.0.as_str()).collect();
+        assert_eq!(names, vec!["Kids 800 m", "Sprint", "4 Km", "10 Km"]);
+    }
+
     #[test]
     fn track_stats() {
         let track_10km = Track("10 Km".to_string());
@@ -478,6 +1423,10 @@ mod tests {
                 time: None,
                 track_rank: None,
                 categories_rank: HashMap::new(),
+                birth_date: None,
+                gender: None,
+                team: None,
+                legs: vec![],
             },
             Racer {
                 id: 1,
@@ -492,6 +1441,10 @@ mod tests {
                 time: None,
                 track_rank: None,
                 categories_rank: HashMap::new(),
+                birth_date: None,
+                gender: None,
+                team: None,
+                legs: vec![],
             },
             Racer {
                 id: 2,
@@ -506,17 +1459,16 @@ mod tests {
                 time: None,
                 track_rank: None,
                 categories_rank: HashMap::new(),
+                birth_date: None,
+                gender: None,
+                team: None,
+                legs: vec![],
             },
         ];
 
-        let race = Race {
-            id: 1,
-            racers,
-            categories: vec![],
-            tracks: vec![track_10km.clone(), track_4km.clone(), track_childs.clone()],
-            track_starts: HashMap::new(),
-            log: Rc::new(RefCell::new(RaceEvents::load(100000))),
-        };
+        let mut race = Race::for_test("primary", RaceConfig::default(), vec![]);
+        race.racers = racers;
+        race.tracks = vec![track_10km.clone(), track_4km.clone(), track_childs.clone()];
 
         let stats = race.tracks_stats();
         assert_eq!(
@@ -544,6 +1496,274 @@ mod tests {
         );
     }
 
+    #[test]
+    fn walk_in_ids() {
+        let mut start = Race::for_test("start", RaceConfig::default(), vec![]);
+        let mut finish = Race::for_test("finish", RaceConfig::default(), vec![]);
+        let first = start.next_walk_in_id();
+        assert!(is_walk_in(first));
+        assert_ne!(first, finish.next_walk_in_id());
+
+        start.register(test_registration(first, 1, "5 Km"), None);
+        assert_eq!(start.next_walk_in_id(), first + 1);
+        // ids of the other station do not move the own ones
+        finish.register(test_registration(first, 1, "5 Km"), None);
+        assert_eq!(finish.next_walk_in_id(), walk_in_id_start("finish"));
+    }
+
+    #[test]
+    fn cut_offs() {
+        let start = Utc::now();
+        let minutes = |minutes| start + chrono::Duration::minutes(minutes);
+        let mut info = TrackInfo {
+            cut_off: Some(TimeDelta::minutes(90)),
+            ..Default::default()
+        };
+        assert_eq!(info.cut_off_at(Some(start)), Some(minutes(90)));
+        assert_eq!(info.cut_off_at(None), None);
+        info.closing_time = Some(minutes(60));
+        assert_eq!(info.cut_off_at(Some(start)), Some(minutes(60)));
+        assert_eq!(info.cut_off_at(None), Some(minutes(60)));
+
+        let racer = |finish: Option<i64>| Racer {
+            id: 1,
+            start_number: StartNumber(1),
+            tag: "".into(),
+            first_name: "Alice".into(),
+            last_name: "Smith".into(),
+            track: Track("Trail".into()),
+            track_rank: None,
+            categories: vec![],
+            categories_rank: HashMap::new(),
+            birth_date: None,
+            gender: None,
+            team: None,
+            legs: vec![],
+            start: Some(start),
+            finish: finish.map(minutes),
+            time: None,
+        };
+        let no_dnf = HashSet::new();
+        assert!(is_ranked(Some(&info), &no_dnf, &racer(Some(60))));
+        assert!(out_of_time(Some(&info), &racer(Some(61))));
+        assert!(!is_ranked(Some(&info), &no_dnf, &racer(Some(61))));
+        assert!(!is_ranked(None, &no_dnf, &racer(None)));
+        assert!(!is_ranked(
+            None,
+            &HashSet::from([StartNumber(1)]),
+            &racer(Some(30))
+        ));
+    }
+
+    #[test]
+    fn edited_start() {
+        let mut registration = test_registration(1, 1, "5 Km");
+        registration.track.cut_off = Some(30);
+        registration.categories = vec![crate::restclient::Category {
+            name: "Men".into(),
+            ..Default::default()
+        }];
+        let mut race = Race::for_test("finish", RaceConfig::default(), vec![registration]);
+        let start = Utc::now();
+        race.start(Track("5 Km".into()), start);
+        race.finish_start_number(StartNumber(1), start + chrono::Duration::minutes(40));
+        assert_eq!(race.racers[0].track_rank, None);
+
+        // the start was recorded too early, the racer is in time after all
+        race.start(Track("5 Km".into()), start + chrono::Duration::minutes(20));
+        let category = Category("Men".into());
+        assert_eq!(race.racers[0].time, Some(TimeDelta::minutes(20)));
+        assert_eq!(race.racers[0].track_rank, Some(1));
+        assert_eq!(race.racers[0].categories_rank.get(&category), Some(&1));
+        assert_eq!(race.get_finished_racers_by_category(&category).len(), 1);
+    }
+
+    #[test]
+    fn equal_official_times() {
+        let config = RaceConfig {
+            timing: crate::time_utils::Timing {
+                precision: crate::time_utils::Precision::Seconds,
+                rounding: crate::time_utils::Rounding::Truncate,
+            },
+            ..Default::default()
+        };
+        let registrations = vec![
+            test_registration(1, 1, "5 Km"),
+            test_registration(2, 2, "5 Km"),
+        ];
+        let mut race = Race::for_test("finish", config, registrations);
+        let start = Utc::now();
+        race.start(Track("5 Km".into()), start);
+        race.finish_start_number(StartNumber(2), start + TimeDelta::milliseconds(10_200));
+        race.finish_start_number(StartNumber(1), start + TimeDelta::milliseconds(10_700));
+
+        // both are 10 seconds officially, the lower start number ranks first
+        let rank = |start_number| {
+            race.racers
+                .iter()
+                .find(|r| r.start_number == StartNumber(start_number))
+                .and_then(|r| r.track_rank)
+        };
+        assert_eq!(rank(1), Some(1));
+        assert_eq!(rank(2), Some(2));
+    }
+
+    #[test]
+    fn start_number_swap() {
+        let mut relay = test_registration(3, 5, "Relay");
+        relay.legs = vec![
+            crate::restclient::Leg {
+                name: "First".into(),
+                tag_id: Some("L1".into()),
+            },
+            crate::restclient::Leg {
+                name: "Second".into(),
+                tag_id: Some("L2".into()),
+            },
+        ];
+        let registrations = vec![
+            test_registration(1, 10, "5 Km"),
+            test_registration(2, 11, "5 Km"),
+            relay.clone(),
+        ];
+        let mut race = Race::for_test("finish", RaceConfig::default(), registrations.clone());
+        let start = Utc::now();
+        let minutes = |minutes| Some(start + chrono::Duration::minutes(minutes));
+        race.start(Track("5 Km".into()), start);
+        race.start(Track("Relay".into()), start);
+        race.finish_start_number(StartNumber(10), minutes(20).unwrap());
+        race.finish_start_number(StartNumber(11), minutes(25).unwrap());
+        race.tag_finished("L1", minutes(12));
+
+        let mut swapped = registrations;
+        swapped[0].start_number = Some(11);
+        swapped[1].start_number = Some(10);
+        swapped[2].start_number = Some(6);
+        race.apply_registrations(swapped);
+
+        let racer = |id| race.racers.iter().find(|racer| racer.id == id).unwrap();
+        assert_eq!(racer(1).start_number, StartNumber(11));
+        assert_eq!(racer(1).finish, minutes(20));
+        assert_eq!(racer(2).finish, minutes(25));
+        assert_eq!(racer(3).start_number, StartNumber(6));
+        assert_eq!(racer(3).legs[0].finish, minutes(12));
+        assert_eq!(racer(3).legs[1].finish, None);
+        let state = race.log.lock().state().clone();
+        assert!(!state.leg_finishes.contains_key(&(StartNumber(5), 0)));
+    }
+
+    #[test]
+    fn unregistered_finish() {
+        let registrations = vec![
+            test_registration(1, 10, "5 Km"),
+            test_registration(2, 11, "5 Km"),
+        ];
+        let mut race = Race::for_test("finish", RaceConfig::default(), registrations.clone());
+        let start = Utc::now();
+        race.start(Track("5 Km".into()), start);
+        race.finish_start_number(StartNumber(10), start + chrono::Duration::minutes(20));
+        race.set_dnf(StartNumber(10), true);
+
+        // the racer withdrew and the start number went to a late registration
+        race.apply_registrations(vec![registrations[1].clone()]);
+        let state = race.log.lock().state().clone();
+        assert!(!state.finish_times.contains_key(&StartNumber(10)));
+        assert!(state.dnf.is_empty());
+        race.apply_registrations(vec![
+            registrations[1].clone(),
+            test_registration(3, 10, "5 Km"),
+        ]);
+        let late = race.racers.iter().find(|racer| racer.id == 3).unwrap();
+        assert_eq!(late.finish, None);
+        assert_eq!(race.status(late), None);
+    }
+
+    #[test]
+    fn relay_handover() {
+        let mut relay = test_registration(1, 5, "Relay");
+        relay.legs = ["L1", "L2", "L3"]
+            .into_iter()
+            .map(|tag| crate::restclient::Leg {
+                name: tag.into(),
+                tag_id: Some(tag.into()),
+            })
+            .collect();
+        let mut race = Race::for_test("finish", RaceConfig::default(), vec![relay]);
+        let start = Utc::now();
+        let minutes = |minutes| Some(start + chrono::Duration::minutes(minutes));
+        race.start(Track("Relay".into()), start);
+        let legs =
+            |race: &Race| -> Vec<_> { race.racers[0].legs.iter().map(|leg| leg.finish).collect() };
+
+        race.tag_finished("L1", minutes(10));
+        assert_eq!(legs(&race), vec![minutes(10), None, None]);
+        assert_eq!(race.racers[0].finish, None);
+
+        // the runner of the first leg passes the reader again after the handover
+        race.tag_finished("L1", minutes(11));
+        assert_eq!(legs(&race), vec![minutes(10), None, None]);
+
+        race.tag_finished("L2", minutes(21));
+        race.tag_finished("L3", minutes(30));
+        assert_eq!(legs(&race), vec![minutes(10), minutes(21), minutes(30)]);
+        assert_eq!(race.racers[0].finish, minutes(30));
+
+        race.tag_finished("L3", minutes(31));
+        race.tag_finished("L2", minutes(32));
+        assert_eq!(legs(&race), vec![minutes(10), minutes(21), minutes(30)]);
+        assert_eq!(race.racers[0].finish, minutes(30));
+
+        let state = race.log.lock().state().clone();
+        assert_eq!(state.leg_finishes.len(), 3);
+        assert_eq!(
+            state.leg_finishes.get(&(StartNumber(5), 1)),
+            minutes(21).as_ref()
+        );
+    }
+
+    #[test]
+    fn relay_splits() {
+        let start = Utc::now();
+        let leg = |name: &str, finish: Option<i64>| RelayLeg {
+            name: name.into(),
+            tag: name.into(),
+            finish: finish.map(|minutes| start + chrono::Duration::minutes(minutes)),
+        };
+        let team = Racer {
+            id: 1,
+            start_number: StartNumber(1),
+            tag: "".into(),
+            first_name: "Relay".into(),
+            last_name: "Team".into(),
+            track: Track("4x1 Km".into()),
+            track_rank: None,
+            categories: vec![],
+            categories_rank: HashMap::new(),
+            birth_date: None,
+            gender: None,
+            team: None,
+            legs: vec![
+                leg("A", Some(4)),
+                leg("B", Some(9)),
+                leg("C", None),
+                leg("D", Some(20)),
+            ],
+            start: Some(start),
+            finish: None,
+            time: None,
+        };
+        assert!(team.is_relay());
+        assert_eq!(
+            team.leg_splits(),
+            vec![
+                Some(TimeDelta::minutes(4)),
+                Some(TimeDelta::minutes(5)),
+                None,
+                None
+            ]
+        );
+    }
+
     #[test]
     fn test_calculate_track_rank() {
         let track = Track("Track 1".to_string());
@@ -569,6 +1789,10 @@ mod tests {
                 time: Some(shared.signed_duration_since(start)),
                 track_rank: None,
                 categories_rank: HashMap::new(),
+                birth_date: None,
+                gender: None,
+                team: None,
+                legs: vec![],
             },
             // 2. place
             Racer {
@@ -584,6 +1808,10 @@ mod tests {
                 time: Some(shared.signed_duration_since(start)),
                 track_rank: None,
                 categories_rank: HashMap::new(),
+                birth_date: None,
+                gender: None,
+                team: None,
+                legs: vec![],
             },
             // Did not finish
             Racer {
@@ -599,6 +1827,10 @@ mod tests {
                 time: None,
                 track_rank: None,
                 categories_rank: HashMap::new(),
+                birth_date: None,
+                gender: None,
+                team: None,
+                legs: vec![],
             },
             // winner with best time
             Racer {
@@ -614,6 +1846,10 @@ mod tests {
                 time: Some(best.signed_duration_since(start)),
                 track_rank: None,
                 categories_rank: HashMap::new(),
+                birth_date: None,
+                gender: None,
+                team: None,
+                legs: vec![],
             },
             // winner, but different category
             Racer {
@@ -629,17 +1865,16 @@ mod tests {
                 time: Some(best_wrong_cat.signed_duration_since(start)),
                 track_rank: None,
                 categories_rank: HashMap::new(),
+                birth_date: None,
+                gender: None,
+                team: None,
+                legs: vec![],
             },
         ];
 
-        let mut race = Race {
-            id: 1,
-            racers,
-            categories: vec![],
-            tracks: vec![track.clone()],
-            track_starts: HashMap::new(),
-            log: Rc::new(RefCell::new(RaceEvents::load(100000))),
-        };
+        let mut race = Race::for_test("primary", RaceConfig::default(), vec![]);
+        race.racers = racers;
+        race.tracks = vec![track.clone()];
 
         race.calculate_track_rank(&track);
 
@@ -1,153 +1,797 @@
 use std::fs::File;
 use std::io::BufRead;
-use std::io::BufWriter;
-use std::io::Write;
-use std::{collections::HashMap, fs::OpenOptions, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
 use tracing::error;
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use tracing::warn;
 
-use crate::config::app_dir;
+use crate::config::{station_id, StorageBackend};
 use crate::race::StartNumber;
 use crate::race::Track;
+use crate::restclient;
+use crate::storage::{self, RegistrationsSnapshot, Storage};
 
-#[derive(Debug, Deserialize, Serialize)]
-struct RacerFinish {
-    start_number: StartNumber,
-    finish: Option<DateTime<Utc>>,
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RacerFinish {
+    pub start_number: StartNumber,
+    pub finish: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct TrackStart {
-    track: Track,
-    start: DateTime<Utc>,
+/// Handover of a relay team, the finish of the last leg is also logged as `RacerFinish`
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct LegFinish {
+    pub start_number: StartNumber,
+    /// index of the leg
+    pub leg: usize,
+    pub finish: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Operator decision that a racer did not finish, `dnf: false` takes it back
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RacerDnf {
+    pub start_number: StartNumber,
+    pub dnf: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct TrackStart {
+    pub track: Track,
+    pub start: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct UploadedResult {
+    pub registration_id: u32,
+    pub start: DateTime<Utc>,
+    pub finish: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ResultsUploaded {
+    pub results: Vec<UploadedResult>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct TagRead {
+    pub tag: String,
+}
+
+/// Local change of the registration data
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct RacerOverride {
+    pub registration_id: u32,
+    pub start_number: Option<StartNumber>,
+    pub tag: Option<String>,
+}
+
+/// Racer registered on the spot. When the registration is accepted by the server,
+/// it is registered again with the server id, replacing the local one.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RacerRegistered {
+    pub racer: Box<restclient::Racer>,
+    pub replaces: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type")]
-enum EventType {
+pub enum EventType {
     #[serde(rename = "racer_finish")]
     RacerFinish(RacerFinish),
     #[serde(rename = "track_start")]
     TrackStart(TrackStart),
+    #[serde(rename = "results_uploaded")]
+    ResultsUploaded(ResultsUploaded),
+    #[serde(rename = "tag_read")]
+    TagRead(TagRead),
+    #[serde(rename = "racer_override")]
+    RacerOverride(RacerOverride),
+    #[serde(rename = "racer_registered")]
+    RacerRegistered(RacerRegistered),
+    #[serde(rename = "leg_finish")]
+    LegFinish(LegFinish),
+    #[serde(rename = "racer_dnf")]
+    RacerDnf(RacerDnf),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub timestamp: DateTime<Utc>,
+    pub event: EventType,
+    /// station that logged the event, not set in logs recorded before the synchronization
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<Origin>,
+}
+
+/// Station and its sequence number of an event replicated between stations
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Origin {
+    pub station: String,
+    pub seq: u64,
+}
+
+/// Order in which the events are replayed, the same on all stations regardless of the
+/// order the events arrived in. Events without an origin keep their order in the log.
+fn replay_key(event: &Event) -> (DateTime<Utc>, &str, u64) {
+    match &event.origin {
+        Some(origin) => (event.timestamp, origin.station.as_str(), origin.seq),
+        None => (event.timestamp, "", 0),
+    }
+}
+
+fn in_replay_order(events: &[Event]) -> Vec<&Event> {
+    let mut ordered: Vec<&Event> = events.iter().collect();
+    ordered.sort_by(|a, b| replay_key(a).cmp(&replay_key(b)));
+    ordered
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Event {
-    timestamp: DateTime<Utc>,
-    event: EventType,
+/// Highest sequence number of each station in the events
+pub fn known_seqs(events: &[Event]) -> HashMap<String, u64> {
+    let mut known = HashMap::new();
+    for origin in events.iter().filter_map(|e| e.origin.as_ref()) {
+        let seq = known.entry(origin.station.clone()).or_insert(0);
+        *seq = origin.seq.max(*seq);
+    }
+    known
+}
+
+/// Events of other stations not covered by `known`
+pub fn events_after<'a>(
+    events: &'a [Event],
+    known: &'a HashMap<String, u64>,
+) -> impl Iterator<Item = &'a Event> {
+    events.iter().filter(|e| match &e.origin {
+        Some(origin) => known
+            .get(&origin.station)
+            .is_none_or(|seq| origin.seq > *seq),
+        None => false,
+    })
+}
+
+/// Timing state reconstructed from the event log
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RaceState {
+    pub track_starts: HashMap<Track, DateTime<Utc>>,
+    pub finish_times: HashMap<StartNumber, DateTime<Utc>>,
+    /// handovers of relay teams by start number and leg
+    pub leg_finishes: HashMap<(StartNumber, usize), DateTime<Utc>>,
+    pub overrides: HashMap<u32, RacerOverride>,
+    /// racers confirmed as not finished
+    pub dnf: HashSet<StartNumber>,
+    /// racers registered locally, in the order of registration
+    pub registered: Vec<restclient::Racer>,
+}
+
+impl RaceState {
+    pub fn apply(&mut self, event: &EventType) {
+        match event {
+            EventType::RacerFinish(RacerFinish {
+                start_number,
+                finish,
+            }) => {
+                if let Some(finish_time) = finish {
+                    self.finish_times.insert(start_number.clone(), *finish_time);
+                } else {
+                    self.finish_times.remove(start_number);
+                }
+            }
+            EventType::LegFinish(LegFinish {
+                start_number,
+                leg,
+                finish,
+            }) => {
+                let key = (start_number.clone(), *leg);
+                if let Some(finish_time) = finish {
+                    self.leg_finishes.insert(key, *finish_time);
+                } else {
+                    self.leg_finishes.remove(&key);
+                }
+            }
+            EventType::RacerDnf(RacerDnf { start_number, dnf }) => {
+                if *dnf {
+                    self.dnf.insert(start_number.clone());
+                } else {
+                    self.dnf.remove(start_number);
+                }
+            }
+            EventType::TrackStart(TrackStart { track, start }) => {
+                self.track_starts.insert(track.clone(), *start);
+            }
+            EventType::RacerOverride(racer_override) => {
+                let current = self
+                    .overrides
+                    .entry(racer_override.registration_id)
+                    .or_insert_with(|| RacerOverride {
+                        registration_id: racer_override.registration_id,
+                        ..Default::default()
+                    });
+                if racer_override.start_number.is_some() {
+                    current.start_number = racer_override.start_number.clone();
+                }
+                if racer_override.tag.is_some() {
+                    current.tag = racer_override.tag.clone();
+                }
+            }
+            EventType::RacerRegistered(RacerRegistered { racer, replaces }) => {
+                self.registered
+                    .retain(|r| r.id != racer.id && Some(r.id) != *replaces);
+                self.registered.push(racer.as_ref().clone());
+            }
+            EventType::ResultsUploaded(_) | EventType::TagRead(_) => {}
+        }
+    }
 }
 
 impl PartialEq for RaceEvents {
     fn eq(&self, other: &Self) -> bool {
-        self.track_starts == other.track_starts
+        self.state.track_starts == other.state.track_starts
     }
 }
 
 pub struct RaceEvents {
-    writer: BufWriter<File>,
-    pub track_starts: HashMap<Track, DateTime<Utc>>,
-    finish_times: HashMap<StartNumber, DateTime<Utc>>,
+    storage: Box<dyn Storage>,
+    events: Vec<Event>,
+    /// events appended since the last call of `take_new_events`
+    new_events: Vec<Event>,
+    state: RaceState,
+    /// this station in the synchronization
+    station: String,
+    /// sequence number of the last event logged by this station
+    seq: u64,
 }
 
 impl RaceEvents {
-    pub fn load(race_id: u32) -> Self {
-        let path = app_dir().join(format!("race_{race_id}.jsonl"));
-        let file = OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(&path)
-            .unwrap();
+    pub fn load(race_id: u32, backend: StorageBackend) -> Self {
+        let storage = storage::open(backend, race_id);
+        let events = storage.events();
+        let state = replay(&events, DateTime::<Utc>::MAX_UTC);
+        let station = station_id();
+        let seq = known_seqs(&events).get(&station).copied().unwrap_or(0);
 
-        let writer = BufWriter::new(file);
-        let (track_starts, finish_times) = load_events(&path);
+        RaceEvents {
+            storage,
+            events,
+            new_events: Vec::new(),
+            state,
+            station,
+            seq,
+        }
+    }
 
+    /// Empty log of the given station kept only in memory
+    #[cfg(test)]
+    pub fn in_memory(station: &str) -> Self {
         RaceEvents {
-            writer,
-            track_starts,
-            finish_times,
+            storage: Box::new(storage::MemoryStorage::default()),
+            events: Vec::new(),
+            new_events: Vec::new(),
+            state: RaceState::default(),
+            station: station.to_string(),
+            seq: 0,
         }
     }
 
-    pub fn log_finish(&mut self, start_number: StartNumber, finish: Option<DateTime<Utc>>) {
-        let line = serde_json::to_string(&Event {
+    fn log(&mut self, event: EventType) {
+        self.append(Event {
             timestamp: Utc::now(),
-            event: EventType::RacerFinish(RacerFinish {
-                start_number,
-                finish,
-            }),
-        })
-        .unwrap();
-        writeln!(self.writer, "{line}").unwrap();
-        self.writer.flush().unwrap();
+            event,
+            origin: None,
+        });
+    }
+
+    /// Append an event keeping its original timestamp, events without an origin
+    /// get the next sequence number of this station
+    pub fn append(&mut self, mut event: Event) {
+        if event.origin.is_none() {
+            self.seq += 1;
+            event.origin = Some(Origin {
+                station: self.station.clone(),
+                seq: self.seq,
+            });
+        }
+        self.storage.append(&event);
+        self.new_events.push(event.clone());
+        self.push(event);
+    }
+
+    fn push(&mut self, event: Event) {
+        let in_order = self
+            .events
+            .last()
+            .is_none_or(|last| replay_key(last) <= replay_key(&event));
+        if in_order {
+            self.state.apply(&event.event);
+            self.events.push(event);
+        } else {
+            self.events.push(event);
+            self.state = replay(&self.events, DateTime::<Utc>::MAX_UTC);
+        }
+    }
+
+    pub fn station(&self) -> &str {
+        &self.station
+    }
+
+    /// Highest sequence number of each station in the log
+    pub fn known(&self) -> HashMap<String, u64> {
+        known_seqs(&self.events)
+    }
+
+    /// Events missing at a station that has seen the `known` sequence numbers
+    pub fn events_after(&self, known: &HashMap<String, u64>) -> Vec<Event> {
+        events_after(&self.events, known).cloned().collect()
+    }
+
+    /// Store the events received from other stations, skipping the ones already
+    /// in the log, and return the number of new events
+    pub fn apply_remote(&mut self, events: Vec<Event>) -> usize {
+        let mut seen: HashSet<Origin> = self
+            .events
+            .iter()
+            .filter_map(|e| e.origin.clone())
+            .collect();
+        let mut applied = 0;
+        for event in events {
+            let Some(origin) = event.origin.clone() else {
+                continue;
+            };
+            if !seen.insert(origin) {
+                continue;
+            }
+            self.storage.append(&event);
+            self.push(event);
+            applied += 1;
+        }
+        applied
+    }
+
+    pub fn log_finish(&mut self, start_number: StartNumber, finish: Option<DateTime<Utc>>) {
+        self.log(EventType::RacerFinish(RacerFinish {
+            start_number,
+            finish,
+        }));
+    }
+
+    pub fn log_leg_finish(
+        &mut self,
+        start_number: StartNumber,
+        leg: usize,
+        finish: Option<DateTime<Utc>>,
+    ) {
+        self.log(EventType::LegFinish(LegFinish {
+            start_number,
+            leg,
+            finish,
+        }));
+    }
+
+    pub fn log_dnf(&mut self, start_number: StartNumber, dnf: bool) {
+        self.log(EventType::RacerDnf(RacerDnf { start_number, dnf }));
     }
 
     pub fn log_start(&mut self, track: &Track, start: DateTime<Utc>) {
-        let line = serde_json::to_string(&Event {
-            timestamp: Utc::now(),
-            event: EventType::TrackStart(TrackStart {
-                track: track.clone(),
-                start,
-            }),
-        })
-        .unwrap();
-        writeln!(self.writer, "{line}").unwrap();
-        self.writer.flush().unwrap();
+        self.log(EventType::TrackStart(TrackStart {
+            track: track.clone(),
+            start,
+        }));
+    }
+
+    pub fn log_read(&mut self, tag: &str) {
+        self.log(EventType::TagRead(TagRead {
+            tag: tag.to_string(),
+        }));
+    }
+
+    pub fn log_override(&mut self, racer_override: RacerOverride) {
+        self.log(EventType::RacerOverride(racer_override));
+    }
+
+    pub fn log_registration(&mut self, racer: restclient::Racer, replaces: Option<u32>) {
+        self.log(EventType::RacerRegistered(RacerRegistered {
+            racer: Box::new(racer),
+            replaces,
+        }));
+    }
+
+    pub fn save_registrations(&mut self, snapshot: &RegistrationsSnapshot) {
+        self.storage.save_registrations(snapshot);
+    }
+
+    pub fn registrations(&self) -> Option<RegistrationsSnapshot> {
+        self.storage.registrations()
+    }
+
+    pub fn log_upload(&mut self, results: Vec<UploadedResult>) {
+        self.log(EventType::ResultsUploaded(ResultsUploaded { results }));
     }
 
     pub fn get_finish_time_for(&self, start_number: StartNumber) -> Option<DateTime<Utc>> {
-        Some(*self.finish_times.get(&start_number)?)
+        Some(*self.state.finish_times.get(&start_number)?)
     }
 
     pub fn get_track_start(&self, track: &Track) -> Option<DateTime<Utc>> {
-        Some(*self.track_starts.get(track)?)
+        Some(*self.state.track_starts.get(track)?)
+    }
+
+    pub fn take_new_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.new_events)
+    }
+
+    pub fn state(&self) -> &RaceState {
+        &self.state
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Replay all events logged up to and including `until`
+    pub fn state_at(&self, until: DateTime<Utc>) -> RaceState {
+        replay(&self.events, until)
+    }
+
+    /// Timestamps of the first and the last logged event
+    pub fn period(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let first = self.events.iter().map(|e| e.timestamp).min()?;
+        let last = self.events.iter().map(|e| e.timestamp).max()?;
+        Some((first, last))
+    }
+
+    /// The most recent upload of results together with its timestamp
+    pub fn last_upload(&self) -> Option<(DateTime<Utc>, &ResultsUploaded)> {
+        self.events.iter().rev().find_map(|e| match &e.event {
+            EventType::ResultsUploaded(uploaded) => Some((e.timestamp, uploaded)),
+            _ => None,
+        })
     }
 }
 
-fn parse_event(line: Result<String, std::io::Error>) -> Result<EventType, std::io::Error> {
-    let event = serde_json::from_str::<Event>(&line?)?;
-    Ok(event.event)
+/// Finish of a racer recorded with different times in the merged logs
+#[derive(Clone, Debug, PartialEq)]
+pub struct FinishConflict {
+    pub start_number: StartNumber,
+    pub primary: DateTime<Utc>,
+    pub backup: Event,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Merge {
+    /// events from the backup log missing in the primary one
+    pub events: Vec<Event>,
+    /// number of backup events already present in the primary log
+    pub duplicates: usize,
+    pub conflicts: Vec<FinishConflict>,
+}
+
+fn last_finish_event<'a>(events: &'a [Event], start_number: &StartNumber) -> Option<&'a Event> {
+    events.iter().rev().find(|e| {
+        matches!(&e.event, EventType::RacerFinish(finish) if finish.start_number == *start_number)
+    })
 }
 
-fn load_events(
-    path: &PathBuf,
-) -> (
-    HashMap<Track, DateTime<Utc>>,
-    HashMap<StartNumber, DateTime<Utc>>,
-) {
-    let mut track_starts = HashMap::new();
-    let mut finish_times = HashMap::new();
+fn last_start_event<'a>(events: &'a [Event], track: &Track) -> Option<&'a Event> {
+    events
+        .iter()
+        .rev()
+        .find(|e| matches!(&e.event, EventType::TrackStart(start) if start.track == *track))
+}
+
+/// Merge the backup log into the primary one.
+///
+/// Events present in both logs are skipped. Finishes and track starts the primary log
+/// has never seen are taken from the backup. Finishes recorded in both logs with
+/// different times are reported as conflicts and left for the operator to resolve.
+pub fn merge(primary: &[Event], backup: &[Event]) -> Merge {
+    let mut merge = Merge {
+        duplicates: backup.iter().filter(|e| primary.contains(e)).count(),
+        ..Default::default()
+    };
+    let primary_state = replay(primary, DateTime::<Utc>::MAX_UTC);
+    let backup_state = replay(backup, DateTime::<Utc>::MAX_UTC);
+
+    for (track, start) in &backup_state.track_starts {
+        if last_start_event(primary, track).is_none() {
+            merge
+                .events
+                .extend(last_start_event(backup, track).cloned());
+        } else if primary_state.track_starts.get(track) != Some(start) {
+            warn!("Track {track} started at different times, keeping the primary start");
+        }
+    }
 
+    let mut start_numbers: Vec<&StartNumber> = backup_state.finish_times.keys().collect();
+    start_numbers.sort();
+    for start_number in start_numbers {
+        let backup_finish = backup_state.finish_times[start_number];
+        let backup_event = last_finish_event(backup, start_number).unwrap();
+
+        if last_finish_event(primary, start_number).is_none() {
+            // the primary laptop missed this racer completely
+            merge.events.push(backup_event.clone());
+            continue;
+        }
+
+        match primary_state.finish_times.get(start_number) {
+            Some(primary_finish) if *primary_finish != backup_finish => {
+                merge.conflicts.push(FinishConflict {
+                    start_number: start_number.clone(),
+                    primary: *primary_finish,
+                    backup: backup_event.clone(),
+                });
+            }
+            // same finish or finish removed by the operator on the primary laptop
+            _ => {}
+        }
+    }
+
+    merge.events.sort_by_key(|e| e.timestamp);
+    merge
+}
+
+/// Conflicting events, e.g. two finish times of one racer recorded at different
+/// stations, are resolved by the replay order: the later event wins.
+pub fn replay(events: &[Event], until: DateTime<Utc>) -> RaceState {
+    let mut state = RaceState::default();
+    for event in in_replay_order(events)
+        .into_iter()
+        .filter(|e| e.timestamp <= until)
+    {
+        state.apply(&event.event);
+    }
+    state
+}
+
+fn parse_event(line: Result<String, std::io::Error>) -> Result<Event, std::io::Error> {
+    Ok(serde_json::from_str::<Event>(&line?)?)
+}
+
+pub fn load_events(path: &PathBuf) -> Vec<Event> {
     let file = match File::open(path) {
         Ok(file) => file,
         Err(err) => {
             warn!("Could not open log: {err:?}");
-            return (HashMap::new(), HashMap::new());
+            return Vec::new();
         }
     };
 
+    let mut events = Vec::new();
     for line in std::io::BufReader::new(file).lines() {
         match parse_event(line) {
-            Ok(EventType::RacerFinish(RacerFinish {
-                start_number,
-                finish,
-            })) => {
-                if let Some(finish_time) = finish {
-                    finish_times.insert(start_number, finish_time);
-                } else {
-                    finish_times.remove(&start_number);
-                }
-            }
-            Ok(EventType::TrackStart(TrackStart { track, start })) => {
-                track_starts.insert(track, start);
-            }
+            Ok(event) => events.push(event),
             Err(err) => {
                 error!("Failed to load record: {err:?}")
             }
         }
     }
 
-    (track_starts, finish_times)
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(secs: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 10, 4, 10, 0, 0).unwrap()
+            + chrono::Duration::seconds(secs.into())
+    }
+
+    fn finish(timestamp: u32, start_number: u32, finish: Option<u32>) -> Event {
+        Event {
+            timestamp: at(timestamp),
+            event: EventType::RacerFinish(RacerFinish {
+                start_number: start_number.to_string().parse().unwrap(),
+                finish: finish.map(at),
+            }),
+            origin: None,
+        }
+    }
+
+    fn remote(station: &str, seq: u64, mut event: Event) -> Event {
+        event.origin = Some(Origin {
+            station: station.to_string(),
+            seq,
+        });
+        event
+    }
+
+    #[test]
+    fn replay_until() {
+        let track = Track("10 Km".to_string());
+        let events = vec![
+            Event {
+                timestamp: at(0),
+                event: EventType::TrackStart(TrackStart {
+                    track: track.clone(),
+                    start: at(0),
+                }),
+                origin: None,
+            },
+            finish(100, 1, Some(100)),
+            finish(110, 2, Some(110)),
+            finish(200, 1, None),
+        ];
+
+        let before_start = replay(&events, at(0) - chrono::Duration::seconds(1));
+        assert_eq!(before_start, RaceState::default());
+
+        let state = replay(&events, at(150));
+        assert_eq!(state.track_starts.get(&track), Some(&at(0)));
+        assert_eq!(state.finish_times.len(), 2);
+
+        let state = replay(&events, at(200));
+        assert_eq!(state.finish_times.len(), 1);
+        assert_eq!(
+            state.finish_times.get(&"2".parse().unwrap()),
+            Some(&at(110))
+        );
+    }
+
+    #[test]
+    fn stations() {
+        let start = remote("start", 1, finish(100, 1, Some(100)));
+        let finish_a = remote("finish", 1, finish(120, 1, Some(120)));
+        let finish_b = remote("finish", 2, finish(130, 2, Some(130)));
+        let announcer = remote("announcer", 1, finish(120, 1, Some(121)));
+
+        // the same state whatever order the events arrived in
+        let state = replay(
+            &[finish_b.clone(), announcer.clone(), finish_a.clone()],
+            at(200),
+        );
+        assert_eq!(
+            state,
+            replay(
+                &[
+                    finish_a.clone(),
+                    start.clone(),
+                    finish_b.clone(),
+                    announcer.clone()
+                ],
+                at(200)
+            )
+        );
+        // same timestamp, the station name decides
+        assert_eq!(
+            state.finish_times.get(&"1".parse().unwrap()),
+            Some(&at(120))
+        );
+
+        let events = vec![start, finish_a, finish_b.clone(), announcer];
+        let known = known_seqs(&events);
+        assert_eq!(known.get("finish"), Some(&2));
+        assert_eq!(known.get("start"), Some(&1));
+
+        let station_known = HashMap::from([
+            ("finish".to_string(), 1),
+            ("start".to_string(), 1),
+            ("announcer".to_string(), 1),
+        ]);
+        let missing: Vec<_> = events_after(&events, &station_known).collect();
+        assert_eq!(missing, vec![&finish_b]);
+        assert!(events_after(&events, &known).next().is_none());
+    }
+
+    #[test]
+    fn merge_logs() {
+        let track = Track("10 Km".to_string());
+        let start = Event {
+            timestamp: at(0),
+            event: EventType::TrackStart(TrackStart {
+                track: track.clone(),
+                start: at(0),
+            }),
+            origin: None,
+        };
+        let primary = vec![
+            start.clone(),
+            finish(100, 1, Some(100)),
+            finish(120, 3, Some(120)),
+            finish(130, 4, Some(130)),
+            finish(140, 4, None),
+        ];
+        let backup = vec![
+            start,
+            finish(100, 1, Some(100)),
+            finish(111, 2, Some(110)),
+            finish(121, 3, Some(121)),
+            finish(131, 4, Some(130)),
+        ];
+
+        let merged = merge(&primary, &backup);
+        assert_eq!(merged.duplicates, 2);
+        assert_eq!(merged.events, vec![finish(111, 2, Some(110))]);
+        assert_eq!(
+            merged.conflicts,
+            vec![FinishConflict {
+                start_number: "3".parse().unwrap(),
+                primary: at(120),
+                backup: finish(121, 3, Some(121)),
+            }]
+        );
+    }
+
+    #[test]
+    fn leg_finish() {
+        let start_number: StartNumber = "7".parse().unwrap();
+        let finish = Utc::now();
+        let mut state = RaceState::default();
+        state.apply(&EventType::LegFinish(LegFinish {
+            start_number: start_number.clone(),
+            leg: 1,
+            finish: Some(finish),
+        }));
+        assert_eq!(
+            state.leg_finishes.get(&(start_number.clone(), 1)),
+            Some(&finish)
+        );
+        state.apply(&EventType::LegFinish(LegFinish {
+            start_number: start_number.clone(),
+            leg: 1,
+            finish: None,
+        }));
+        assert!(state.leg_finishes.is_empty());
+        assert!(state.finish_times.is_empty());
+    }
+
+    #[test]
+    fn dnf() {
+        let start_number: StartNumber = "12".parse().unwrap();
+        let mut state = RaceState::default();
+        let event = |dnf| {
+            EventType::RacerDnf(RacerDnf {
+                start_number: start_number.clone(),
+                dnf,
+            })
+        };
+        state.apply(&event(true));
+        assert!(state.dnf.contains(&start_number));
+
+        let line = serde_json::to_string(&event(true)).unwrap();
+        assert!(line.contains(r#""type":"racer_dnf""#));
+        assert_eq!(
+            serde_json::from_str::<EventType>(&line).unwrap(),
+            event(true)
+        );
+
+        state.apply(&event(false));
+        assert!(state.dnf.is_empty());
+    }
+
+    #[test]
+    fn walk_in_registration() {
+        let racer = |id| restclient::Racer {
+            id,
+            first_name: "Alice".into(),
+            last_name: "Smith".into(),
+            start_number: Some(7),
+            categories: vec![],
+            tag_id: None,
+            track: restclient::Track {
+                id: 1,
+                name: "10 Km".into(),
+                description: None,
+                ..Default::default()
+            },
+            birth_date: None,
+            gender: None,
+            team: None,
+            legs: vec![],
+        };
+        let mut state = RaceState::default();
+        state.apply(&EventType::RacerRegistered(RacerRegistered {
+            racer: Box::new(racer(3_000_000_000)),
+            replaces: None,
+        }));
+        state.apply(&EventType::RacerRegistered(RacerRegistered {
+            racer: Box::new(racer(42)),
+            replaces: Some(3_000_000_000),
+        }));
+        assert_eq!(state.registered, vec![racer(42)]);
+    }
 }
@@ -0,0 +1,547 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::io::Cursor;
+
+use calamine::{open_workbook_auto_from_rs, Reader};
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::config::StorageBackend;
+use crate::restclient::{Category, Gender, RaceRestAPI, Racer, Track};
+use crate::storage;
+
+/// Where the registrations of a race come from
+pub trait RegistrationSource {
+    fn registrations(
+        &self,
+        race_id: u32,
+    ) -> impl Future<Output = Result<Vec<Racer>, Box<dyn Error>>>;
+    /// Date of the race if it is known
+    fn date_of_event(&self, race_id: u32) -> Option<DateTime<Utc>>;
+}
+
+impl RegistrationSource for RaceRestAPI {
+    async fn registrations(&self, race_id: u32) -> Result<Vec<Racer>, Box<dyn Error>> {
+        Ok(RaceRestAPI::registrations(self, race_id).await?)
+    }
+
+    fn date_of_event(&self, race_id: u32) -> Option<DateTime<Utc>> {
+        cached_date_of_event(race_id)
+    }
+}
+
+/// Without a registration API the race is loaded from the cached registrations
+impl RegistrationSource for Option<RaceRestAPI> {
+    async fn registrations(&self, race_id: u32) -> Result<Vec<Racer>, Box<dyn Error>> {
+        match self {
+            Some(api) => RegistrationSource::registrations(api, race_id).await,
+            None => Err("Registration API is not configured".into()),
+        }
+    }
+
+    fn date_of_event(&self, race_id: u32) -> Option<DateTime<Utc>> {
+        cached_date_of_event(race_id)
+    }
+}
+
+fn cached_date_of_event(race_id: u32) -> Option<DateTime<Utc>> {
+    storage::cached_races()?
+        .races
+        .into_iter()
+        .find(|race| race.id == race_id)
+        .map(|race| race.date_of_event)
+}
+
+/// Date of a race from the cached races list, or of a race imported locally
+pub fn stored_date_of_event(race_id: u32) -> Option<DateTime<Utc>> {
+    cached_date_of_event(race_id).or_else(|| local_date_of_event(race_id))
+}
+
+fn local_date_of_event(race_id: u32) -> Option<DateTime<Utc>> {
+    storage::local_races()
+        .into_iter()
+        .find(|race| race.id == race_id)
+        .map(|race| race.date_of_event)
+}
+
+/// Registrations imported from a file and kept in the race storage
+pub struct ImportedRegistrations {
+    pub storage: StorageBackend,
+}
+
+impl RegistrationSource for ImportedRegistrations {
+    async fn registrations(&self, race_id: u32) -> Result<Vec<Racer>, Box<dyn Error>> {
+        storage::open(self.storage, race_id)
+            .registrations()
+            .map(|snapshot| snapshot.racers)
+            .ok_or_else(|| format!("No registrations imported for race {race_id}").into())
+    }
+
+    fn date_of_event(&self, race_id: u32) -> Option<DateTime<Utc>> {
+        local_date_of_event(race_id)
+    }
+}
+
+/// A single changed field of a registration
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub before: String,
+    pub after: String,
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} → {}", self.field, self.before, self.after)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangedRacer {
+    pub racer: Racer,
+    pub changes: Vec<FieldChange>,
+}
+
+/// Difference between two versions of the race registrations
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct RegistrationsDiff {
+    pub added: Vec<Racer>,
+    pub removed: Vec<Racer>,
+    pub changed: Vec<ChangedRacer>,
+}
+
+impl RegistrationsDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+fn optional<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(T::to_string).unwrap_or_default()
+}
+
+fn categories(racer: &Racer) -> String {
+    let mut names: Vec<&str> = racer.categories.iter().map(|c| c.name.as_str()).collect();
+    names.sort();
+    names.join(", ")
+}
+
+fn legs(racer: &Racer) -> String {
+    racer
+        .legs
+        .iter()
+        .map(|leg| format!("{} ({})", leg.name, optional(&leg.tag_id)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Name of a registration field and its displayed value
+type Field = (&'static str, fn(&Racer) -> String);
+
+fn field_changes(before: &Racer, after: &Racer) -> Vec<FieldChange> {
+    let fields: [Field; 10] = [
+        ("Start number", |r| optional(&r.start_number)),
+        ("Tag", |r| optional(&r.tag_id)),
+        ("First name", |r| r.first_name.clone()),
+        ("Last name", |r| r.last_name.clone()),
+        ("Track", |r| r.track.name.clone()),
+        ("Categories", categories),
+        ("Birth date", |r| optional(&r.birth_date)),
+        ("Gender", |r| optional(&r.gender)),
+        ("Team", |r| optional(&r.team)),
+        ("Legs", legs),
+    ];
+
+    fields
+        .into_iter()
+        .filter_map(|(field, value)| {
+            let (before, after) = (value(before), value(after));
+            (before != after).then_some(FieldChange {
+                field,
+                before,
+                after,
+            })
+        })
+        .collect()
+}
+
+/// Compare registrations by their registration id
+pub fn diff_registrations(before: &[Racer], after: &[Racer]) -> RegistrationsDiff {
+    let before_by_id: HashMap<u32, &Racer> = before.iter().map(|r| (r.id, r)).collect();
+    let after_by_id: HashMap<u32, &Racer> = after.iter().map(|r| (r.id, r)).collect();
+
+    let mut diff = RegistrationsDiff::default();
+    for racer in after {
+        match before_by_id.get(&racer.id) {
+            None => diff.added.push(racer.clone()),
+            Some(previous) => {
+                let changes = field_changes(previous, racer);
+                if !changes.is_empty() {
+                    diff.changed.push(ChangedRacer {
+                        racer: racer.clone(),
+                        changes,
+                    });
+                }
+            }
+        }
+    }
+    diff.removed = before
+        .iter()
+        .filter(|racer| !after_by_id.contains_key(&racer.id))
+        .cloned()
+        .collect();
+    diff
+}
+
+/// Rows of an imported spreadsheet
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Table {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+fn csv_delimiter(data: &str) -> u8 {
+    let header = data.lines().next().unwrap_or_default();
+    [b';', b'\t', b',']
+        .into_iter()
+        .max_by_key(|delimiter| header.matches(*delimiter as char).count())
+        .unwrap()
+}
+
+fn read_csv(data: &[u8]) -> Result<Table, Box<dyn Error>> {
+    let data = String::from_utf8_lossy(data);
+    let data = data.trim_start_matches('\u{feff}');
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(csv_delimiter(data))
+        .flexible(true)
+        .from_reader(data.as_bytes());
+
+    let header = reader.headers()?.iter().map(str::to_string).collect();
+    let rows = reader
+        .records()
+        .map(|record| Ok(record?.iter().map(str::to_string).collect()))
+        .collect::<Result<_, csv::Error>>()?;
+    Ok(Table { header, rows })
+}
+
+fn read_spreadsheet(data: &[u8]) -> Result<Table, Box<dyn Error>> {
+    let mut workbook = open_workbook_auto_from_rs(Cursor::new(data.to_vec()))?;
+    let sheet = workbook
+        .worksheet_range_at(0)
+        .ok_or("The spreadsheet has no sheets")??;
+
+    let mut rows = sheet
+        .rows()
+        .map(|row| row.iter().map(|cell| cell.to_string()).collect::<Vec<_>>());
+    let header = rows.next().unwrap_or_default();
+    Ok(Table {
+        header,
+        rows: rows.collect(),
+    })
+}
+
+/// Read a CSV or a spreadsheet (XLSX, XLS, ODS), the first row is the header
+pub fn read_table(file_name: &str, data: &[u8]) -> Result<Table, Box<dyn Error>> {
+    if file_name.to_lowercase().ends_with(".csv") {
+        read_csv(data)
+    } else {
+        read_spreadsheet(data)
+    }
+}
+
+/// Registration field imported from a table column
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Column {
+    FirstName,
+    LastName,
+    StartNumber,
+    Tag,
+    Track,
+    Categories,
+    BirthDate,
+    Gender,
+    Team,
+}
+
+impl Column {
+    pub const ALL: [Column; 9] = [
+        Column::FirstName,
+        Column::LastName,
+        Column::StartNumber,
+        Column::Tag,
+        Column::Track,
+        Column::Categories,
+        Column::BirthDate,
+        Column::Gender,
+        Column::Team,
+    ];
+
+    /// Lowercase header names recognized for the column
+    fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            Column::FirstName => &["first name", "firstname", "jméno", "jmeno"],
+            Column::LastName => &["last name", "lastname", "surname", "příjmení", "prijmeni"],
+            Column::StartNumber => &["bib", "start number", "startnumber", "číslo", "cislo"],
+            Column::Tag => &["tag", "tag id", "chip", "čip", "cip"],
+            Column::Track => &["track", "trať", "trat"],
+            Column::Categories => &["category", "categories", "kategorie"],
+            Column::BirthDate => &[
+                "birth date",
+                "birthdate",
+                "date of birth",
+                "datum narození",
+                "datum narozeni",
+                "narozen",
+            ],
+            Column::Gender => &["gender", "sex", "pohlaví", "pohlavi"],
+            Column::Team => &["team", "club", "tým", "tym", "klub", "oddíl", "oddil"],
+        }
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Column::FirstName => "First name",
+            Column::LastName => "Last name",
+            Column::StartNumber => "Start number",
+            Column::Tag => "Tag",
+            Column::Track => "Track",
+            Column::Categories => "Categories",
+            Column::BirthDate => "Birth date",
+            Column::Gender => "Gender",
+            Column::Team => "Team",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Index of the table column for each imported field
+pub type ColumnMapping = HashMap<Column, usize>;
+
+/// Map columns by their header names
+pub fn guess_mapping(header: &[String]) -> ColumnMapping {
+    Column::ALL
+        .into_iter()
+        .filter_map(|column| {
+            let index = header.iter().position(|name| {
+                column
+                    .aliases()
+                    .contains(&name.trim().to_lowercase().as_str())
+            })?;
+            Some((column, index))
+        })
+        .collect()
+}
+
+/// Birth dates as they are commonly written in spreadsheets
+const BIRTH_DATE_FORMATS: [&str; 3] = ["%Y-%m-%d", "%d.%m.%Y", "%d/%m/%Y"];
+
+fn parse_birth_date(value: &str) -> Option<NaiveDate> {
+    // spreadsheet cells with a date are read with the time of day
+    let date = value.split_whitespace().next()?;
+    BIRTH_DATE_FORMATS
+        .into_iter()
+        .find_map(|format| NaiveDate::parse_from_str(date, format).ok())
+}
+
+/// Track used when the table has no track column
+const DEFAULT_TRACK: &str = "Race";
+
+/// Build registrations from the table rows. Registration, track and category ids are
+/// assigned in the order of appearance.
+pub fn table_to_registrations(
+    table: &Table,
+    mapping: &ColumnMapping,
+) -> Result<Vec<Racer>, String> {
+    for column in [Column::FirstName, Column::LastName] {
+        if !mapping.contains_key(&column) {
+            return Err(format!("Column {column} is not mapped"));
+        }
+    }
+
+    let mut tracks: Vec<String> = Vec::new();
+    let mut categories: Vec<String> = Vec::new();
+    let id_of = |names: &mut Vec<String>, name: &str| -> u32 {
+        let index = names.iter().position(|n| n == name).unwrap_or_else(|| {
+            names.push(name.to_string());
+            names.len() - 1
+        });
+        index as u32 + 1
+    };
+
+    let mut racers = Vec::new();
+    for (index, row) in table.rows.iter().enumerate() {
+        let value = |column: Column| -> &str {
+            mapping
+                .get(&column)
+                .and_then(|&i| row.get(i))
+                .map(|v| v.trim())
+                .unwrap_or_default()
+        };
+        if row.iter().all(|cell| cell.trim().is_empty()) {
+            continue;
+        }
+        // header is the first row of the file
+        let line = index + 2;
+
+        let start_number = match value(Column::StartNumber) {
+            "" => None,
+            bib => Some(
+                bib.parse::<u32>()
+                    .map_err(|_| format!("Row {line}: invalid start number {bib:?}"))?,
+            ),
+        };
+        let track = match value(Column::Track) {
+            "" => DEFAULT_TRACK,
+            track => track,
+        };
+        let birth_date = match value(Column::BirthDate) {
+            "" => None,
+            date => Some(
+                parse_birth_date(date)
+                    .ok_or_else(|| format!("Row {line}: invalid birth date {date:?}"))?,
+            ),
+        };
+        let gender = match value(Column::Gender) {
+            "" => None,
+            gender => Some(
+                gender
+                    .parse::<Gender>()
+                    .map_err(|err| format!("Row {line}: {err}"))?,
+            ),
+        };
+
+        racers.push(Racer {
+            id: index as u32 + 1,
+            first_name: value(Column::FirstName).to_string(),
+            last_name: value(Column::LastName).to_string(),
+            start_number,
+            categories: value(Column::Categories)
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| Category {
+                    id: id_of(&mut categories, name),
+                    name: name.to_string(),
+                    description: None,
+                    ..Default::default()
+                })
+                .collect(),
+            tag_id: Some(value(Column::Tag))
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string),
+            track: Track {
+                id: id_of(&mut tracks, track),
+                name: track.to_string(),
+                description: None,
+                ..Default::default()
+            },
+            birth_date,
+            gender,
+            team: Some(value(Column::Team))
+                .filter(|team| !team.is_empty())
+                .map(str::to_string),
+            legs: vec![],
+        });
+    }
+    Ok(racers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn racer(id: u32, start_number: u32, tag: &str) -> Racer {
+        Racer {
+            id,
+            first_name: "Alice".into(),
+            last_name: "Smith".into(),
+            start_number: Some(start_number),
+            categories: vec![Category {
+                id: 1,
+                name: "Women".into(),
+                description: None,
+                ..Default::default()
+            }],
+            tag_id: Some(tag.into()),
+            track: Track {
+                id: 1,
+                name: "10 Km".into(),
+                description: None,
+                ..Default::default()
+            },
+            birth_date: None,
+            gender: None,
+            team: None,
+            legs: vec![],
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let before = vec![racer(1, 10, "A"), racer(2, 20, "B"), racer(3, 30, "C")];
+        let mut changed = racer(2, 21, "B");
+        changed.categories.clear();
+        let after = vec![racer(1, 10, "A"), changed.clone(), racer(4, 40, "D")];
+
+        let diff = diff_registrations(&before, &after);
+        assert_eq!(diff.added, vec![racer(4, 40, "D")]);
+        assert_eq!(diff.removed, vec![racer(3, 30, "C")]);
+        assert_eq!(
+            diff.changed,
+            vec![ChangedRacer {
+                racer: changed,
+                changes: vec![
+                    FieldChange {
+                        field: "Start number",
+                        before: "20".into(),
+                        after: "21".into(),
+                    },
+                    FieldChange {
+                        field: "Categories",
+                        before: "Women".into(),
+                        after: "".into(),
+                    },
+                ],
+            }]
+        );
+        assert!(diff_registrations(&before, &before).is_empty());
+    }
+
+    #[test]
+    fn import_csv() {
+        let data = "\u{feff}Jméno;Příjmení;Číslo;Čip;Trať;Kategorie;Narozen;Pohlaví;Oddíl\n\
+                    Alice;Smith;12;A1;10 Km;Women, Veterans;5.3.1980;Ž;Sokol\n\
+                    ;;;;;;;;\n\
+                    Bob;Jones;;;4 Km;;;;\n";
+        let table = read_table("registrations.csv", data.as_bytes()).unwrap();
+        assert_eq!(table.header.len(), 9);
+        assert_eq!(table.rows.len(), 3);
+
+        let mapping = guess_mapping(&table.header);
+        assert_eq!(mapping.len(), 9);
+
+        let racers = table_to_registrations(&table, &mapping).unwrap();
+        assert_eq!(racers.len(), 2);
+        assert_eq!(racers[0].start_number, Some(12));
+        assert_eq!(racers[0].tag_id.as_deref(), Some("A1"));
+        assert_eq!(racers[0].categories.len(), 2);
+        assert_eq!(racers[0].birth_date, NaiveDate::from_ymd_opt(1980, 3, 5));
+        assert_eq!(racers[0].gender, Some(Gender::Female));
+        assert_eq!(racers[1].gender, None);
+        assert_eq!(racers[0].team.as_deref(), Some("Sokol"));
+        assert_eq!(racers[1].team, None);
+        assert_eq!(racers[1].id, 3);
+        assert_eq!(racers[1].start_number, None);
+        assert_eq!(racers[1].track.id, 2);
+
+        let mut invalid = table.clone();
+        invalid.rows[0][2] = "twelve".into();
+        assert_eq!(
+            table_to_registrations(&invalid, &mapping),
+            Err("Row 2: invalid start number \"twelve\"".to_string())
+        );
+    }
+}
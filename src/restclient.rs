@@ -1,21 +1,40 @@
-use chrono::TimeZone;
-use chrono::{DateTime, NaiveDateTime, Utc};
-use chrono_tz::Europe::Prague;
-use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
 
+use crate::time_utils::localize;
+
+/// A hung request would hold back every batch queued after it
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Times are exchanged as wall clock times in the timezone of the race
 const DATE_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.6f";
 
-#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct Race {
     pub id: u32,
     pub name: String,
     pub description: Option<String>,
-    #[serde(rename = "dateOfEvent", deserialize_with = "parse_dt")]
+    #[serde(rename = "dateOfEvent")]
     pub date_of_event: DateTime<Utc>,
 }
 
-#[derive(Debug, Deserialize, Clone, PartialEq)]
+/// Race as it is sent by the API
+#[derive(Debug, Deserialize)]
+struct ApiRace {
+    id: u32,
+    name: String,
+    description: Option<String>,
+    #[serde(rename = "dateOfEvent")]
+    date_of_event: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct Racer {
     pub id: u32,
     #[serde(rename = "firstName")]
@@ -28,68 +47,133 @@ pub struct Racer {
     #[serde(rename = "tagId")]
     pub tag_id: Option<String>,
     pub track: Track,
+    #[serde(rename = "birthDate", default, skip_serializing_if = "Option::is_none")]
+    pub birth_date: Option<NaiveDate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gender: Option<Gender>,
+    /// team or club of the racer
+    #[serde(default, alias = "club", skip_serializing_if = "Option::is_none")]
+    pub team: Option<String>,
+    /// members of a relay team in the order they run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub legs: Vec<Leg>,
+}
+
+/// Member of a relay team
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Leg {
+    pub name: String,
+    #[serde(rename = "tagId")]
+    pub tag_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Gender {
+    #[serde(alias = "M", alias = "m")]
+    Male,
+    #[serde(alias = "F", alias = "f")]
+    Female,
 }
 
-#[derive(Debug, Deserialize, Ord, Eq, Clone, PartialOrd, PartialEq, Hash)]
+impl fmt::Display for Gender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Gender::Male => write!(f, "M"),
+            Gender::Female => write!(f, "F"),
+        }
+    }
+}
+
+impl FromStr for Gender {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "m" | "male" | "man" | "muž" | "muz" => Ok(Gender::Male),
+            "f" | "female" | "w" | "woman" | "ž" | "z" | "žena" | "zena" => Ok(Gender::Female),
+            _ => Err(format!("Unknown gender {s:?}")),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Ord, Eq, Clone, PartialOrd, PartialEq, Hash, Default)]
 pub struct Category {
     pub id: u32,
     pub name: String,
     pub description: Option<String>,
+    #[serde(rename = "sortOrder", default, skip_serializing_if = "Option::is_none")]
+    pub sort_order: Option<i32>,
 }
 
-#[derive(Debug, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Hash, Default)]
 pub struct Track {
     pub id: u32,
     pub name: String,
     pub description: Option<String>,
+    /// length in meters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distance: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub laps: Option<u32>,
+    /// maximum running time in minutes
+    #[serde(rename = "cutOff", default, skip_serializing_if = "Option::is_none")]
+    pub cut_off: Option<u32>,
+    /// wall clock time in the timezone of the race
+    #[serde(
+        rename = "scheduledStart",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub scheduled_start: Option<String>,
+    #[serde(rename = "sortOrder", default, skip_serializing_if = "Option::is_none")]
+    pub sort_order: Option<i32>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct RacerResult {
     #[serde(rename = "registrationId")]
     pub registration_id: u32,
-    #[serde(rename = "startTime", serialize_with = "serialize_dt")]
+    #[serde(rename = "startTime")]
     pub start_time: DateTime<Utc>,
-    #[serde(rename = "finishTime", serialize_with = "serialize_dt")]
+    #[serde(rename = "finishTime")]
     pub finish_time: DateTime<Utc>,
 }
 
+/// Result as it is sent to the API
 #[derive(Debug, Serialize)]
-struct Results {
-    results: Vec<RacerResult>,
+struct ApiResult {
+    #[serde(rename = "registrationId")]
+    registration_id: u32,
+    #[serde(rename = "startTime")]
+    start_time: String,
+    #[serde(rename = "finishTime")]
+    finish_time: String,
 }
 
-fn serialize_dt<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    let s = date
-        .with_timezone(&Prague)
-        .format(DATE_TIME_FORMAT)
-        .to_string();
-    serializer.serialize_str(&s)
+impl ApiResult {
+    fn new(result: &RacerResult, tz: Tz) -> Self {
+        ApiResult {
+            registration_id: result.registration_id,
+            start_time: serialize_dt(result.start_time, tz),
+            finish_time: serialize_dt(result.finish_time, tz),
+        }
+    }
 }
 
-fn parse_dt<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let s: String = Deserialize::deserialize(deserializer)?;
-
-    // parse date without timezone
-    let naive =
-        NaiveDateTime::parse_from_str(&s, DATE_TIME_FORMAT).map_err(serde::de::Error::custom)?;
+#[derive(Debug, Serialize)]
+struct Results {
+    results: Vec<ApiResult>,
+}
 
-    // interpret as local time in Prague
-    let local_dt = Prague
-        .from_local_datetime(&naive)
-        .single()
-        .ok_or(serde::de::Error::custom(
-            "Failed to interpret local time in Prague",
-        ))?;
+fn serialize_dt(date: DateTime<Utc>, tz: Tz) -> String {
+    date.with_timezone(&tz).format(DATE_TIME_FORMAT).to_string()
+}
 
-    // convert to UTC
-    Ok(local_dt.with_timezone(&Utc))
+pub fn parse_dt(s: &str, tz: Tz) -> Result<DateTime<Utc>, Error> {
+    let naive = NaiveDateTime::parse_from_str(s, DATE_TIME_FORMAT)
+        .map_err(|err| Error::Decode(format!("{s:?}: {err}")))?;
+    Ok(localize(tz, naive))
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -109,62 +193,303 @@ impl Race {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// the server could not be reached
+    Network(String),
+    /// the credentials were rejected
+    Auth(StatusCode),
+    /// the server failed to handle the request
+    Server { status: StatusCode, message: String },
+    /// the server refused the request, e.g. a result it does not know
+    Refused { status: StatusCode, message: String },
+    /// the server rejected the submitted data
+    Validation(Vec<String>),
+    /// the response has unexpected format
+    Decode(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Network(err) => write!(f, "Network error: {err}"),
+            Error::Auth(status) => write!(f, "Authentication failed: {status}"),
+            Error::Server { status, message } => write!(f, "Server error {status}: {message}"),
+            Error::Refused { status, message } => write!(f, "Refused {status}: {message}"),
+            Error::Validation(messages) => write!(f, "Rejected: {}", messages.join("; ")),
+            Error::Decode(err) => write!(f, "Invalid response: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(value: reqwest::Error) -> Self {
+        Error::Network(value.to_string())
+    }
+}
+
+impl Error {
+    /// Whether repeating the same request may succeed. Authentication errors are retried
+    /// too, so nothing is lost while an expired token is being replaced.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Network(_) | Error::Server { .. } | Error::Auth(_) => true,
+            Error::Refused { status, .. } => {
+                *status == StatusCode::REQUEST_TIMEOUT || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            Error::Validation(_) | Error::Decode(_) => false,
+        }
+    }
+}
+
+/// Messages of a validation error response, e.g. `{"detail": [{"loc": [..], "msg": ".."}]}`
+fn validation_messages(body: &str) -> Vec<String> {
+    let detail = serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|json| json.get("detail").cloned());
+
+    match detail {
+        Some(serde_json::Value::String(message)) => vec![message],
+        Some(serde_json::Value::Array(details)) => details
+            .iter()
+            .map(|detail| {
+                let message = detail["msg"].as_str().unwrap_or_default();
+                let location = detail["loc"]
+                    .as_array()
+                    .map(|loc| {
+                        loc.iter()
+                            .map(|part| match part {
+                                serde_json::Value::String(s) => s.clone(),
+                                other => other.to_string(),
+                            })
+                            .collect::<Vec<_>>()
+                            .join(".")
+                    })
+                    .unwrap_or_default();
+                if location.is_empty() {
+                    message.to_string()
+                } else {
+                    format!("{location}: {message}")
+                }
+            })
+            .collect(),
+        _ if body.trim().is_empty() => vec!["no details".to_string()],
+        _ => vec![body.trim().to_string()],
+    }
+}
+
+async fn decode<T: DeserializeOwned>(resp: Response) -> Result<T, Error> {
+    let body = resp.text().await?;
+    serde_json::from_str(&body).map_err(|err| Error::Decode(err.to_string()))
+}
+
+async fn check_status(resp: Response) -> Result<Response, Error> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+
+    let body = resp.text().await.unwrap_or_default();
+    Err(match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Error::Auth(status),
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => {
+            Error::Validation(validation_messages(&body))
+        }
+        _ if status.is_server_error() => Error::Server {
+            status,
+            message: body,
+        },
+        _ => Error::Refused {
+            status,
+            message: body,
+        },
+    })
+}
+
+/// Credentials sent with every request
+#[derive(Debug, Clone, PartialEq)]
+pub enum Auth {
+    None,
+    Basic { username: String, password: String },
+    Bearer(String),
+    ApiKey { header: String, key: String },
+}
+
+impl Auth {
+    fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        match self {
+            Auth::None => request,
+            Auth::Basic { username, password } => request.basic_auth(username, Some(password)),
+            Auth::Bearer(token) => request.bearer_auth(token),
+            Auth::ApiKey { header, key } => request.header(header, key),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct RaceRestAPI {
     client: reqwest::Client,
     url: String,
-    username: String,
-    password: String,
+    auth: Auth,
 }
 
 impl RaceRestAPI {
-    pub fn new(url: &str, username: &str, password: &str) -> Self {
+    pub fn new(url: &str, auth: Auth) -> Self {
         RaceRestAPI {
-            client: Default::default(),
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
             url: url.to_string(),
-            username: username.to_string(),
-            password: password.to_string(),
+            auth,
         }
     }
 
-    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, Box<dyn std::error::Error>> {
+    async fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        let resp = self.auth.apply(request).send().await?;
+        check_status(resp).await
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, Error> {
         let resp = self
-            .client
-            .get(format!("{}/{}", self.url, path.trim_start_matches('/')))
-            .basic_auth(&self.username, Some(&self.password))
-            .send()
+            .send(
+                self.client
+                    .get(format!("{}/{}", self.url, path.trim_start_matches('/'))),
+            )
             .await?;
-        Ok(resp.json().await?)
+        decode(resp).await
     }
 
-    pub async fn races(&self) -> Result<Vec<Race>, Box<dyn std::error::Error>> {
-        self.get("/races").await
+    /// Races with their dates parsed in the timezone of each race
+    pub async fn races(&self, timezone: impl Fn(u32) -> Tz) -> Result<Vec<Race>, Error> {
+        let races: Vec<ApiRace> = self.get("/races").await?;
+        races
+            .into_iter()
+            .map(|race| {
+                Ok(Race {
+                    date_of_event: parse_dt(&race.date_of_event, timezone(race.id))?,
+                    id: race.id,
+                    name: race.name,
+                    description: race.description,
+                })
+            })
+            .collect()
     }
 
-    pub async fn registrations(
-        &self,
-        race_id: u32,
-    ) -> Result<Vec<Racer>, Box<dyn std::error::Error>> {
+    pub async fn registrations(&self, race_id: u32) -> Result<Vec<Racer>, Error> {
         self.get(&format!("/races/{race_id}/registrations")).await
     }
 
     pub async fn results(
         &self,
         race_id: u32,
+        tz: Tz,
         results: Vec<RacerResult>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let body = Results { results };
+    ) -> Result<(), Error> {
+        let body = Results {
+            results: results
+                .iter()
+                .map(|result| ApiResult::new(result, tz))
+                .collect(),
+        };
+        self.send(
+            self.client
+                .post(format!("{0}/races/{race_id}/results", self.url))
+                .json(&body),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Create or replace the result of a single racer
+    pub async fn upsert_result(
+        &self,
+        race_id: u32,
+        tz: Tz,
+        result: &RacerResult,
+    ) -> Result<(), Error> {
+        self.send(
+            self.client
+                .put(format!(
+                    "{0}/races/{race_id}/results/{1}",
+                    self.url, result.registration_id
+                ))
+                .json(&ApiResult::new(result, tz)),
+        )
+        .await?;
+        Ok(())
+    }
 
+    /// Register a racer on the spot, returns the registration created by the server.
+    /// The local id is sent as the idempotency key, so a retried request whose response
+    /// was lost does not register the racer twice.
+    pub async fn register(&self, race_id: u32, racer: &Racer) -> Result<Racer, Error> {
         let resp = self
-            .client
-            .post(format!("{0}/races/{race_id}/results", self.url))
-            .basic_auth(&self.username, Some(&self.password))
-            .json(&body)
-            .send()
+            .send(
+                self.client
+                    .post(format!("{0}/races/{race_id}/registrations", self.url))
+                    .header("Idempotency-Key", format!("{race_id}-{}", racer.id))
+                    .json(racer),
+            )
             .await?;
+        decode(resp).await
+    }
 
-        println!("{:#?}", resp.text().await?);
-
+    pub async fn delete_result(&self, race_id: u32, registration_id: u32) -> Result<(), Error> {
+        self.send(self.client.delete(format!(
+            "{0}/races/{race_id}/results/{registration_id}",
+            self.url
+        )))
+        .await?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_times() {
+        let tz = chrono_tz::Europe::Prague;
+        let dt = parse_dt("2025-10-26 02:30:00.000000", tz).unwrap();
+        assert_eq!(dt.to_rfc3339(), "2025-10-26T00:30:00+00:00");
+        assert_eq!(serialize_dt(dt, tz), "2025-10-26 02:30:00.000000");
+        assert_eq!(
+            serialize_dt(dt, chrono_tz::America::New_York),
+            "2025-10-25 20:30:00.000000"
+        );
+        assert!(matches!(parse_dt("26.10.2025", tz), Err(Error::Decode(_))));
+    }
+
+    #[test]
+    fn validation_details() {
+        let body = r#"{"detail": [{"loc": ["body", "results", 0, "finishTime"], "msg": "field required", "type": "missing"}]}"#;
+        assert_eq!(
+            validation_messages(body),
+            vec!["body.results.0.finishTime: field required"]
+        );
+        assert_eq!(
+            validation_messages(r#"{"detail": "Race is closed"}"#),
+            vec!["Race is closed"]
+        );
+        assert_eq!(validation_messages("Bad request"), vec!["Bad request"]);
+    }
+
+    #[test]
+    fn retryable() {
+        assert!(Error::Auth(StatusCode::UNAUTHORIZED).is_retryable());
+        assert!(Error::Network("timeout".into()).is_retryable());
+        assert!(!Error::Decode("missing field".into()).is_retryable());
+        let refused = |status| Error::Refused {
+            status,
+            message: String::new(),
+        };
+        assert!(refused(StatusCode::TOO_MANY_REQUESTS).is_retryable());
+        assert!(refused(StatusCode::REQUEST_TIMEOUT).is_retryable());
+        assert!(!refused(StatusCode::NOT_FOUND).is_retryable());
+        assert!(!refused(StatusCode::CONFLICT).is_retryable());
+    }
+}
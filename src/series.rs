@@ -0,0 +1,464 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use chrono::Datelike;
+use serde::Deserialize;
+use tracing::{error, info};
+
+use crate::config::app_dir;
+use crate::race::{is_walk_in, Race, Racer};
+use crate::storage::is_local_race;
+
+fn default_true() -> bool {
+    true
+}
+
+/// Cup scored over several races, defined in `series.toml` in the app directory
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Series {
+    pub name: String,
+    pub races: Vec<u32>,
+    /// points for the first, second, … place in a category
+    pub points: Vec<u32>,
+    /// number of the best results that count, all if not set
+    pub best: Option<usize>,
+    /// series category of the race categories, unmapped categories keep their name
+    #[serde(default)]
+    pub categories: HashMap<String, String>,
+    /// registration ids of the registration API identify the same racer in all races
+    #[serde(default = "default_true")]
+    pub match_registration_ids: bool,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct SeriesFile {
+    #[serde(default)]
+    series: Vec<Series>,
+}
+
+fn series_path() -> PathBuf {
+    app_dir().join("series.toml")
+}
+
+pub fn load_series() -> Vec<Series> {
+    let path = series_path();
+    match fs::read_to_string(&path) {
+        Ok(toml_str) => match toml::from_str::<SeriesFile>(&toml_str) {
+            Ok(file) => {
+                info!("Loaded series {path:?}");
+                file.series
+            }
+            Err(err) => {
+                error!("Failed to parse {path:?}: {err}");
+                Vec::new()
+            }
+        },
+        Err(err) => {
+            if err.kind() != ErrorKind::NotFound {
+                error!("Failed to load {path:?}: {err}");
+            }
+            Vec::new()
+        }
+    }
+}
+
+/// Registration of a racer in one race of the series
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntryKey {
+    pub race_id: u32,
+    pub registration_id: u32,
+}
+
+impl fmt::Display for EntryKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.race_id, self.registration_id)
+    }
+}
+
+/// Operator decisions whether two entries are the same racer, by `pair_key`
+pub type MatchDecisions = HashMap<String, bool>;
+
+pub fn pair_key(a: EntryKey, b: EntryKey) -> String {
+    let (a, b) = if a < b { (a, b) } else { (b, a) };
+    format!("{a}={b}")
+}
+
+fn decisions_path() -> PathBuf {
+    app_dir().join("series_matches.json")
+}
+
+pub fn load_match_decisions() -> MatchDecisions {
+    let Ok(json) = fs::read_to_string(decisions_path()) else {
+        return MatchDecisions::new();
+    };
+    serde_json::from_str(&json)
+        .inspect_err(|err| error!("Failed to load series matches: {err}"))
+        .unwrap_or_default()
+}
+
+pub fn save_match_decisions(decisions: &MatchDecisions) {
+    let json = serde_json::to_string(decisions).unwrap();
+    if let Err(err) = fs::write(decisions_path(), json) {
+        error!("Failed to save series matches: {err}");
+    }
+}
+
+/// Result of a racer in one race of the series
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeriesEntry {
+    pub key: EntryKey,
+    pub first_name: String,
+    pub last_name: String,
+    pub birth_year: Option<i32>,
+    /// points by series category
+    pub points: HashMap<String, u32>,
+}
+
+impl SeriesEntry {
+    fn name(&self) -> String {
+        format!("{} {}", self.first_name, self.last_name)
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    }
+
+    /// Registration id stable across the races of the registration API
+    fn global_id(&self) -> Option<u32> {
+        (!is_local_race(self.key.race_id) && !is_walk_in(self.key.registration_id))
+            .then_some(self.key.registration_id)
+    }
+}
+
+impl fmt::Display for SeriesEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.first_name, self.last_name)?;
+        if let Some(year) = self.birth_year {
+            write!(f, " ({year})")?;
+        }
+        write!(f, ", race {}", self.key.race_id)
+    }
+}
+
+/// Entry that may be the same racer as an earlier one, to be decided by the operator
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchReview {
+    pub entry: SeriesEntry,
+    pub candidate: SeriesEntry,
+}
+
+impl MatchReview {
+    pub fn key(&self) -> String {
+        pair_key(self.entry.key, self.candidate.key)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StandingRow {
+    pub rank: u32,
+    pub name: String,
+    pub birth_year: Option<i32>,
+    /// points in each race of the series and whether they count
+    pub races: Vec<Option<(u32, bool)>>,
+    pub total: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CategoryStandings {
+    pub category: String,
+    pub rows: Vec<StandingRow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SeriesStandings {
+    pub categories: Vec<CategoryStandings>,
+    /// undecided matches, such entries are counted as separate racers until decided
+    pub reviews: Vec<MatchReview>,
+}
+
+impl Series {
+    fn entry(&self, race_id: u32, racer: &Racer) -> SeriesEntry {
+        let mut points: HashMap<String, u32> = HashMap::new();
+        for (category, rank) in &racer.categories_rank {
+            let series_category = self
+                .categories
+                .get(&category.0)
+                .cloned()
+                .unwrap_or(category.0.clone());
+            let race_points = self.points.get(*rank as usize - 1).copied().unwrap_or(0);
+            let best = points.entry(series_category).or_default();
+            *best = (*best).max(race_points);
+        }
+        SeriesEntry {
+            key: EntryKey {
+                race_id,
+                registration_id: racer.id,
+            },
+            first_name: racer.first_name.clone(),
+            last_name: racer.last_name.clone(),
+            birth_year: racer.birth_date.map(|date| date.year()),
+            points,
+        }
+    }
+
+    /// Ranked results of the races of the series
+    pub fn entries(&self, race: &Race) -> Vec<SeriesEntry> {
+        race.racers
+            .iter()
+            .filter(|racer| !racer.categories_rank.is_empty())
+            .map(|racer| self.entry(race.id, racer))
+            .collect()
+    }
+
+    /// Group the entries of all races into racers
+    fn match_entries(
+        &self,
+        entries: Vec<SeriesEntry>,
+        decisions: &MatchDecisions,
+    ) -> (Vec<Vec<SeriesEntry>>, Vec<MatchReview>) {
+        let mut competitors: Vec<Vec<SeriesEntry>> = Vec::new();
+        let mut reviews = Vec::new();
+
+        for entry in entries {
+            let in_race = |competitor: &Vec<SeriesEntry>| {
+                competitor
+                    .iter()
+                    .any(|other| other.key.race_id == entry.key.race_id)
+            };
+
+            let by_id = entry
+                .global_id()
+                .filter(|_| self.match_registration_ids)
+                .and_then(|id| {
+                    competitors.iter().position(|competitor| {
+                        !in_race(competitor)
+                            && competitor.iter().any(|other| other.global_id() == Some(id))
+                    })
+                });
+            if let Some(index) = by_id {
+                competitors[index].push(entry);
+                continue;
+            }
+
+            let mut confident = Vec::new();
+            let mut undecided = Vec::new();
+            let mut decided = None;
+            for (index, competitor) in competitors.iter().enumerate() {
+                let first = &competitor[0];
+                if in_race(competitor) || first.name() != entry.name() {
+                    continue;
+                }
+                match decisions.get(&pair_key(entry.key, first.key)) {
+                    Some(true) => decided = decided.or(Some(index)),
+                    Some(false) => {}
+                    None => match (entry.birth_year, first.birth_year) {
+                        (Some(a), Some(b)) if a != b => {}
+                        (Some(_), Some(_)) => confident.push(index),
+                        _ => undecided.push(index),
+                    },
+                }
+            }
+
+            let matched = match (decided, confident.as_slice(), undecided.is_empty()) {
+                (Some(index), _, _) => Some(index),
+                (None, [index], true) => Some(*index),
+                _ => {
+                    for index in confident.into_iter().chain(undecided) {
+                        reviews.push(MatchReview {
+                            entry: entry.clone(),
+                            candidate: competitors[index][0].clone(),
+                        });
+                    }
+                    None
+                }
+            };
+            match matched {
+                Some(index) => competitors[index].push(entry),
+                None => competitors.push(vec![entry]),
+            }
+        }
+        (competitors, reviews)
+    }
+
+    /// Standings of the series categories from the loaded races
+    pub fn standings(&self, races: &[Race], decisions: &MatchDecisions) -> SeriesStandings {
+        let entries = self
+            .races
+            .iter()
+            .filter_map(|race_id| races.iter().find(|race| race.id == *race_id))
+            .flat_map(|race| self.entries(race))
+            .collect();
+        let (competitors, reviews) = self.match_entries(entries, decisions);
+        SeriesStandings {
+            categories: self.category_standings(&competitors),
+            reviews,
+        }
+    }
+
+    fn category_standings(&self, competitors: &[Vec<SeriesEntry>]) -> Vec<CategoryStandings> {
+        let mut categories: Vec<String> = competitors
+            .iter()
+            .flatten()
+            .flat_map(|entry| entry.points.keys().cloned())
+            .collect();
+        categories.sort();
+        categories.dedup();
+
+        let best = self.best.unwrap_or(self.races.len());
+        categories
+            .into_iter()
+            .map(|category| {
+                let mut rows: Vec<StandingRow> = competitors
+                    .iter()
+                    .filter(|competitor| {
+                        competitor
+                            .iter()
+                            .any(|entry| entry.points.contains_key(&category))
+                    })
+                    .map(|competitor| {
+                        let points: Vec<Option<u32>> = self
+                            .races
+                            .iter()
+                            .map(|race_id| {
+                                competitor
+                                    .iter()
+                                    .find(|entry| entry.key.race_id == *race_id)
+                                    .and_then(|entry| entry.points.get(&category).copied())
+                            })
+                            .collect();
+
+                        // indexes of the races that count, best points first
+                        let mut counted: Vec<usize> =
+                            (0..points.len()).filter(|i| points[*i].is_some()).collect();
+                        counted.sort_by_key(|i| std::cmp::Reverse(points[*i]));
+                        counted.truncate(best);
+
+                        let first = &competitor[0];
+                        StandingRow {
+                            rank: 0,
+                            name: format!("{} {}", first.first_name, first.last_name),
+                            birth_year: competitor.iter().find_map(|entry| entry.birth_year),
+                            races: points
+                                .iter()
+                                .enumerate()
+                                .map(|(i, points)| points.map(|p| (p, counted.contains(&i))))
+                                .collect(),
+                            total: counted.iter().filter_map(|i| points[*i]).sum(),
+                        }
+                    })
+                    .collect();
+                rows.sort_by(|a, b| b.total.cmp(&a.total).then(a.name.cmp(&b.name)));
+                for (index, row) in rows.iter_mut().enumerate() {
+                    row.rank = index as u32 + 1;
+                }
+                CategoryStandings { category, rows }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(
+        race_id: u32,
+        registration_id: u32,
+        name: &str,
+        birth_year: Option<i32>,
+        points: u32,
+    ) -> SeriesEntry {
+        let (first_name, last_name) = name.split_once(' ').unwrap();
+        SeriesEntry {
+            key: EntryKey {
+                race_id,
+                registration_id,
+            },
+            first_name: first_name.into(),
+            last_name: last_name.into(),
+            birth_year,
+            points: HashMap::from([("Men".to_string(), points)]),
+        }
+    }
+
+    fn series() -> Series {
+        Series {
+            name: "Cup".into(),
+            races: vec![1, 2, 3],
+            points: vec![10, 8, 6],
+            best: Some(2),
+            categories: HashMap::new(),
+            match_registration_ids: true,
+        }
+    }
+
+    #[test]
+    fn matching() {
+        let series = series();
+        let entries = vec![
+            entry(1, 10, "Jan Novak", Some(1980), 10),
+            entry(1, 11, "Petr Svoboda", None, 8),
+            entry(2, 10, "Jan Novák", Some(1980), 6),
+            entry(2, 20, "jan  novak", Some(1980), 10),
+            entry(2, 21, "Petr Svoboda", Some(1990), 8),
+            entry(3, 30, "Jan Novak", Some(1981), 8),
+        ];
+        let (competitors, reviews) = series.match_entries(entries.clone(), &MatchDecisions::new());
+        let keys: Vec<Vec<u32>> = competitors
+            .iter()
+            .map(|c| c.iter().map(|e| e.key.registration_id).collect())
+            .collect();
+        // same registration id, same name and birth year, different birth year
+        assert_eq!(
+            keys,
+            vec![vec![10, 10], vec![11], vec![20], vec![21], vec![30]]
+        );
+        // the same name in the same race is never the same racer, no birth year to compare
+        assert_eq!(reviews.len(), 1);
+        assert_eq!(reviews[0].key(), "1:11=2:21");
+
+        let decisions = MatchDecisions::from([(reviews[0].key(), true)]);
+        let (competitors, reviews) = series.match_entries(entries, &decisions);
+        assert_eq!(competitors.len(), 4);
+        assert!(reviews.is_empty());
+    }
+
+    #[test]
+    fn best_of() {
+        let series = series();
+        let entries = vec![
+            entry(1, 1, "Jan Novak", Some(1980), 10),
+            entry(2, 1, "Jan Novak", Some(1980), 6),
+            entry(3, 1, "Jan Novak", Some(1980), 8),
+            entry(1, 2, "Petr Svoboda", Some(1990), 8),
+            entry(2, 2, "Petr Svoboda", Some(1990), 10),
+        ];
+        let (competitors, _) = series.match_entries(entries, &MatchDecisions::new());
+        let standings = series.category_standings(&competitors);
+        assert_eq!(standings.len(), 1);
+        assert_eq!(
+            standings[0].rows,
+            vec![
+                StandingRow {
+                    rank: 1,
+                    name: "Jan Novak".into(),
+                    birth_year: Some(1980),
+                    races: vec![Some((10, true)), Some((6, false)), Some((8, true))],
+                    total: 18,
+                },
+                StandingRow {
+                    rank: 2,
+                    name: "Petr Svoboda".into(),
+                    birth_year: Some(1990),
+                    races: vec![Some((8, true)), Some((10, true)), None],
+                    total: 18,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,482 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
+
+use crate::race::{Race, Racer, StartNumber, Track};
+use crate::rfid_reader;
+use crate::sync::{Primary, StationStatus, SyncRequest, SyncResponse};
+
+/// Racer as sent to the operators
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RacerView {
+    pub id: u32,
+    pub start_number: StartNumber,
+    pub first_name: String,
+    pub last_name: String,
+    pub track: String,
+    pub categories: Vec<String>,
+    pub start: Option<DateTime<Utc>>,
+    pub finish: Option<DateTime<Utc>>,
+    /// official running time formatted to the timing precision of the race
+    pub time: String,
+    pub track_rank: Option<u32>,
+    pub categories_rank: HashMap<String, u32>,
+    /// DNF or out of time
+    pub status: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrackView {
+    pub track: String,
+    pub start: Option<DateTime<Utc>>,
+    pub racers: usize,
+    pub finished: usize,
+}
+
+/// Whole race state, sent on every change
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RaceView {
+    pub id: u32,
+    pub timezone: String,
+    pub tracks: Vec<TrackView>,
+    pub racers: Vec<RacerView>,
+}
+
+impl RacerView {
+    fn new(race: &Race, racer: &Racer) -> Self {
+        RacerView {
+            id: racer.id,
+            start_number: racer.start_number.clone(),
+            first_name: racer.first_name.clone(),
+            last_name: racer.last_name.clone(),
+            track: racer.track.0.clone(),
+            categories: racer.categories.iter().map(|c| c.0.clone()).collect(),
+            start: racer.start,
+            finish: racer.finish,
+            time: race.config.timing.format(racer.time),
+            track_rank: racer.track_rank,
+            categories_rank: racer
+                .categories_rank
+                .iter()
+                .map(|(category, rank)| (category.0.clone(), *rank))
+                .collect(),
+            status: race.status(racer),
+        }
+    }
+}
+
+impl RaceView {
+    pub fn new(race: &Race) -> Self {
+        RaceView {
+            id: race.id,
+            timezone: race.timezone.to_string(),
+            tracks: race
+                .tracks_stats()
+                .into_iter()
+                .map(|stats| TrackView {
+                    track: stats.track.0,
+                    start: stats.start,
+                    racers: stats.racers,
+                    finished: stats.finished,
+                })
+                .collect(),
+            racers: race
+                .racers
+                .iter()
+                .map(|racer| RacerView::new(race, racer))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StartRequest {
+    pub track: String,
+    /// now if not set
+    pub time: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FinishRequest {
+    pub start_number: StartNumber,
+    /// now if not set
+    pub time: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EditRequest {
+    pub start_number: StartNumber,
+    /// removes the finish if not set
+    pub time: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DnfRequest {
+    pub start_number: StartNumber,
+    pub dnf: bool,
+}
+
+/// Race shared by the HTTP handlers, the WebSocket clients and the readers
+#[derive(Clone)]
+pub struct ServerState {
+    race: Arc<Mutex<Race>>,
+    updates: broadcast::Sender<RaceView>,
+    /// stations synchronizing their race events with this server
+    primary: Arc<Mutex<Primary>>,
+}
+
+impl ServerState {
+    pub fn new(race: Race) -> Self {
+        let (updates, _) = broadcast::channel(64);
+        ServerState {
+            race: Arc::new(Mutex::new(race)),
+            updates,
+            primary: Arc::new(Mutex::new(Primary::default())),
+        }
+    }
+
+    fn view(&self) -> RaceView {
+        RaceView::new(&self.race.lock().expect("Race lock poisoned"))
+    }
+
+    /// Change the race and send the new state to all WebSocket clients
+    fn update<R>(&self, change: impl FnOnce(&mut Race) -> R) -> R {
+        let (result, view) = {
+            let mut race = self.race.lock().expect("Race lock poisoned");
+            let result = change(&mut race);
+            (result, RaceView::new(&race))
+        };
+        self.broadcast(view);
+        result
+    }
+
+    fn broadcast(&self, view: RaceView) {
+        // no receivers just means no operator is connected
+        let _ = self.updates.send(view);
+    }
+}
+
+async fn race(State(state): State<ServerState>) -> Json<RaceView> {
+    Json(state.view())
+}
+
+async fn start(
+    State(state): State<ServerState>,
+    Json(request): Json<StartRequest>,
+) -> Result<Json<RaceView>, (StatusCode, String)> {
+    let track = Track(request.track);
+    let known = state
+        .race
+        .lock()
+        .expect("Race lock poisoned")
+        .tracks
+        .contains(&track);
+    if !known {
+        return Err((StatusCode::NOT_FOUND, format!("Unknown track {track}")));
+    }
+    state.update(|race| race.start(track, request.time.unwrap_or_else(Utc::now)));
+    Ok(Json(state.view()))
+}
+
+/// 404 unless a racer has the start number
+fn known_start_number(
+    state: &ServerState,
+    start_number: &StartNumber,
+) -> Result<(), (StatusCode, String)> {
+    let known = state
+        .race
+        .lock()
+        .expect("Race lock poisoned")
+        .racers
+        .iter()
+        .any(|racer| racer.start_number == *start_number);
+    if !known {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Unknown start number {start_number}"),
+        ));
+    }
+    Ok(())
+}
+
+async fn finish(
+    State(state): State<ServerState>,
+    Json(request): Json<FinishRequest>,
+) -> Result<Json<RaceView>, (StatusCode, String)> {
+    known_start_number(&state, &request.start_number)?;
+    state.update(|race| {
+        race.finish_start_number(request.start_number, request.time.unwrap_or_else(Utc::now))
+    });
+    Ok(Json(state.view()))
+}
+
+async fn edit(
+    State(state): State<ServerState>,
+    Json(request): Json<EditRequest>,
+) -> Result<Json<RaceView>, (StatusCode, String)> {
+    known_start_number(&state, &request.start_number)?;
+    state.update(|race| race.edit_finish_start_number(request.start_number, request.time));
+    Ok(Json(state.view()))
+}
+
+async fn dnf(
+    State(state): State<ServerState>,
+    Json(request): Json<DnfRequest>,
+) -> Result<Json<RaceView>, (StatusCode, String)> {
+    known_start_number(&state, &request.start_number)?;
+    state.update(|race| race.set_dnf(request.start_number, request.dnf));
+    Ok(Json(state.view()))
+}
+
+async fn sync(
+    State(state): State<ServerState>,
+    Json(request): Json<SyncRequest>,
+) -> Result<Json<SyncResponse>, (StatusCode, String)> {
+    let (response, view) = {
+        let mut race = state.race.lock().expect("Race lock poisoned");
+        let mut primary = state.primary.lock().expect("Sync lock poisoned");
+        let known = race.sync_known();
+        let response = primary.sync(&mut race, request, Utc::now());
+        // stations poll every second, so only new events are sent to the operators
+        let changed = race.sync_known() != known;
+        (response, changed.then(|| RaceView::new(&race)))
+    };
+    if let Some(view) = view {
+        state.broadcast(view);
+    }
+    response.map(Json).map_err(|err| {
+        warn!("{err}");
+        (StatusCode::CONFLICT, err)
+    })
+}
+
+async fn stations(State(state): State<ServerState>) -> Json<Vec<StationStatus>> {
+    let race = state.race.lock().expect("Race lock poisoned");
+    let primary = state.primary.lock().expect("Sync lock poisoned");
+    Json(primary.stations(&race, Utc::now()))
+}
+
+async fn updates(ws: WebSocketUpgrade, State(state): State<ServerState>) -> Response {
+    ws.on_upgrade(move |socket| stream_updates(socket, state))
+}
+
+/// Send the race to a WebSocket client now and after every change
+async fn stream_updates(mut socket: WebSocket, state: ServerState) {
+    let mut updates = state.updates.subscribe();
+    let mut view = state.view();
+    loop {
+        let text = match serde_json::to_string(&view) {
+            Ok(text) => text,
+            Err(err) => {
+                error!("Failed to serialize race: {err}");
+                return;
+            }
+        };
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            return;
+        }
+        view = match updates.recv().await {
+            Ok(view) => view,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!("WebSocket client skipped {skipped} updates");
+                state.view()
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+    }
+}
+
+/// HTTP API: `GET /race`, `POST /start`, `POST /finish`, `POST /edit`, `POST /dnf`,
+/// the `/updates` WebSocket stream and `POST /sync`, `GET /stations` of the stations
+/// synchronizing with this server
+pub fn router(state: ServerState) -> Router {
+    Router::new()
+        .route("/race", get(race))
+        .route("/start", post(start))
+        .route("/finish", post(finish))
+        .route("/edit", post(edit))
+        .route("/dnf", post(dnf))
+        .route("/updates", get(updates))
+        .route("/sync", post(sync))
+        .route("/stations", get(stations))
+        .with_state(state)
+}
+
+/// Finish racers by the tags read by the RFID readers
+pub async fn read_tags(state: ServerState, devices: Vec<String>) {
+    let (tx, mut rfid_rx) = broadcast::channel::<rfid_reader::Event>(128);
+    for serial in devices {
+        tokio::spawn(rfid_reader::rfid_serial(serial, tx.clone()));
+    }
+
+    loop {
+        let event = match rfid_rx.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                error!("Skipped {skipped} RFID events");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        match event {
+            rfid_reader::Event::Connected(device) => info!("RFID {device} connected"),
+            rfid_reader::Event::Disconnected { device, error } => {
+                error!("RFID {device} disconnected: {error:?}")
+            }
+            rfid_reader::Event::Tag(tag) => {
+                info!("Tag {tag}");
+                state.update(|race| race.tag_finished(&tag, Some(Utc::now())));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::config::RaceConfig;
+    use crate::race::test_registration;
+
+    fn state() -> ServerState {
+        ServerState::new(Race::for_test(
+            "primary",
+            RaceConfig::default(),
+            vec![
+                test_registration(1, 1, "5 Km"),
+                test_registration(2, 2, "5 Km"),
+            ],
+        ))
+    }
+
+    fn racer(view: &RaceView, start_number: &str) -> RacerView {
+        let start_number: StartNumber = start_number.parse().unwrap();
+        view.racers
+            .iter()
+            .find(|racer| racer.start_number == start_number)
+            .cloned()
+            .unwrap()
+    }
+
+    #[test]
+    fn timing() {
+        let state = state();
+        let mut updates = state.updates.subscribe();
+        let t0 = Utc::now();
+
+        let unknown = StartRequest {
+            track: "10 Km".into(),
+            time: Some(t0),
+        };
+        let err = block_on(start(State(state.clone()), Json(unknown))).unwrap_err();
+        assert_eq!(err.0, StatusCode::NOT_FOUND);
+        assert!(updates.try_recv().is_err());
+
+        let request = StartRequest {
+            track: "5 Km".into(),
+            time: Some(t0),
+        };
+        let Json(view) = block_on(start(State(state.clone()), Json(request))).unwrap();
+        assert_eq!(view.tracks[0].start, Some(t0));
+        assert_eq!(racer(&view, "2").start, Some(t0));
+        assert_eq!(updates.try_recv().unwrap(), view);
+
+        let request = FinishRequest {
+            start_number: "2".parse().unwrap(),
+            time: Some(t0 + TimeDelta::minutes(20)),
+        };
+        let Json(view) = block_on(finish(State(state.clone()), Json(request))).unwrap();
+        let finished = racer(&view, "2");
+        assert_eq!(finished.finish, Some(t0 + TimeDelta::minutes(20)));
+        assert_eq!(finished.time, "00:20:00.000");
+        assert_eq!(finished.track_rank, Some(1));
+        assert_eq!(view.tracks[0].finished, 1);
+
+        let request = EditRequest {
+            start_number: "2".parse().unwrap(),
+            time: None,
+        };
+        let Json(view) = block_on(edit(State(state.clone()), Json(request))).unwrap();
+        assert_eq!(racer(&view, "2").finish, None);
+        assert_eq!(racer(&view, "2").track_rank, None);
+        assert_eq!(view.tracks[0].finished, 0);
+        assert_eq!(block_on(race(State(state))).0, view);
+    }
+
+    #[test]
+    fn unknown_start_number() {
+        let state = state();
+        let mut updates = state.updates.subscribe();
+        let start_number: StartNumber = "42".parse().unwrap();
+
+        let request = FinishRequest {
+            start_number: start_number.clone(),
+            time: None,
+        };
+        let err = block_on(finish(State(state.clone()), Json(request))).unwrap_err();
+        assert_eq!(err.0, StatusCode::NOT_FOUND);
+        let request = EditRequest {
+            start_number: start_number.clone(),
+            time: None,
+        };
+        let err = block_on(edit(State(state.clone()), Json(request))).unwrap_err();
+        assert_eq!(err.0, StatusCode::NOT_FOUND);
+        let request = DnfRequest {
+            start_number,
+            dnf: true,
+        };
+        let err = block_on(dnf(State(state.clone()), Json(request))).unwrap_err();
+        assert_eq!(err.0, StatusCode::NOT_FOUND);
+
+        assert!(updates.try_recv().is_err());
+        let race = state.race.lock().unwrap();
+        assert!(race.events_after(&HashMap::new()).is_empty());
+    }
+
+    #[test]
+    fn sync_updates() {
+        let state = state();
+        let mut updates = state.updates.subscribe();
+        let request = SyncRequest {
+            race_id: 1,
+            station: "finish".into(),
+            instance: "a".into(),
+            events: vec![],
+            known: HashMap::new(),
+        };
+
+        // polling without new events does not wake up the operators
+        let Json(response) = block_on(sync(State(state.clone()), Json(request.clone()))).unwrap();
+        assert!(response.events.is_empty());
+        assert!(updates.try_recv().is_err());
+
+        let mut station = Race::for_test(
+            "finish",
+            RaceConfig::default(),
+            vec![test_registration(1, 1, "5 Km")],
+        );
+        station.start(Track("5 Km".into()), Utc::now());
+        let request = SyncRequest {
+            events: station.events_after(&HashMap::new()),
+            ..request
+        };
+        let Json(response) = block_on(sync(State(state.clone()), Json(request))).unwrap();
+        assert!(response.events.is_empty());
+        assert!(updates.try_recv().unwrap().tracks[0].start.is_some());
+
+        let Json(stations) = block_on(stations(State(state)));
+        assert_eq!(stations.len(), 2);
+    }
+}
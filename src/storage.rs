@@ -0,0 +1,516 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::config::{app_dir, StorageBackend};
+use crate::race_events::{load_events, Event};
+use crate::restclient::{Race, Racer};
+
+/// Registrations as they were received from the registration API
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RegistrationsSnapshot {
+    pub fetched: DateTime<Utc>,
+    pub racers: Vec<Racer>,
+}
+
+/// Races as they were received from the registration API
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RacesSnapshot {
+    pub fetched: DateTime<Utc>,
+    pub races: Vec<Race>,
+}
+
+fn races_path() -> PathBuf {
+    app_dir().join("races.json")
+}
+
+pub fn save_races(snapshot: &RacesSnapshot) {
+    let json = serde_json::to_string(snapshot).unwrap();
+    if let Err(err) = fs::write(races_path(), json) {
+        error!("Failed to save races: {err}");
+    }
+}
+
+pub fn cached_races() -> Option<RacesSnapshot> {
+    let json = fs::read_to_string(races_path()).ok()?;
+    serde_json::from_str(&json)
+        .inspect_err(|err| error!("Failed to load races: {err}"))
+        .ok()
+}
+
+/// Races created on this computer get ids from this value up, so they never clash with
+/// races of the registration API
+pub const LOCAL_RACE_ID_START: u32 = 1_000_000;
+
+pub fn is_local_race(race_id: u32) -> bool {
+    race_id >= LOCAL_RACE_ID_START
+}
+
+fn local_races_path() -> PathBuf {
+    app_dir().join("local_races.json")
+}
+
+/// Races with registrations imported from a file instead of the registration API
+pub fn local_races() -> Vec<Race> {
+    let Ok(json) = fs::read_to_string(local_races_path()) else {
+        return Vec::new();
+    };
+    serde_json::from_str(&json)
+        .inspect_err(|err| error!("Failed to load local races: {err}"))
+        .unwrap_or_default()
+}
+
+/// Create a local race with the given registrations
+pub fn create_local_race(
+    name: &str,
+    date_of_event: DateTime<Utc>,
+    backend: StorageBackend,
+    racers: Vec<Racer>,
+) -> Race {
+    let mut races = local_races();
+    let race = Race {
+        id: races
+            .iter()
+            .map(|race| race.id + 1)
+            .max()
+            .unwrap_or(LOCAL_RACE_ID_START),
+        name: name.to_string(),
+        description: None,
+        date_of_event,
+    };
+    races.push(race.clone());
+    let json = serde_json::to_string(&races).unwrap();
+    if let Err(err) = fs::write(local_races_path(), json) {
+        error!("Failed to save local races: {err}");
+    }
+
+    open(backend, race.id).save_registrations(&RegistrationsSnapshot {
+        fetched: Utc::now(),
+        racers,
+    });
+    race
+}
+
+/// Persistent storage of a single race
+pub trait Storage: Send {
+    /// All events in the order they were appended
+    fn events(&self) -> Vec<Event>;
+    fn append(&mut self, event: &Event);
+    /// Save the registrations of a fetch, also unchanged ones to keep the fetch time
+    fn save_registrations(&mut self, snapshot: &RegistrationsSnapshot);
+    /// The most recent registrations snapshot
+    fn registrations(&self) -> Option<RegistrationsSnapshot>;
+}
+
+pub fn open(backend: StorageBackend, race_id: u32) -> Box<dyn Storage> {
+    open_in(backend, &app_dir(), race_id)
+}
+
+/// Open the storage of the race kept in `dir`
+pub fn open_in(backend: StorageBackend, dir: &Path, race_id: u32) -> Box<dyn Storage> {
+    match backend {
+        StorageBackend::Jsonl => Box::new(JsonlStorage::open_in(dir, race_id)),
+        StorageBackend::Sqlite => Box::new(SqliteStorage::open_in(dir, race_id)),
+    }
+}
+
+/// Storage of the tests, nothing is written to disk
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStorage {
+    events: Vec<Event>,
+    registrations: Option<RegistrationsSnapshot>,
+}
+
+#[cfg(test)]
+impl Storage for MemoryStorage {
+    fn events(&self) -> Vec<Event> {
+        self.events.clone()
+    }
+
+    fn append(&mut self, event: &Event) {
+        self.events.push(event.clone());
+    }
+
+    fn save_registrations(&mut self, snapshot: &RegistrationsSnapshot) {
+        self.registrations = Some(snapshot.clone());
+    }
+
+    fn registrations(&self) -> Option<RegistrationsSnapshot> {
+        self.registrations.clone()
+    }
+}
+
+/// Events in `race_{id}.jsonl`, registrations in `race_{id}_registrations.json`
+pub struct JsonlStorage {
+    path: PathBuf,
+    registrations_path: PathBuf,
+    /// opened by the first appended event, reading a race does not create its log
+    writer: Option<BufWriter<File>>,
+}
+
+impl JsonlStorage {
+    pub fn open(race_id: u32) -> Self {
+        JsonlStorage::open_in(&app_dir(), race_id)
+    }
+
+    pub fn open_in(dir: &Path, race_id: u32) -> Self {
+        JsonlStorage {
+            path: dir.join(format!("race_{race_id}.jsonl")),
+            registrations_path: dir.join(format!("race_{race_id}_registrations.json")),
+            writer: None,
+        }
+    }
+}
+
+impl Storage for JsonlStorage {
+    fn events(&self) -> Vec<Event> {
+        load_events(&self.path)
+    }
+
+    fn append(&mut self, event: &Event) {
+        let writer = self.writer.get_or_insert_with(|| {
+            let file = OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&self.path)
+                .unwrap();
+            BufWriter::new(file)
+        });
+        let line = serde_json::to_string(event).unwrap();
+        writeln!(writer, "{line}").unwrap();
+        writer.flush().unwrap();
+    }
+
+    fn save_registrations(&mut self, snapshot: &RegistrationsSnapshot) {
+        let json = serde_json::to_string(snapshot).unwrap();
+        if let Err(err) = fs::write(&self.registrations_path, json) {
+            error!("Failed to save registrations: {err}");
+        }
+    }
+
+    fn registrations(&self) -> Option<RegistrationsSnapshot> {
+        let json = fs::read_to_string(&self.registrations_path).ok()?;
+        serde_json::from_str(&json)
+            .inspect_err(|err| error!("Failed to load registrations: {err}"))
+            .ok()
+    }
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY,
+    race_id INTEGER NOT NULL,
+    timestamp TEXT NOT NULL,
+    type TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS events_race ON events (race_id);
+CREATE TABLE IF NOT EXISTS registrations (
+    id INTEGER PRIMARY KEY,
+    race_id INTEGER NOT NULL,
+    fetched TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE VIEW IF NOT EXISTS finishes AS
+    SELECT race_id, timestamp,
+        json_extract(data, '$.event.start_number') AS start_number,
+        json_extract(data, '$.event.finish') AS finish
+    FROM events WHERE type = 'racer_finish';
+CREATE VIEW IF NOT EXISTS reads AS
+    SELECT race_id, timestamp, json_extract(data, '$.event.tag') AS tag
+    FROM events WHERE type = 'tag_read';
+CREATE VIEW IF NOT EXISTS overrides AS
+    SELECT race_id, timestamp,
+        json_extract(data, '$.event.registration_id') AS registration_id,
+        json_extract(data, '$.event.start_number') AS start_number,
+        json_extract(data, '$.event.tag') AS tag
+    FROM events WHERE type = 'racer_override';
+";
+
+/// All races in a single `rustpolnak.sqlite` database
+pub struct SqliteStorage {
+    race_id: u32,
+    conn: Connection,
+}
+
+pub fn sqlite_path() -> PathBuf {
+    app_dir().join(SQLITE_FILENAME)
+}
+
+const SQLITE_FILENAME: &str = "rustpolnak.sqlite";
+
+fn event_type(event: &Event) -> String {
+    serde_json::to_value(&event.event).unwrap()["type"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string()
+}
+
+impl SqliteStorage {
+    pub fn open(race_id: u32) -> Self {
+        SqliteStorage::open_in(&app_dir(), race_id)
+    }
+
+    pub fn open_in(dir: &Path, race_id: u32) -> Self {
+        let conn = Connection::open(dir.join(SQLITE_FILENAME)).unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        SqliteStorage { race_id, conn }
+    }
+
+    pub fn contains(&self, event: &Event) -> bool {
+        self.conn
+            .query_row(
+                "SELECT 1 FROM events WHERE race_id = ?1 AND timestamp = ?2 AND data = ?3",
+                params![
+                    self.race_id,
+                    event.timestamp.to_rfc3339(),
+                    serde_json::to_string(event).unwrap()
+                ],
+                |_| Ok(()),
+            )
+            .optional()
+            .unwrap()
+            .is_some()
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn events(&self) -> Vec<Event> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT data FROM events WHERE race_id = ?1 ORDER BY id")
+            .unwrap();
+        stmt.query_map([self.race_id], |row| row.get::<_, String>(0))
+            .unwrap()
+            .filter_map(|data| match serde_json::from_str(&data.ok()?) {
+                Ok(event) => Some(event),
+                Err(err) => {
+                    error!("Failed to load record: {err:?}");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn append(&mut self, event: &Event) {
+        self.conn
+            .execute(
+                "INSERT INTO events (race_id, timestamp, type, data) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    self.race_id,
+                    event.timestamp.to_rfc3339(),
+                    event_type(event),
+                    serde_json::to_string(event).unwrap()
+                ],
+            )
+            .unwrap();
+    }
+
+    fn save_registrations(&mut self, snapshot: &RegistrationsSnapshot) {
+        let fetched = snapshot.fetched.to_rfc3339();
+        let data = serde_json::to_string(&snapshot.racers).unwrap();
+        // unchanged registrations only update the time of the latest snapshot
+        let saved = self
+            .conn
+            .execute(
+                "UPDATE registrations SET fetched = ?2 WHERE id = (
+                    SELECT id FROM registrations WHERE race_id = ?1 ORDER BY id DESC LIMIT 1
+                ) AND data = ?3",
+                params![self.race_id, fetched, data],
+            )
+            .and_then(|updated| match updated {
+                0 => self.conn.execute(
+                    "INSERT INTO registrations (race_id, fetched, data) VALUES (?1, ?2, ?3)",
+                    params![self.race_id, fetched, data],
+                ),
+                updated => Ok(updated),
+            });
+        if let Err(err) = saved {
+            error!("Failed to save registrations: {err}");
+        }
+    }
+
+    fn registrations(&self) -> Option<RegistrationsSnapshot> {
+        let (fetched, data) = self
+            .conn
+            .query_row(
+                "SELECT fetched, data FROM registrations WHERE race_id = ?1 ORDER BY id DESC LIMIT 1",
+                [self.race_id],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+            )
+            .optional()
+            .inspect_err(|err| error!("Failed to load registrations: {err}"))
+            .ok()??;
+
+        Some(RegistrationsSnapshot {
+            fetched: DateTime::parse_from_rfc3339(&fetched).ok()?.to_utc(),
+            racers: serde_json::from_str(&data).ok()?,
+        })
+    }
+}
+
+/// Import `race_{id}.jsonl` logs and registration snapshots into the SQLite database.
+/// Events already present in the database are skipped.
+pub fn import_jsonl(race_id: u32) -> usize {
+    import_jsonl_in(&app_dir(), race_id)
+}
+
+fn import_jsonl_in(dir: &Path, race_id: u32) -> usize {
+    let jsonl = JsonlStorage::open_in(dir, race_id);
+    let mut sqlite = SqliteStorage::open_in(dir, race_id);
+
+    let mut imported = 0;
+    for event in jsonl.events() {
+        if !sqlite.contains(&event) {
+            sqlite.append(&event);
+            imported += 1;
+        }
+    }
+
+    if let Some(snapshot) = jsonl.registrations() {
+        if sqlite.registrations().as_ref() != Some(&snapshot) {
+            sqlite.save_registrations(&snapshot);
+        }
+    }
+    imported
+}
+
+/// Ids of all races having a JSONL log in the app directory
+pub fn jsonl_race_ids() -> Vec<u32> {
+    jsonl_race_ids_in(&app_dir())
+}
+
+fn jsonl_race_ids_in(dir: &Path) -> Vec<u32> {
+    let mut ids: Vec<u32> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| {
+                    let name = entry.ok()?.file_name().into_string().ok()?;
+                    name.strip_prefix("race_")?
+                        .strip_suffix(".jsonl")?
+                        .parse()
+                        .ok()
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    ids.sort();
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::race_events::{EventType, Origin, RacerFinish, TagRead};
+
+    /// Empty app directory of the test
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rustpolnak-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn events() -> Vec<Event> {
+        let at = |secs| Utc.with_ymd_and_hms(2025, 10, 4, 10, 0, secs).unwrap();
+        vec![
+            Event {
+                timestamp: at(1),
+                event: EventType::TagRead(TagRead { tag: "T1".into() }),
+                origin: None,
+            },
+            Event {
+                timestamp: at(2),
+                event: EventType::RacerFinish(RacerFinish {
+                    start_number: "1".parse().unwrap(),
+                    finish: Some(at(2)),
+                }),
+                origin: Some(Origin {
+                    station: "finish".into(),
+                    seq: 1,
+                }),
+            },
+        ]
+    }
+
+    /// Registrations as the registration API sends them
+    fn snapshot(racers: u32) -> RegistrationsSnapshot {
+        let racer = |id: u32| {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "firstName": "Racer",
+                "lastName": id.to_string(),
+                "startNumber": id,
+                "categories": [],
+                "tagId": format!("T{id}"),
+                "track": {"id": 1, "name": "10 Km", "description": null},
+            }))
+            .unwrap()
+        };
+        RegistrationsSnapshot {
+            fetched: Utc.with_ymd_and_hms(2025, 10, 4, 8, racers, 0).unwrap(),
+            racers: (1..=racers).map(racer).collect(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = temp_dir("round-trip");
+        for (backend, race_id) in [(StorageBackend::Jsonl, 901), (StorageBackend::Sqlite, 902)] {
+            let mut storage = open_in(backend, &dir, race_id);
+            assert!(storage.events().is_empty());
+            assert_eq!(storage.registrations(), None);
+
+            for event in &events() {
+                storage.append(event);
+            }
+            storage.save_registrations(&snapshot(1));
+            storage.save_registrations(&snapshot(2));
+            let refetched = RegistrationsSnapshot {
+                fetched: snapshot(2).fetched + chrono::Duration::minutes(5),
+                ..snapshot(2)
+            };
+            storage.save_registrations(&refetched);
+
+            let reopened = open_in(backend, &dir, race_id);
+            assert_eq!(reopened.events(), events(), "{backend:?}");
+            assert_eq!(reopened.registrations(), Some(refetched), "{backend:?}");
+            assert!(open_in(backend, &dir, race_id + 100).events().is_empty());
+        }
+        // the refetch updated the latest snapshot instead of adding one
+        let snapshots: u32 = SqliteStorage::open_in(&dir, 902)
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM registrations WHERE race_id = 902",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(snapshots, 2);
+
+        let mut jsonl = JsonlStorage::open_in(&dir, 903);
+        for event in &events() {
+            jsonl.append(event);
+        }
+        jsonl.save_registrations(&snapshot(3));
+        assert_eq!(import_jsonl_in(&dir, 903), 2);
+        // events already in the database are not imported twice
+        assert_eq!(import_jsonl_in(&dir, 903), 0);
+        let sqlite = SqliteStorage::open_in(&dir, 903);
+        assert_eq!(sqlite.events(), events());
+        assert_eq!(sqlite.registrations(), Some(snapshot(3)));
+        // importing a race without a log does not create one
+        assert_eq!(import_jsonl_in(&dir, 904), 0);
+
+        assert_eq!(jsonl_race_ids_in(&dir), vec![901, 903]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::race::{walk_in_id_start, Race};
+use crate::race_events::{known_seqs, Event};
+
+/// Stations not heard from for longer are shown as disconnected
+const CONNECTED: TimeDelta = TimeDelta::seconds(5);
+
+/// Events sent by a station to the primary
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncRequest {
+    pub race_id: u32,
+    pub station: String,
+    /// token of the running application, tells apart two stations with the same name
+    pub instance: String,
+    /// events the primary has not acknowledged yet
+    pub events: Vec<Event>,
+    /// highest sequence number of each station in the log of the station
+    pub known: HashMap<String, u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncResponse {
+    /// events missing at the station
+    pub events: Vec<Event>,
+    /// highest sequence number of each station in the log of the primary
+    pub known: HashMap<String, u64>,
+    pub stations: Vec<StationStatus>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StationStatus {
+    pub station: String,
+    pub primary: bool,
+    pub last_seen: DateTime<Utc>,
+    /// seen recently by the clock of the primary
+    pub connected: bool,
+    /// events of the primary the station was missing at its last contact
+    pub behind: usize,
+}
+
+/// Token of this run of the application
+pub fn instance_token() -> String {
+    format!(
+        "{}-{}",
+        std::process::id(),
+        Utc::now().timestamp_nanos_opt().unwrap_or_default()
+    )
+}
+
+impl SyncRequest {
+    /// Request of this station, `acknowledged` are the sequence numbers the primary
+    /// reported in its last response
+    pub fn new(race: &Race, instance: &str, acknowledged: &HashMap<String, u64>) -> Self {
+        SyncRequest {
+            race_id: race.id,
+            station: race.station(),
+            instance: instance.to_string(),
+            events: race.events_after(acknowledged),
+            known: race.sync_known(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Station {
+    instance: String,
+    status: StationStatus,
+}
+
+/// Stations synchronized through this primary
+#[derive(Debug, Clone, Default)]
+pub struct Primary {
+    stations: HashMap<String, Station>,
+}
+
+impl Primary {
+    /// Store the events of the station and answer with the events it is missing
+    pub fn sync(
+        &mut self,
+        race: &mut Race,
+        request: SyncRequest,
+        now: DateTime<Utc>,
+    ) -> Result<SyncResponse, String> {
+        if request.race_id != race.id {
+            return Err(format!(
+                "Station {} times race {}, the primary race {}",
+                request.station, request.race_id, race.id
+            ));
+        }
+        if request.station == race.station() {
+            return Err(format!(
+                "Station {} has the name of the primary",
+                request.station
+            ));
+        }
+        if let Some(other) = self.stations.get(&request.station) {
+            // a restarted station takes over its name once the previous run went silent
+            if other.instance != request.instance && now - other.status.last_seen <= CONNECTED {
+                return Err(format!(
+                    "Station {} is already connected, set RUSTPOLNAK_STATION to a unique name",
+                    request.station
+                ));
+            }
+        }
+
+        let walk_in_ids = walk_in_id_start(&request.station);
+        if let Some(other) = self
+            .stations
+            .keys()
+            .cloned()
+            .chain([race.station()])
+            .find(|other| *other != request.station && walk_in_id_start(other) == walk_in_ids)
+        {
+            return Err(format!(
+                "Station {} would register racers with the ids of station {other}, \
+                 set RUSTPOLNAK_STATION to another name",
+                request.station
+            ));
+        }
+
+        // the station has the events it has just sent
+        let mut known = request.known;
+        for (station, seq) in known_seqs(&request.events) {
+            let known_seq = known.entry(station).or_insert(0);
+            *known_seq = seq.max(*known_seq);
+        }
+        race.apply_remote(request.events);
+        let events = race.events_after(&known);
+        self.stations.insert(
+            request.station.clone(),
+            Station {
+                instance: request.instance,
+                status: StationStatus {
+                    station: request.station,
+                    primary: false,
+                    last_seen: now,
+                    connected: true,
+                    behind: events.len(),
+                },
+            },
+        );
+        Ok(SyncResponse {
+            events,
+            known: race.sync_known(),
+            stations: self.stations(race, now),
+        })
+    }
+
+    /// The primary and all stations that have ever synchronized, by name
+    pub fn stations(&self, race: &Race, now: DateTime<Utc>) -> Vec<StationStatus> {
+        let mut stations: Vec<StationStatus> = self
+            .stations
+            .values()
+            .map(|station| StationStatus {
+                connected: now - station.status.last_seen <= CONNECTED,
+                ..station.status.clone()
+            })
+            .collect();
+        stations.push(StationStatus {
+            station: race.station(),
+            primary: true,
+            last_seen: now,
+            connected: true,
+            behind: 0,
+        });
+        stations.sort_by(|a, b| a.station.cmp(&b.station));
+        stations
+    }
+}
+
+/// Send the events of this station to the primary at `url`, a refused request
+/// fails with the reason given by the primary
+pub async fn sync(
+    client: &reqwest::Client,
+    url: &str,
+    request: &SyncRequest,
+) -> Result<SyncResponse, String> {
+    let response = client
+        .post(format!("{}/sync", url.trim_end_matches('/')))
+        .json(request)
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    if !response.status().is_success() {
+        let status = response.status();
+        return Err(response.text().await.unwrap_or_else(|_| status.to_string()));
+    }
+    response.json().await.map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::config::RaceConfig;
+    use crate::race::{test_registration, Track};
+
+    /// One exchange of the station with the primary
+    fn round(
+        primary: &mut Primary,
+        primary_race: &mut Race,
+        station: &mut Race,
+        acknowledged: &mut HashMap<String, u64>,
+    ) -> SyncResponse {
+        let request = SyncRequest::new(station, &station.station(), acknowledged);
+        let response = primary.sync(primary_race, request, Utc::now()).unwrap();
+        station.apply_remote(response.events.clone());
+        *acknowledged = response.known.clone();
+        response
+    }
+
+    fn finishes(race: &Race) -> Vec<Option<DateTime<Utc>>> {
+        race.racers.iter().map(|racer| racer.finish).collect()
+    }
+
+    fn request(station: &str, instance: &str) -> SyncRequest {
+        SyncRequest {
+            race_id: 1,
+            station: station.into(),
+            instance: instance.into(),
+            events: vec![],
+            known: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn duplicate_station() {
+        let mut race = Race::for_test("primary", RaceConfig::default(), vec![]);
+        let mut primary = Primary::default();
+        let now = Utc::now();
+
+        assert!(primary.sync(&mut race, request("finish", "a"), now).is_ok());
+        assert!(primary
+            .sync(&mut race, request("finish", "b"), now)
+            .is_err());
+        assert!(primary.sync(&mut race, request("finish", "a"), now).is_ok());
+        assert!(primary
+            .sync(&mut race, request("primary", "c"), now)
+            .is_err());
+        // the first run is gone, e.g. the laptop was restarted
+        let later = now + TimeDelta::seconds(10);
+        assert!(primary
+            .sync(&mut race, request("finish", "b"), later)
+            .is_ok());
+
+        // a name whose walk-in ids are those of the finish station
+        let clash = (0..)
+            .map(|n| format!("station{n}"))
+            .find(|name| walk_in_id_start(name) == walk_in_id_start("finish"))
+            .unwrap();
+        assert!(primary
+            .sync(&mut race, request(&clash, "d"), later)
+            .is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let registrations = vec![
+            test_registration(1, 1, "5 Km"),
+            test_registration(2, 2, "5 Km"),
+        ];
+        let race = |station| Race::for_test(station, RaceConfig::default(), registrations.clone());
+        let (mut primary_race, mut start, mut finish) =
+            (race("primary"), race("start"), race("finish"));
+        let mut primary = Primary::default();
+        let (mut start_ack, mut finish_ack) = (HashMap::new(), HashMap::new());
+
+        let t0 = Utc::now();
+        start.start(Track("5 Km".into()), t0);
+        round(&mut primary, &mut primary_race, &mut start, &mut start_ack);
+        let response = round(
+            &mut primary,
+            &mut primary_race,
+            &mut finish,
+            &mut finish_ack,
+        );
+        assert_eq!(response.events.len(), 1);
+        assert!(finish.racers.iter().all(|racer| racer.start == Some(t0)));
+
+        // the finish station records both racers, the start station corrects racer 1 later
+        // but reaches the primary first
+        finish.finish_start_number("1".parse().unwrap(), t0 + TimeDelta::minutes(20));
+        finish.finish_start_number("2".parse().unwrap(), t0 + TimeDelta::minutes(22));
+        start.edit_finish_start_number("1".parse().unwrap(), Some(t0 + TimeDelta::minutes(21)));
+        round(&mut primary, &mut primary_race, &mut start, &mut start_ack);
+        round(
+            &mut primary,
+            &mut primary_race,
+            &mut finish,
+            &mut finish_ack,
+        );
+        round(&mut primary, &mut primary_race, &mut start, &mut start_ack);
+
+        let expected = vec![
+            Some(t0 + TimeDelta::minutes(21)),
+            Some(t0 + TimeDelta::minutes(22)),
+        ];
+        assert_eq!(finishes(&primary_race), expected);
+        assert_eq!(finishes(&start), expected);
+        assert_eq!(finishes(&finish), expected);
+
+        // a resent request does not duplicate any event
+        let known = primary_race.sync_known();
+        let events = primary_race.events_after(&HashMap::new()).len();
+        let resend = SyncRequest::new(&finish, "finish", &HashMap::new());
+        let response = primary
+            .sync(&mut primary_race, resend.clone(), Utc::now())
+            .unwrap();
+        assert!(response.events.is_empty());
+        primary.sync(&mut primary_race, resend, Utc::now()).unwrap();
+        assert_eq!(primary_race.sync_known(), known);
+        assert_eq!(primary_race.events_after(&HashMap::new()).len(), events);
+        assert_eq!(finishes(&primary_race), expected);
+
+        let stations = primary.stations(&primary_race, Utc::now());
+        let names: Vec<_> = stations
+            .iter()
+            .map(|status| status.station.as_str())
+            .collect();
+        assert_eq!(names, vec!["finish", "primary", "start"]);
+        assert!(stations.iter().all(|status| status.connected));
+
+        let mut other_race = SyncRequest::new(&start, "start", &start_ack);
+        other_race.race_id = 2;
+        assert!(primary
+            .sync(&mut primary_race, other_race, Utc::now())
+            .is_err());
+    }
+}
@@ -0,0 +1,292 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use chrono::TimeDelta;
+use serde::Deserialize;
+
+use crate::race::{Race, Racer, Track};
+use crate::time_utils::Timing;
+
+/// How the finishers of a team are scored
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TeamScoringMethod {
+    /// sum of the times of the best finishers, the lowest wins
+    #[default]
+    BestTimes,
+    /// sum of the points for the track places of the best finishers, the highest wins
+    Points,
+}
+
+fn default_counted() -> usize {
+    3
+}
+
+/// Team competition of a race, teams are scored on each track separately
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TeamScoring {
+    #[serde(default)]
+    pub method: TeamScoringMethod,
+    /// number of the best finishers of a team that score
+    #[serde(default = "default_counted")]
+    pub counted: usize,
+    /// points for the first, second, … place, places beyond the list get no points
+    #[serde(default)]
+    pub points: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TeamScore {
+    Time(TimeDelta),
+    Points(u32),
+}
+
+impl TeamScore {
+    /// Score for the standings, times to the precision of the race
+    pub fn format(&self, timing: &Timing) -> String {
+        match self {
+            TeamScore::Time(time) => timing.format(Some(*time)),
+            TeamScore::Points(points) => format!("{points} pts"),
+        }
+    }
+}
+
+/// Scored finisher of a team
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeamMember {
+    pub name: String,
+    pub track_rank: Option<u32>,
+    pub score: TeamScore,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeamStanding {
+    pub rank: u32,
+    pub team: String,
+    pub score: TeamScore,
+    /// the scoring members, best first
+    pub members: Vec<TeamMember>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackTeamStandings {
+    pub track: Track,
+    pub standings: Vec<TeamStanding>,
+}
+
+impl TeamScoring {
+    fn member_score(&self, racer: &Racer, timing: &Timing) -> TeamScore {
+        match self.method {
+            TeamScoringMethod::BestTimes => {
+                TeamScore::Time(timing.apply(racer.time.unwrap_or_default()))
+            }
+            TeamScoringMethod::Points => TeamScore::Points(
+                racer
+                    .track_rank
+                    .and_then(|rank| self.points.get(rank as usize - 1))
+                    .copied()
+                    .unwrap_or(0),
+            ),
+        }
+    }
+
+    /// Standings of the teams from the finishers of a track sorted by their track rank,
+    /// team times are the sums of the official times of the members
+    pub fn standings(&self, finished: &[&Racer], timing: &Timing) -> Vec<TeamStanding> {
+        let mut teams: HashMap<&str, Vec<&Racer>> = HashMap::new();
+        for racer in finished {
+            if let Some(team) = racer.team.as_deref().filter(|team| !team.is_empty()) {
+                teams.entry(team).or_default().push(racer);
+            }
+        }
+
+        let mut standings: Vec<TeamStanding> = teams
+            .into_iter()
+            .map(|(team, mut racers)| {
+                racers.sort_by_key(|racer| (racer.track_rank, racer.time));
+                racers.truncate(self.counted);
+                let members: Vec<TeamMember> = racers
+                    .into_iter()
+                    .map(|racer| TeamMember {
+                        name: format!("{} {}", racer.first_name, racer.last_name),
+                        track_rank: racer.track_rank,
+                        score: self.member_score(racer, timing),
+                    })
+                    .collect();
+                let score = match self.method {
+                    TeamScoringMethod::BestTimes => TeamScore::Time(
+                        members
+                            .iter()
+                            .map(|member| match member.score {
+                                TeamScore::Time(time) => time,
+                                TeamScore::Points(_) => TimeDelta::zero(),
+                            })
+                            .sum(),
+                    ),
+                    TeamScoringMethod::Points => TeamScore::Points(
+                        members
+                            .iter()
+                            .map(|member| match member.score {
+                                TeamScore::Points(points) => points,
+                                TeamScore::Time(_) => 0,
+                            })
+                            .sum(),
+                    ),
+                };
+                TeamStanding {
+                    rank: 0,
+                    team: team.to_string(),
+                    score,
+                    members,
+                }
+            })
+            .collect();
+
+        // teams with fewer scoring finishers are ranked after the complete ones,
+        // ties are broken by the best finisher
+        standings.sort_by(|a, b| {
+            let best =
+                |standing: &TeamStanding| standing.members.first().and_then(|m| m.track_rank);
+            let by_score = match (a.score, b.score) {
+                (TeamScore::Time(a_time), TeamScore::Time(b_time)) => Reverse(a.members.len())
+                    .cmp(&Reverse(b.members.len()))
+                    .then(a_time.cmp(&b_time)),
+                (TeamScore::Points(a_points), TeamScore::Points(b_points)) => {
+                    b_points.cmp(&a_points)
+                }
+                _ => std::cmp::Ordering::Equal,
+            };
+            by_score
+                .then(best(a).cmp(&best(b)))
+                .then(a.team.cmp(&b.team))
+        });
+        for (index, standing) in standings.iter_mut().enumerate() {
+            standing.rank = index as u32 + 1;
+        }
+        standings
+    }
+}
+
+/// Team standings of every track with teams
+pub fn team_standings(race: &Race, scoring: &TeamScoring) -> Vec<TrackTeamStandings> {
+    race.tracks
+        .iter()
+        .map(|track| TrackTeamStandings {
+            track: track.clone(),
+            standings: scoring.standings(
+                &race.get_finished_racers_by_track(track),
+                &race.config.timing,
+            ),
+        })
+        .filter(|track_standings| !track_standings.standings.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race::Category;
+    use crate::time_utils::{Precision, Rounding};
+
+    fn racer(rank: u32, team: &str, minutes: i64) -> Racer {
+        Racer {
+            id: rank,
+            start_number: rank.to_string().parse().unwrap(),
+            tag: String::new(),
+            first_name: "Bob".into(),
+            last_name: format!("Jones{rank}"),
+            track: Track("10 Km".into()),
+            track_rank: Some(rank),
+            categories: vec![Category("Men".into())],
+            categories_rank: HashMap::new(),
+            birth_date: None,
+            gender: None,
+            team: Some(team.into()).filter(|team: &String| !team.is_empty()),
+            legs: vec![],
+            start: None,
+            finish: None,
+            time: Some(TimeDelta::minutes(minutes)),
+        }
+    }
+
+    #[test]
+    fn scoring() {
+        let racers = [
+            racer(1, "Sparta", 40),
+            racer(2, "Slavia", 41),
+            racer(3, "Slavia", 42),
+            racer(4, "", 43),
+            racer(5, "Sparta", 44),
+            racer(6, "Slavia", 45),
+            racer(7, "Sparta", 46),
+            racer(8, "Dukla", 47),
+        ];
+        let finished: Vec<&Racer> = racers.iter().collect();
+
+        let best_times = TeamScoring {
+            method: TeamScoringMethod::BestTimes,
+            counted: 2,
+            points: vec![],
+        };
+        let standings = best_times.standings(&finished, &Timing::default());
+        let teams: Vec<_> = standings
+            .iter()
+            .map(|s| (s.rank, s.team.as_str(), s.score))
+            .collect();
+        assert_eq!(
+            teams,
+            vec![
+                (1, "Slavia", TeamScore::Time(TimeDelta::minutes(83))),
+                (2, "Sparta", TeamScore::Time(TimeDelta::minutes(84))),
+                (3, "Dukla", TeamScore::Time(TimeDelta::minutes(47))),
+            ]
+        );
+        assert_eq!(standings[0].members.len(), 2);
+
+        let points = TeamScoring {
+            method: TeamScoringMethod::Points,
+            counted: 3,
+            points: vec![10, 8, 6, 5, 4, 3, 2],
+        };
+        let teams: Vec<_> = points
+            .standings(&finished, &Timing::default())
+            .iter()
+            .map(|s| (s.team.clone(), s.score))
+            .collect();
+        assert_eq!(
+            teams,
+            vec![
+                ("Slavia".to_string(), TeamScore::Points(17)),
+                ("Sparta".to_string(), TeamScore::Points(16)),
+                ("Dukla".to_string(), TeamScore::Points(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn official_times() {
+        let mut racers = [racer(1, "Sparta", 40), racer(2, "Sparta", 41)];
+        racers[0].time = Some(TimeDelta::milliseconds(2_400_400));
+        racers[1].time = Some(TimeDelta::milliseconds(2_460_400));
+        let finished: Vec<&Racer> = racers.iter().collect();
+        let scoring = TeamScoring {
+            method: TeamScoringMethod::BestTimes,
+            counted: 2,
+            points: vec![],
+        };
+        let timing = Timing {
+            precision: Precision::Seconds,
+            rounding: Rounding::Ceil,
+        };
+
+        // each member time is rounded before the sum, not the sum itself
+        let standings = scoring.standings(&finished, &timing);
+        assert_eq!(
+            standings[0].score,
+            TeamScore::Time(TimeDelta::seconds(4862))
+        );
+        assert_eq!(standings[0].score.format(&timing), "01:21:02");
+        assert_eq!(standings[0].members[0].score.format(&timing), "00:40:01");
+    }
+}
@@ -1,15 +1,37 @@
-use chrono::{DateTime, Local, TimeDelta, Utc};
+use chrono::{DateTime, LocalResult, NaiveDateTime, Offset, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
 
-pub fn format_time(datetime: Option<DateTime<Utc>>) -> String {
+/// Interpret a wall clock time in the timezone. Times repeated when DST ends resolve to
+/// the earlier instant, times skipped when DST starts are shifted forward by the gap.
+pub fn localize(tz: Tz, naive: NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(datetime) => datetime.to_utc(),
+        LocalResult::Ambiguous(earliest, _latest) => earliest.to_utc(),
+        LocalResult::None => {
+            // shifting forward by the gap is the same as keeping the offset before it,
+            // so no second lookup is needed even for an unusual gap
+            let before = tz.from_utc_datetime(&(naive - TimeDelta::days(1)));
+            let offset = TimeDelta::seconds(before.offset().fix().local_minus_utc().into());
+            (naive - offset).and_utc()
+        }
+    }
+}
+
+pub fn format_time(datetime: Option<DateTime<Utc>>, tz: Tz) -> String {
     match datetime {
         Some(datetime) => datetime
-            .with_timezone(&Local)
+            .with_timezone(&tz)
             .format("%H:%M:%S%.3f")
             .to_string(),
         None => "".to_string(),
     }
 }
 
+pub fn format_date(datetime: DateTime<Utc>, tz: Tz) -> String {
+    datetime.with_timezone(&tz).format("%Y-%m-%d").to_string()
+}
+
 pub fn format_time_delta_millis(delta: Option<TimeDelta>) -> String {
     format_time_delta(delta, true)
 }
@@ -36,3 +58,160 @@ fn format_time_delta(delta: Option<TimeDelta>, with_ms: bool) -> String {
         format!("{hours:02}:{mins:02}:{secs:02}")
     }
 }
+
+/// Smallest unit of the official times
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Precision {
+    Seconds,
+    Tenths,
+    Hundredths,
+    #[default]
+    Milliseconds,
+}
+
+/// How the running times are brought to the precision
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    #[default]
+    Truncate,
+    Round,
+    Ceil,
+}
+
+/// Official timing rules of a race
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct Timing {
+    pub precision: Precision,
+    pub rounding: Rounding,
+}
+
+impl Precision {
+    fn unit_millis(self) -> i64 {
+        match self {
+            Precision::Seconds => 1000,
+            Precision::Tenths => 100,
+            Precision::Hundredths => 10,
+            Precision::Milliseconds => 1,
+        }
+    }
+
+    fn digits(self) -> usize {
+        match self {
+            Precision::Seconds => 0,
+            Precision::Tenths => 1,
+            Precision::Hundredths => 2,
+            Precision::Milliseconds => 3,
+        }
+    }
+}
+
+impl Timing {
+    /// Official time of a running time
+    pub fn apply(&self, delta: TimeDelta) -> TimeDelta {
+        let Some(nanos) = delta.num_nanoseconds() else {
+            return delta;
+        };
+        let unit = self.precision.unit_millis() * 1_000_000;
+        let remainder = nanos.rem_euclid(unit);
+        let truncated = nanos - remainder;
+        let nanos = match self.rounding {
+            Rounding::Truncate => truncated,
+            Rounding::Round if remainder * 2 >= unit => truncated + unit,
+            Rounding::Round => truncated,
+            Rounding::Ceil if remainder > 0 => truncated + unit,
+            Rounding::Ceil => truncated,
+        };
+        TimeDelta::nanoseconds(nanos)
+    }
+
+    /// Official time formatted to the precision
+    pub fn format(&self, delta: Option<TimeDelta>) -> String {
+        let Some(delta) = delta else {
+            return "".to_string();
+        };
+        let formatted = format_time_delta_millis(Some(self.apply(delta)));
+        match self.precision.digits() {
+            0 => formatted[..formatted.len() - 4].to_string(),
+            digits => formatted[..formatted.len() - 3 + digits].to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use chrono_tz::Europe::Prague;
+
+    fn naive(day: u32, month: u32, hour: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, month, day)
+            .unwrap()
+            .and_hms_opt(hour, min, 0)
+            .unwrap()
+    }
+
+    fn utc(day: u32, month: u32, hour: u32, min: u32) -> DateTime<Utc> {
+        naive(day, month, hour, min).and_utc()
+    }
+
+    #[test]
+    fn localize_dst() {
+        assert_eq!(localize(Prague, naive(5, 10, 10, 0)), utc(5, 10, 8, 0));
+        assert_eq!(localize(Prague, naive(5, 1, 10, 0)), utc(5, 1, 9, 0));
+        // 02:30 happens twice when DST ends
+        assert_eq!(localize(Prague, naive(26, 10, 2, 30)), utc(26, 10, 0, 30));
+        // 02:30 does not exist when DST starts
+        assert_eq!(localize(Prague, naive(30, 3, 2, 30)), utc(30, 3, 1, 30));
+
+        // Samoa skipped the whole 30 December 2011
+        let skipped = NaiveDate::from_ymd_opt(2011, 12, 30)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let expected = NaiveDate::from_ymd_opt(2011, 12, 30)
+            .unwrap()
+            .and_hms_opt(22, 0, 0)
+            .unwrap()
+            .and_utc();
+        assert_eq!(localize(chrono_tz::Pacific::Apia, skipped), expected);
+    }
+
+    #[test]
+    fn timing() {
+        let time = TimeDelta::milliseconds(3_723_450);
+        let timing = |precision, rounding| Timing {
+            precision,
+            rounding,
+        };
+
+        let seconds = timing(Precision::Seconds, Rounding::Truncate);
+        assert_eq!(seconds.apply(time), TimeDelta::seconds(3723));
+        assert_eq!(seconds.format(Some(time)), "01:02:03");
+        assert_eq!(
+            timing(Precision::Seconds, Rounding::Round).apply(time),
+            TimeDelta::seconds(3723)
+        );
+        assert_eq!(
+            timing(Precision::Seconds, Rounding::Ceil).apply(time),
+            TimeDelta::seconds(3724)
+        );
+        assert_eq!(
+            timing(Precision::Tenths, Rounding::Round).format(Some(time)),
+            "01:02:03.5"
+        );
+        assert_eq!(
+            timing(Precision::Tenths, Rounding::Ceil)
+                .format(Some(TimeDelta::milliseconds(3_723_401))),
+            "01:02:03.5"
+        );
+        assert_eq!(
+            timing(Precision::Hundredths, Rounding::Truncate).format(Some(time)),
+            "01:02:03.45"
+        );
+        assert_eq!(Timing::default().format(Some(time)), "01:02:03.450");
+        assert_eq!(Timing::default().format(None), "");
+    }
+}
@@ -0,0 +1,448 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::NaiveDate;
+
+use crate::category_rules::{assign, is_ruled, CategoryRule, RacerProfile};
+use crate::config::RaceConfig;
+use crate::race::{Category, Racer, StartNumber, Track, TrackInfo};
+
+/// Problem of the registration data that may break the timing
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationIssue {
+    DuplicateStartNumber {
+        start_number: StartNumber,
+        racers: Vec<String>,
+    },
+    DuplicateTag {
+        tag: String,
+        racers: Vec<String>,
+    },
+    MissingStartNumber {
+        racer: String,
+    },
+    MissingTag {
+        racer: String,
+    },
+    MissingTrack {
+        racer: String,
+    },
+    /// track missing from the tracks of the race config or without an API track id
+    UnknownTrack {
+        racer: String,
+        track: Track,
+    },
+    /// racer is in a category whose other racers run a different track
+    CategoryTrackMismatch {
+        racer: String,
+        category: Category,
+        track: Track,
+        expected: Track,
+    },
+    /// categories of the racer differ from the ones of the category rules
+    CategoryRuleMismatch {
+        racer: String,
+        registered: Vec<Category>,
+        expected: Vec<Category>,
+    },
+}
+
+impl ValidationIssue {
+    /// Issues that make the finish of a racer ambiguous or impossible
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            ValidationIssue::DuplicateStartNumber { .. }
+                | ValidationIssue::DuplicateTag { .. }
+                | ValidationIssue::MissingStartNumber { .. }
+                | ValidationIssue::MissingTrack { .. }
+        )
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationIssue::DuplicateStartNumber {
+                start_number,
+                racers,
+            } => write!(
+                f,
+                "Start number {start_number} is shared by {}",
+                racers.join(", ")
+            ),
+            ValidationIssue::DuplicateTag { tag, racers } => {
+                write!(f, "Tag {tag} is shared by {}", racers.join(", "))
+            }
+            ValidationIssue::MissingStartNumber { racer } => {
+                write!(f, "{racer} has no start number")
+            }
+            ValidationIssue::MissingTag { racer } => write!(f, "{racer} has no tag"),
+            ValidationIssue::MissingTrack { racer } => write!(f, "{racer} has no track"),
+            ValidationIssue::UnknownTrack { racer, track } => {
+                write!(f, "{racer} runs {track}, which is not a track of the race")
+            }
+            ValidationIssue::CategoryTrackMismatch {
+                racer,
+                category,
+                track,
+                expected,
+            } => write!(
+                f,
+                "{racer} runs {track}, but category {category} runs {expected}"
+            ),
+            ValidationIssue::CategoryRuleMismatch {
+                racer,
+                registered,
+                expected,
+            } => write!(
+                f,
+                "{racer} is registered in {}, but the category rules give {}",
+                names(registered),
+                names(expected)
+            ),
+        }
+    }
+}
+
+fn names(categories: &[Category]) -> String {
+    if categories.is_empty() {
+        return "no category".to_string();
+    }
+    categories
+        .iter()
+        .map(|category| category.0.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn label(racer: &Racer) -> String {
+    if racer.start_number.is_missing() {
+        format!("{} {}", racer.first_name, racer.last_name)
+    } else {
+        format!(
+            "{} {} ({})",
+            racer.first_name, racer.last_name, racer.start_number
+        )
+    }
+}
+
+/// Track run by most racers of each category
+fn category_tracks(racers: &[Racer]) -> HashMap<&Category, &Track> {
+    let mut counts: HashMap<&Category, HashMap<&Track, usize>> = HashMap::new();
+    for racer in racers {
+        for category in &racer.categories {
+            *counts
+                .entry(category)
+                .or_default()
+                .entry(&racer.track)
+                .or_default() += 1;
+        }
+    }
+
+    counts
+        .into_iter()
+        .filter_map(|(category, tracks)| {
+            let (track, _) = tracks
+                .into_iter()
+                .max_by(|(a_track, a), (b_track, b)| a.cmp(b).then(b_track.0.cmp(&a_track.0)))?;
+            Some((category, track))
+        })
+        .collect()
+}
+
+/// Categories of the racer assigned by the rules, compared to the registered ones
+fn check_category_rules(
+    racer: &Racer,
+    rules: &[CategoryRule],
+    race_date: NaiveDate,
+) -> Option<ValidationIssue> {
+    let profile = RacerProfile {
+        track: &racer.track.0,
+        gender: racer.gender,
+        birth_date: racer.birth_date,
+    };
+    let mut expected: Vec<Category> = assign(rules, &profile, race_date)?
+        .into_iter()
+        .map(Category)
+        .collect();
+    let mut registered: Vec<Category> = racer
+        .categories
+        .iter()
+        .filter(|category| is_ruled(rules, &category.0))
+        .cloned()
+        .collect();
+    expected.sort();
+    registered.sort();
+    (expected != registered).then(|| ValidationIssue::CategoryRuleMismatch {
+        racer: label(racer),
+        registered,
+        expected,
+    })
+}
+
+/// Whether the track is neither configured nor known to the registration API
+fn is_unknown_track(
+    track: &Track,
+    tracks: &HashMap<Track, TrackInfo>,
+    config: &RaceConfig,
+) -> bool {
+    let configured = config.tracks.is_empty() || config.tracks.contains_key(&track.0);
+    let registered = tracks.get(track).is_some_and(|info| info.id != 0);
+    !configured || !registered
+}
+
+/// Check the registration data of the race
+pub fn validate(
+    racers: &[Racer],
+    tracks: &HashMap<Track, TrackInfo>,
+    config: &RaceConfig,
+    race_date: NaiveDate,
+) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    let mut by_start_number: HashMap<&StartNumber, Vec<&Racer>> = HashMap::new();
+    let mut by_tag: HashMap<&str, Vec<&Racer>> = HashMap::new();
+    for racer in racers {
+        if racer.start_number.is_missing() {
+            issues.push(ValidationIssue::MissingStartNumber {
+                racer: label(racer),
+            });
+        } else {
+            by_start_number
+                .entry(&racer.start_number)
+                .or_default()
+                .push(racer);
+        }
+
+        if racer.is_relay() {
+            // relay teams are timed by the tags of their legs
+            for (index, leg) in racer.legs.iter().enumerate() {
+                if leg.tag.trim().is_empty() {
+                    issues.push(ValidationIssue::MissingTag {
+                        racer: format!("{} leg {} ({})", label(racer), index + 1, leg.name),
+                    });
+                } else {
+                    by_tag.entry(&leg.tag).or_default().push(racer);
+                }
+            }
+        } else if racer.tag.trim().is_empty() {
+            issues.push(ValidationIssue::MissingTag {
+                racer: label(racer),
+            });
+        } else {
+            by_tag.entry(&racer.tag).or_default().push(racer);
+        }
+
+        if racer.track.0.trim().is_empty() {
+            issues.push(ValidationIssue::MissingTrack {
+                racer: label(racer),
+            });
+        } else if is_unknown_track(&racer.track, tracks, config) {
+            issues.push(ValidationIssue::UnknownTrack {
+                racer: label(racer),
+                track: racer.track.clone(),
+            });
+        }
+    }
+
+    let mut duplicates: Vec<_> = by_start_number
+        .into_iter()
+        .filter(|(_, racers)| racers.len() > 1)
+        .collect();
+    duplicates.sort_by_key(|(start_number, _)| *start_number);
+    issues.extend(duplicates.into_iter().map(|(start_number, racers)| {
+        ValidationIssue::DuplicateStartNumber {
+            start_number: start_number.clone(),
+            racers: racers.into_iter().map(label).collect(),
+        }
+    }));
+
+    let mut duplicates: Vec<_> = by_tag
+        .into_iter()
+        .filter(|(_, racers)| racers.len() > 1)
+        .collect();
+    duplicates.sort_by_key(|(tag, _)| *tag);
+    issues.extend(
+        duplicates
+            .into_iter()
+            .map(|(tag, racers)| ValidationIssue::DuplicateTag {
+                tag: tag.to_string(),
+                racers: racers.into_iter().map(label).collect(),
+            }),
+    );
+
+    let expected_tracks = category_tracks(racers);
+    for racer in racers {
+        for category in &racer.categories {
+            match expected_tracks.get(category) {
+                Some(expected) if **expected != racer.track => {
+                    issues.push(ValidationIssue::CategoryTrackMismatch {
+                        racer: label(racer),
+                        category: category.clone(),
+                        track: racer.track.clone(),
+                        expected: (*expected).clone(),
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+
+    issues.extend(
+        racers
+            .iter()
+            .filter_map(|racer| check_category_rules(racer, &config.category_rules, race_date)),
+    );
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn racer(start_number: u32, tag: &str, track: &str, category: &str) -> Racer {
+        Racer {
+            id: start_number,
+            start_number: start_number.to_string().parse().unwrap(),
+            tag: tag.into(),
+            first_name: "Bob".into(),
+            last_name: format!("Jones{start_number}"),
+            track: Track(track.into()),
+            track_rank: None,
+            categories: vec![Category(category.into())],
+            categories_rank: HashMap::new(),
+            birth_date: None,
+            gender: None,
+            team: None,
+            legs: vec![],
+            start: None,
+            finish: None,
+            time: None,
+        }
+    }
+
+    /// Tracks of the registration API with their ids
+    fn tracks(names: &[&str]) -> HashMap<Track, TrackInfo> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                let info = TrackInfo {
+                    id: index as u32 + 1,
+                    ..Default::default()
+                };
+                (Track(name.to_string()), info)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn issues() {
+        let racers = vec![
+            racer(1, "A", "10 Km", "Men"),
+            racer(2, "B", "10 Km", "Men"),
+            racer(2, "C", "10 Km", "Men"),
+            racer(0, "", "4 Km", "Men"),
+            racer(5, "C", "", "Kids"),
+        ];
+
+        let race_date = NaiveDate::from_ymd_opt(2025, 10, 5).unwrap();
+        let tracks = tracks(&["10 Km", "4 Km"]);
+        let config = RaceConfig::default();
+        let issues: Vec<String> = validate(&racers, &tracks, &config, race_date)
+            .iter()
+            .map(|i| i.to_string())
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                "Bob Jones0 has no start number",
+                "Bob Jones0 has no tag",
+                "Bob Jones5 (5) has no track",
+                "Start number 2 is shared by Bob Jones2 (2), Bob Jones2 (2)",
+                "Tag C is shared by Bob Jones2 (2), Bob Jones5 (5)",
+                "Bob Jones0 runs 4 Km, but category Men runs 10 Km",
+            ]
+        );
+        assert!(validate(&racers[..2], &tracks, &config, race_date).is_empty());
+    }
+
+    #[test]
+    fn unknown_tracks() {
+        let racers = vec![
+            racer(1, "A", "10 Km", "Men"),
+            racer(2, "B", "4 Km", "Kids"),
+            // walk-in registered with a track the registration API does not know
+            racer(3, "C", "5 Km", "Women"),
+        ];
+        let race_date = NaiveDate::from_ymd_opt(2025, 10, 5).unwrap();
+        let tracks = tracks(&["10 Km", "4 Km"]);
+
+        let issues: Vec<String> = validate(&racers, &tracks, &RaceConfig::default(), race_date)
+            .iter()
+            .map(|i| i.to_string())
+            .collect();
+        assert_eq!(
+            issues,
+            vec!["Bob Jones3 (3) runs 5 Km, which is not a track of the race"]
+        );
+
+        let mut config = RaceConfig::default();
+        config.tracks.insert("10 Km".into(), Default::default());
+        config.tracks.insert("5 Km".into(), Default::default());
+        let issues: Vec<String> = validate(&racers, &tracks, &config, race_date)
+            .iter()
+            .map(|i| i.to_string())
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                "Bob Jones2 (2) runs 4 Km, which is not a track of the race",
+                "Bob Jones3 (3) runs 5 Km, which is not a track of the race",
+            ]
+        );
+    }
+
+    #[test]
+    fn category_rules() {
+        let rule = |category: &str, min_age, max_age| CategoryRule {
+            category: category.into(),
+            track: Some("10 Km".into()),
+            gender: None,
+            min_age,
+            max_age,
+        };
+        let rules = vec![
+            rule("Men", None, Some(39)),
+            rule("Veterans", Some(40), None),
+        ];
+        let race_date = NaiveDate::from_ymd_opt(2025, 10, 5).unwrap();
+
+        let mut young = racer(1, "A", "10 Km", "Men");
+        young.birth_date = NaiveDate::from_ymd_opt(1990, 1, 1);
+        let mut veteran = racer(2, "B", "10 Km", "Men");
+        veteran.birth_date = NaiveDate::from_ymd_opt(1970, 1, 1);
+        // no birth date, nothing to compare
+        let unknown = racer(3, "C", "10 Km", "Men");
+
+        let config = RaceConfig {
+            category_rules: rules,
+            ..Default::default()
+        };
+        let issues: Vec<String> = validate(
+            &[young, veteran, unknown],
+            &tracks(&["10 Km"]),
+            &config,
+            race_date,
+        )
+        .iter()
+        .map(|i| i.to_string())
+        .collect();
+        assert_eq!(
+            issues,
+            vec!["Bob Jones2 (2) is registered in Men, but the category rules give Veterans"]
+        );
+    }
+}
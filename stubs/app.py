@@ -17,12 +17,23 @@ class Category(BaseModel):
     id: int
     name: str
     description: Optional[str]
+    sortOrder: Optional[int] = None
 
 
 class Track(BaseModel):
     id: int
     name: str
     description: str
+    distance: Optional[int] = None
+    laps: Optional[int] = None
+    cutOff: Optional[int] = None
+    scheduledStart: Optional[str] = None
+    sortOrder: Optional[int] = None
+
+
+class Leg(BaseModel):
+    name: str
+    tagId: str
 
 
 class Racer(BaseModel):
@@ -33,6 +44,10 @@ class Racer(BaseModel):
     categories: List[Category]
     tagId: Optional[str]
     track: Track
+    birthDate: Optional[str] = None
+    gender: Optional[str] = None
+    team: Optional[str] = None
+    legs: List[Leg] = []
 
 
 class Result(BaseModel):
@@ -97,9 +112,9 @@ def registrations(race_id: int) -> List[Racer]:
         "Taylor",
     ]
     tracks = [
-        Track(id=1, name="10 Km", description=""),
-        Track(id=2, name="4 Km", description=""),
-        Track(id=3, name="Dedska trat", description=""),
+        Track(id=1, name="10 Km", description="", distance=10000, laps=2, cutOff=90),
+        Track(id=2, name="4 Km", description="", distance=4000),
+        Track(id=3, name="Dedska trat", description="", distance=800, sortOrder=-1),
     ]
     categories = [
         Category(id=1, name="A10", description=None),
@@ -133,3 +148,21 @@ def registrations(race_id: int) -> List[Racer]:
 def results(race_id: int, results: RaceResult):
     print(results)
     return {}
+
+
+@app.put("/races/{race_id}/results/{registration_id}")
+def upsert_result(race_id: int, registration_id: int, result: Result):
+    print(result)
+    return {}
+
+
+@app.delete("/races/{race_id}/results/{registration_id}")
+def delete_result(race_id: int, registration_id: int):
+    print(f"delete {registration_id}")
+    return {}
+
+
+@app.post("/races/{race_id}/registrations")
+def register(race_id: int, racer: Racer) -> Racer:
+    racer.id = random.randint(10000, 20000)
+    return racer